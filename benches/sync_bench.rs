@@ -0,0 +1,194 @@
+//! Sync loop throughput benchmark.
+//!
+//! A synthetic sync response with 200 rooms, each having 50 timeline events and
+//! 100 members, is served by the mock homeserver. Each iteration processes one
+//! sync cycle with the real client: receiving and deserializing the response,
+//! updating the store and dispatching every event to the handlers of the REPL,
+//! which update the timeline cache.
+//!
+//! Run with `cargo bench --bench sync_bench --features testing`. The benchmark
+//! exits with an error if a cycle is more than 20% slower than in the previous
+//! run, or than a saved baseline (`--save-baseline main`, then
+//! `--baseline main`), so CI fails on a significant regression.
+//!
+//! Run with `--features dhat-heap` to also write the allocations of a single
+//! cycle to `dhat-heap.json`.
+
+use std::{fs, path::PathBuf, process, sync::Arc, time::Duration};
+
+use criterion::{black_box, criterion_group, Criterion, Throughput};
+use matrix_client::{
+    bot::Bot,
+    config::Config,
+    crypto::verification::PendingVerifications,
+    display::{cache::DisplayNameCache, status::ActiveUsersDisplay},
+    notifications::sound::SoundNotifier,
+    plugins::PluginRegistry,
+    rooms::timeline::TimelineCache,
+    sync::add_event_handlers,
+    testing::mock_server::MockServer,
+};
+use matrix_sdk::{config::SyncSettings, Client};
+use serde_json::{json, Value};
+use tokio::{runtime::Runtime, sync::RwLock};
+
+#[cfg(feature = "dhat-heap")]
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+const ROOMS: usize = 200;
+const TIMELINE_EVENTS_PER_ROOM: usize = 50;
+const MEMBERS_PER_ROOM: usize = 100;
+
+/// The slowdown of a cycle, against the previous run or the baseline, that
+/// fails the benchmark.
+const REGRESSION_THRESHOLD: f64 = 0.20;
+
+/// Build the JSON of a sync response.
+fn synthetic_sync_response() -> Value {
+    let mut join = serde_json::Map::new();
+
+    for room in 0..ROOMS {
+        let members: Vec<Value> = (0..MEMBERS_PER_ROOM)
+            .map(|member| {
+                let user_id = format!("@user{member}:server{}.example", member % 10);
+                json!({
+                    "type": "m.room.member",
+                    "state_key": user_id,
+                    "sender": user_id,
+                    "event_id": format!("$member{room}_{member}"),
+                    "origin_server_ts": 1_700_000_000_000_u64,
+                    "content": { "membership": "join", "displayname": format!("User {member}") },
+                })
+            })
+            .collect();
+
+        let timeline: Vec<Value> = (0..TIMELINE_EVENTS_PER_ROOM)
+            .map(|event| {
+                json!({
+                    "type": "m.room.message",
+                    "sender": format!("@user{}:server{}.example", event % MEMBERS_PER_ROOM, event % 10),
+                    "event_id": format!("$event{room}_{event}"),
+                    "origin_server_ts": 1_700_000_000_000_u64 + event as u64,
+                    "content": { "msgtype": "m.text", "body": format!("Message number {event} in room {room}") },
+                })
+            })
+            .collect();
+
+        join.insert(
+            format!("!room{room}:example.org"),
+            json!({
+                "state": { "events": members },
+                "timeline": { "events": timeline, "limited": false },
+            }),
+        );
+    }
+
+    json!({
+        "next_batch": "s2_bench",
+        "rooms": { "join": join },
+    })
+}
+
+/// Build a client logged in on a mock homeserver answering every sync with the
+/// synthetic response, with the event handlers and contexts of the REPL.
+async fn client_with_handlers(server: &MockServer) -> Client {
+    server.mount_sync_response(synthetic_sync_response()).await;
+    let client = server
+        .logged_in_client()
+        .await
+        .expect("the mock homeserver should accept the session");
+
+    client.add_event_handler_context(SoundNotifier::disabled());
+    client.add_event_handler_context(TimelineCache::new(TIMELINE_EVENTS_PER_ROOM));
+    client.add_event_handler_context(Arc::new(DisplayNameCache::default()));
+    client.add_event_handler_context(ActiveUsersDisplay::default());
+    client.add_event_handler_context(Bot::with_builtin_commands());
+    client.add_event_handler_context(PluginRegistry::load(&[]));
+    client.add_event_handler_context(PendingVerifications::default());
+    add_event_handlers(&client, Arc::new(RwLock::new(Config::default())));
+
+    client
+}
+
+/// Process one sync cycle: receive the response, update the store and dispatch
+/// the events to the handlers of the REPL.
+async fn process_sync(client: &Client) {
+    client
+        .sync_once(SyncSettings::default())
+        .await
+        .expect("the mock homeserver should answer the sync");
+}
+
+fn sync_throughput(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("the runtime should start");
+    let server = runtime.block_on(MockServer::with_password_login("alice", "secret"));
+    let client = runtime.block_on(client_with_handlers(&server));
+
+    #[cfg(feature = "dhat-heap")]
+    {
+        let _profiler = dhat::Profiler::new_heap();
+        runtime.block_on(process_sync(&client));
+    }
+
+    let mut group = c.benchmark_group("sync");
+    group.throughput(Throughput::Elements(
+        (ROOMS * TIMELINE_EVENTS_PER_ROOM) as u64,
+    ));
+    group.bench_function("process_one_cycle", |b| {
+        b.iter(|| runtime.block_on(process_sync(black_box(&client))))
+    });
+    group.finish();
+}
+
+/// The file where criterion writes the change of the benchmark against the
+/// previous run or the baseline given with `--baseline`.
+fn change_estimates_file() -> PathBuf {
+    let criterion_home = std::env::var_os("CRITERION_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            std::env::var_os("CARGO_TARGET_DIR")
+                .map_or_else(|| PathBuf::from("target"), PathBuf::from)
+                .join("criterion")
+        });
+
+    criterion_home.join("sync/process_one_cycle/change/estimates.json")
+}
+
+/// Exit with an error if the mean time of a cycle increased by more than
+/// [`REGRESSION_THRESHOLD`].
+fn check_regression() {
+    let Ok(estimates) = fs::read_to_string(change_estimates_file()) else {
+        // There was nothing to compare to.
+        return;
+    };
+    let estimates: Value =
+        serde_json::from_str(&estimates).expect("criterion should write valid JSON");
+    let change = estimates["mean"]["point_estimate"]
+        .as_f64()
+        .expect("the estimates should contain the mean change");
+
+    if change > REGRESSION_THRESHOLD {
+        eprintln!(
+            "The sync cycle is {:.1}% slower, more than the {:.0}% threshold",
+            change * 100.0,
+            REGRESSION_THRESHOLD * 100.0
+        );
+        process::exit(1);
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().measurement_time(Duration::from_secs(10));
+    targets = sync_throughput
+}
+
+fn main() {
+    // Only compare with the results of this run.
+    let _ = fs::remove_file(change_estimates_file());
+
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+    check_regression();
+}
@@ -0,0 +1,75 @@
+//! Expose build-time information to the client as environment variables.
+use std::{env, f64::consts::PI, fs, path::Path, process::Command};
+
+/// The sample rate of the generated notification sound.
+const SAMPLE_RATE: u32 = 44_100;
+
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("beep.wav"), beep()).unwrap();
+
+    let lock_file = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.lock");
+    let sdk_version = fs::read_to_string(lock_file)
+        .ok()
+        .and_then(|lock| locked_version(&lock, "matrix-sdk"))
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=MATRIX_SDK_VERSION={sdk_version}");
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let rustc_version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=RUSTC_VERSION={rustc_version}");
+}
+
+/// Find the version of the given package in the content of `Cargo.lock`.
+fn locked_version(lock: &str, package: &str) -> Option<String> {
+    let name_line = format!("name = \"{package}\"");
+    let mut lines = lock.lines();
+    lines.find(|line| *line == name_line)?;
+
+    let version = lines
+        .next()?
+        .strip_prefix("version = \"")?
+        .strip_suffix('"')?;
+    Some(version.to_owned())
+}
+
+/// Generate a 200 ms beep as a 16-bit mono WAV file.
+fn beep() -> Vec<u8> {
+    let samples: Vec<i16> = (0..SAMPLE_RATE / 5)
+        .map(|idx| {
+            let t = f64::from(idx) / f64::from(SAMPLE_RATE);
+            ((2.0 * PI * 880.0 * t).sin() * f64::from(i16::MAX) * 0.3) as i16
+        })
+        .collect();
+    let data_len = (samples.len() * 2) as u32;
+
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16_u32.to_le_bytes());
+    // PCM format, 1 channel.
+    wav.extend_from_slice(&1_u16.to_le_bytes());
+    wav.extend_from_slice(&1_u16.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    // Byte rate, block align and bits per sample.
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2_u16.to_le_bytes());
+    wav.extend_from_slice(&16_u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    wav
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use futures::future::BoxFuture;
+use matrix_sdk::{
+    event_handler::Ctx,
+    ruma::{
+        events::room::message::{
+            MessageType, OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+        },
+        OwnedRoomId, RoomId,
+    },
+    Room,
+};
+use tracing::warn;
+
+/// The prefix of the commands the bot responds to.
+const COMMAND_PREFIX: char = '!';
+
+/// A handler for a bot command.
+///
+/// It receives the room where the command was sent and the arguments of the
+/// command.
+pub type CommandHandler =
+    Box<dyn Fn(Room, String) -> BoxFuture<'static, anyhow::Result<()>> + Send + Sync>;
+
+/// A minimal bot framework, that dispatches the commands sent in the rooms it
+/// listens to.
+///
+/// It is shared with the event handlers, it can be cloned cheaply.
+#[derive(Clone, Default)]
+pub struct Bot {
+    /// The rooms where commands are handled.
+    rooms: Arc<RwLock<HashSet<OwnedRoomId>>>,
+
+    /// The handlers of the commands, by command name without the prefix.
+    handlers: Arc<RwLock<HashMap<String, Arc<CommandHandler>>>>,
+}
+
+impl Bot {
+    /// Create a bot with the built-in `!ping` and `!help` commands.
+    pub fn with_builtin_commands() -> Self {
+        let bot = Self::default();
+
+        bot.register(
+            "ping",
+            Box::new(|room, _| {
+                Box::pin(async move {
+                    room.send(RoomMessageEventContent::notice_plain("pong"))
+                        .await?;
+                    Ok(())
+                })
+            }),
+        );
+
+        let handlers = bot.handlers.clone();
+        bot.register(
+            "help",
+            Box::new(move |room, _| {
+                let mut commands: Vec<_> = handlers
+                    .read()
+                    .expect("the bot handlers lock is not poisoned")
+                    .keys()
+                    .map(|name| format!("{COMMAND_PREFIX}{name}"))
+                    .collect();
+                commands.sort_unstable();
+
+                Box::pin(async move {
+                    let body = format!("Available commands: {}", commands.join(", "));
+                    room.send(RoomMessageEventContent::notice_plain(body))
+                        .await?;
+                    Ok(())
+                })
+            }),
+        );
+
+        bot
+    }
+
+    /// Register the handler of the command with the given name, without the
+    /// prefix.
+    pub fn register(&self, name: &str, handler: CommandHandler) {
+        self.handlers
+            .write()
+            .expect("the bot handlers lock is not poisoned")
+            .insert(name.to_owned(), Arc::new(handler));
+    }
+
+    /// Start handling the commands sent in the given room.
+    pub fn listen(&self, room_id: &RoomId) {
+        self.rooms
+            .write()
+            .expect("the bot rooms lock is not poisoned")
+            .insert(room_id.to_owned());
+    }
+
+    /// Whether the commands sent in the given room are handled.
+    fn is_listening(&self, room_id: &RoomId) -> bool {
+        self.rooms
+            .read()
+            .expect("the bot rooms lock is not poisoned")
+            .contains(room_id)
+    }
+}
+
+/// Dispatch the commands sent in the rooms the bot listens to.
+pub async fn on_room_message(event: OriginalSyncRoomMessageEvent, room: Room, bot: Ctx<Bot>) {
+    if !bot.is_listening(room.room_id()) {
+        return;
+    }
+    // Don't respond to ourselves.
+    if room.client().user_id() == Some(&*event.sender) {
+        return;
+    }
+    let MessageType::Text(text_content) = &event.content.msgtype else {
+        return;
+    };
+    let Some(command) = text_content.body.strip_prefix(COMMAND_PREFIX) else {
+        return;
+    };
+
+    let (name, args) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+    // The lock is released before calling the handler, `!help` takes it too.
+    let handler = bot
+        .handlers
+        .read()
+        .expect("the bot handlers lock is not poisoned")
+        .get(name)
+        .cloned();
+    let Some(handler) = handler else {
+        return;
+    };
+
+    if let Err(error) = handler(room, args.trim().to_owned()).await {
+        warn!("Error handling the bot command {name}: {error}");
+    }
+}
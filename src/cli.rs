@@ -0,0 +1,144 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
+
+use crate::{
+    diagnostics::LONG_VERSION,
+    display::{formatting::MessageFormat, image::ImageMode},
+};
+
+/// A Matrix client for the terminal.
+#[derive(Debug, Parser)]
+#[command(version, long_version = LONG_VERSION)]
+pub struct Cli {
+    /// The folder containing the data of the client.
+    ///
+    /// Defaults to `$XDG_DATA_HOME/matrix-client/`.
+    #[arg(long, env = "MATRIX_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// The file where the session is persisted.
+    ///
+    /// Defaults to the `session` file in the data directory.
+    #[arg(long, env = "MATRIX_SESSION_FILE")]
+    pub session_file: Option<PathBuf>,
+
+    /// The URL of the homeserver to log in to.
+    ///
+    /// Overrides the `MATRIX_HOMESERVER` environment variable.
+    #[arg(long)]
+    pub homeserver: Option<String>,
+
+    /// The username to log in with, without any prompt.
+    ///
+    /// Overrides the `MATRIX_USER` environment variable.
+    #[arg(long, requires = "password")]
+    pub user: Option<String>,
+
+    /// The password to log in with, without any prompt.
+    ///
+    /// Overrides the `MATRIX_PASSWORD` environment variable. It is visible to
+    /// other users of the system, prefer the environment variable.
+    #[arg(long, requires = "user")]
+    pub password: Option<String>,
+
+    /// Start with the application service account of the config, without
+    /// logging in.
+    #[arg(long, env = "MATRIX_BOT_MODE")]
+    pub bot_mode: bool,
+
+    /// Delete the local state store before logging in.
+    ///
+    /// Useful if it is corrupt or too large, it is fetched again from the
+    /// homeserver. The store of the bot mode is kept.
+    #[arg(long)]
+    pub clear_cache: bool,
+
+    /// Display the dates in UTC instead of the local timezone.
+    #[arg(long, env = "MATRIX_UTC_TIMESTAMPS")]
+    pub utc_timestamps: bool,
+
+    /// How the images of received messages are displayed.
+    ///
+    /// Defaults to the setting of the config, `off` if not set.
+    #[arg(long, value_enum, env = "MATRIX_IMAGE_MODE")]
+    pub image_mode: Option<ImageMode>,
+
+    /// How the text messages are sent, `md` renders them as Markdown.
+    ///
+    /// Defaults to the setting of the config, `plain` if not set.
+    #[arg(long, value_enum, env = "MATRIX_FORMAT")]
+    pub format: Option<MessageFormat>,
+
+    /// Connect to a client detached with `/detach` instead of logging in.
+    #[arg(long, conflicts_with = "command")]
+    pub attach: bool,
+
+    /// Serve the REPLs attached with `--attach` instead of running the REPL.
+    ///
+    /// This is how `/detach` restarts the client in the background.
+    #[arg(long, hide = true, conflicts_with_all = ["attach", "command"])]
+    pub detached: bool,
+
+    /// Don't play a sound when you are mentioned.
+    #[arg(long, env = "MATRIX_NO_SOUND")]
+    pub no_sound: bool,
+
+    /// Run a single command instead of the REPL.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// The commands that can be run without the REPL, for scripting.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Send a text message to a room and exit.
+    Send {
+        /// The ID or alias of the room.
+        room: String,
+
+        /// The text of the message.
+        #[arg(required_unless_present = "file", conflicts_with = "file")]
+        message: Option<String>,
+
+        /// Send each non-empty line of this file as a separate message.
+        #[arg(long)]
+        file: Option<PathBuf>,
+
+        /// The delay between messages sent from a file, in milliseconds.
+        #[arg(long, default_value_t = 200)]
+        delay_ms: u64,
+
+        /// The type of the message.
+        #[arg(long = "type", value_enum, default_value_t)]
+        message_type: MessageKind,
+    },
+
+    /// Check that the homeserver is reachable without logging in, and exit.
+    ///
+    /// Exits with a non-zero status if the homeserver doesn't answer
+    /// correctly.
+    HealthCheck,
+}
+
+/// The types of messages that can be sent with the `send` command.
+#[derive(Debug, Default, Clone, Copy, ValueEnum)]
+pub enum MessageKind {
+    /// A regular text message.
+    #[default]
+    Text,
+
+    /// A notice, for automated messages.
+    Notice,
+}
+
+impl MessageKind {
+    /// The content of a message of this type with the given body.
+    pub fn content(self, body: &str, format: MessageFormat) -> RoomMessageEventContent {
+        match self {
+            MessageKind::Text => format.text(body),
+            MessageKind::Notice => format.notice(body),
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crossterm::style::Color;
+use matrix_sdk::ruma::{OwnedDeviceId, OwnedUserId};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::RwLock};
+use tracing::warn;
+
+use crate::{
+    display::{
+        filter::{EventFilter, MentionFilter},
+        formatting::MessageFormat,
+        image::ImageMode,
+    },
+    notifications::watcher::UserWatcher,
+    rooms::receipts::ReceiptType,
+};
+
+/// The name of the configuration file, in the data directory.
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// The environment variable containing the URL of the homeserver.
+const HOMESERVER_ENV_VAR: &str = "MATRIX_HOMESERVER";
+
+/// The environment variable containing the username to log in with.
+const USER_ENV_VAR: &str = "MATRIX_USER";
+
+/// The environment variable containing the password to log in with.
+const PASSWORD_ENV_VAR: &str = "MATRIX_PASSWORD";
+
+/// The configuration shared between the REPL and the event handlers.
+pub type SharedConfig = Arc<RwLock<Config>>;
+
+/// The settings of the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The folder containing the data of the client.
+    #[serde(skip)]
+    pub data_dir: PathBuf,
+
+    /// The file where the session is persisted.
+    #[serde(skip)]
+    pub session_file: PathBuf,
+
+    /// The URL of the homeserver to log in to.
+    ///
+    /// It is asked during the login if this is not set.
+    pub homeserver: Option<String>,
+
+    /// The username to log in with.
+    pub user: Option<String>,
+
+    /// The password to log in with.
+    ///
+    /// It is never written to the configuration file.
+    #[serde(skip)]
+    pub password: Option<String>,
+
+    /// The events hidden from the timeline.
+    pub filter: EventFilter,
+
+    /// Whether only the messages mentioning the user are displayed.
+    pub mention_filter: MentionFilter,
+
+    /// The users whose joins in the joined rooms are announced.
+    pub watched_users: UserWatcher,
+
+    /// The WAV or OGG file to play when the user is mentioned.
+    ///
+    /// A short beep is played if this is not set.
+    pub notification_sound_path: Option<PathBuf>,
+
+    /// The maximum number of commands kept in the REPL history.
+    pub max_history_size: usize,
+
+    /// The number of events kept in memory per room, for the commands working on
+    /// the events received since the client started.
+    pub timeline_cache_size: usize,
+
+    /// The number of recent events of the current room whose IDs are completed
+    /// in the REPL.
+    pub completed_events_count: usize,
+
+    /// How the text messages typed by the user are sent.
+    pub message_format: MessageFormat,
+
+    /// The read receipts sent when marking a room as read.
+    pub receipt_type: ReceiptType,
+
+    /// How the timeline is displayed.
+    pub display: DisplayConfig,
+
+    /// The account used in bot mode.
+    pub bot: BotConfig,
+
+    /// The names of the plugins to load at startup.
+    pub plugins: Vec<String>,
+
+    /// Whether our own devices are signed with the cross-signing key after
+    /// they are verified.
+    pub auto_cross_sign_on_verification: bool,
+
+    /// The values of the file for the settings overridden in the running
+    /// session.
+    #[serde(skip)]
+    file_values: FileValues,
+}
+
+/// The values of the configuration file for the settings overridden by the
+/// environment, the command line or the REPL, for the running session only.
+///
+/// They are written back instead of the overrides when the configuration is
+/// saved.
+#[derive(Debug, Clone, Default)]
+struct FileValues {
+    homeserver: Option<Option<String>>,
+    user: Option<Option<String>>,
+    utc_timestamps: Option<bool>,
+    image_mode: Option<ImageMode>,
+    message_format: Option<MessageFormat>,
+}
+
+/// How the timeline is displayed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Whether the names of the senders are colored.
+    pub colored_senders: bool,
+
+    /// The color of our own name, when the names of the senders are colored.
+    pub self_message_color: Color,
+
+    /// Whether the dates are displayed in UTC instead of the local timezone.
+    pub utc_timestamps: bool,
+
+    /// How the images of received messages are displayed.
+    ///
+    /// They are not displayed by default, `external` opens every received
+    /// image.
+    pub image_mode: ImageMode,
+
+    /// The number of characters of a message displayed before it is truncated.
+    ///
+    /// Truncated messages can be displayed fully with `/expand`. Messages are
+    /// never truncated if this is 0.
+    pub max_message_display_length: usize,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            colored_senders: true,
+            self_message_color: Color::Cyan,
+            utc_timestamps: false,
+            image_mode: ImageMode::default(),
+            max_message_display_length: 500,
+        }
+    }
+}
+
+/// The application service account used in bot mode.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BotConfig {
+    /// The URL of the homeserver of the account.
+    pub homeserver: Option<String>,
+
+    /// The user ID of the account.
+    pub user_id: Option<OwnedUserId>,
+
+    /// The device ID to use.
+    pub device_id: Option<OwnedDeviceId>,
+
+    /// The access token of the account.
+    pub access_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::new(),
+            session_file: PathBuf::new(),
+            homeserver: None,
+            user: None,
+            password: None,
+            filter: EventFilter::default(),
+            mention_filter: MentionFilter::default(),
+            watched_users: UserWatcher::default(),
+            notification_sound_path: None,
+            max_history_size: 1000,
+            timeline_cache_size: 500,
+            completed_events_count: 50,
+            message_format: MessageFormat::default(),
+            receipt_type: ReceiptType::default(),
+            display: DisplayConfig::default(),
+            bot: BotConfig::default(),
+            plugins: Vec::new(),
+            auto_cross_sign_on_verification: true,
+            file_values: FileValues::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration file from the given data directory.
+    ///
+    /// The default settings are used if the file doesn't exist yet.
+    pub async fn load(data_dir: &Path, session_file: &Path) -> anyhow::Result<Self> {
+        let config_file = data_dir.join(CONFIG_FILE_NAME);
+
+        let mut config: Config = if config_file.exists() {
+            toml::from_str(&fs::read_to_string(&config_file).await?)?
+        } else {
+            Config::default()
+        };
+
+        config.data_dir = data_dir.to_owned();
+        config.session_file = session_file.to_owned();
+
+        Ok(config)
+    }
+
+    /// Override the settings with the values of the `MATRIX_HOMESERVER`,
+    /// `MATRIX_USER` and `MATRIX_PASSWORD` environment variables, if they are
+    /// set.
+    ///
+    /// The overrides are not saved to the file.
+    pub fn apply_env(&mut self) {
+        if let Ok(homeserver) = std::env::var(HOMESERVER_ENV_VAR) {
+            self.override_homeserver(homeserver);
+        }
+        if let Ok(user) = std::env::var(USER_ENV_VAR) {
+            self.override_user(user);
+        }
+        if let Ok(password) = std::env::var(PASSWORD_ENV_VAR) {
+            warn!(
+                "Reading the password from {PASSWORD_ENV_VAR}, it can be read by other users \
+                 in shared environments"
+            );
+            self.password = Some(password);
+        }
+    }
+
+    /// Log in to the given homeserver in the running session only.
+    pub fn override_homeserver(&mut self, homeserver: String) {
+        set_override(
+            &mut self.homeserver,
+            &mut self.file_values.homeserver,
+            Some(homeserver),
+        );
+    }
+
+    /// Log in with the given username in the running session only.
+    pub fn override_user(&mut self, user: String) {
+        set_override(&mut self.user, &mut self.file_values.user, Some(user));
+    }
+
+    /// Display the dates in UTC in the running session only.
+    pub fn override_utc_timestamps(&mut self, utc_timestamps: bool) {
+        set_override(
+            &mut self.display.utc_timestamps,
+            &mut self.file_values.utc_timestamps,
+            utc_timestamps,
+        );
+    }
+
+    /// Display the images with the given mode in the running session only.
+    pub fn override_image_mode(&mut self, image_mode: ImageMode) {
+        set_override(
+            &mut self.display.image_mode,
+            &mut self.file_values.image_mode,
+            image_mode,
+        );
+    }
+
+    /// Send the text messages in the given format in the running session only.
+    pub fn override_message_format(&mut self, message_format: MessageFormat) {
+        set_override(
+            &mut self.message_format,
+            &mut self.file_values.message_format,
+            message_format,
+        );
+    }
+
+    /// The configuration as it is written to the file, without the overrides
+    /// of the running session.
+    fn persisted(&self) -> Config {
+        let mut config = self.clone();
+        let FileValues {
+            homeserver,
+            user,
+            utc_timestamps,
+            image_mode,
+            message_format,
+        } = &self.file_values;
+        if let Some(homeserver) = homeserver {
+            config.homeserver = homeserver.clone();
+        }
+        if let Some(user) = user {
+            config.user = user.clone();
+        }
+        if let Some(utc_timestamps) = utc_timestamps {
+            config.display.utc_timestamps = *utc_timestamps;
+        }
+        if let Some(image_mode) = image_mode {
+            config.display.image_mode = *image_mode;
+        }
+        if let Some(message_format) = message_format {
+            config.message_format = *message_format;
+        }
+
+        config
+    }
+
+    /// The path of the configuration file.
+    pub fn file(&self) -> PathBuf {
+        self.data_dir.join(CONFIG_FILE_NAME)
+    }
+
+    /// Write the configuration to the given file.
+    ///
+    /// The configuration is written to a temporary file that replaces the
+    /// previous one, so it is not corrupted if the write is interrupted.
+    pub async fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp_path = path.with_extension("toml.tmp");
+        fs::write(&tmp_path, toml::to_string_pretty(&self.persisted())?).await?;
+        fs::rename(&tmp_path, path).await?;
+
+        Ok(())
+    }
+
+    /// List the settings that differ from the given file, like
+    /// `display.colored_senders: true → false`.
+    ///
+    /// The default settings are compared if the file doesn't exist yet.
+    pub async fn diff_with_file(&self, path: &Path) -> anyhow::Result<Vec<String>> {
+        let saved: toml::Value = if path.exists() {
+            toml::from_str(&fs::read_to_string(path).await?)?
+        } else {
+            toml::Value::try_from(Config::default())?
+        };
+
+        let mut old = BTreeMap::new();
+        flatten_settings("", &saved, &mut old);
+        let mut new = BTreeMap::new();
+        flatten_settings("", &toml::Value::try_from(self.persisted())?, &mut new);
+
+        let keys: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+        let changes = keys
+            .into_iter()
+            .filter_map(|key| {
+                let (old, new) = (old.get(key), new.get(key));
+                (old != new).then(|| {
+                    let show =
+                        |value: Option<&String>| value.map_or("unset", String::as_str).to_owned();
+                    format!("{key}: {} → {}", show(old), show(new))
+                })
+            })
+            .collect();
+
+        Ok(changes)
+    }
+}
+
+/// Replace the value of a setting for the running session, remembering the
+/// value of the file the first time it is overridden.
+fn set_override<T: Clone>(setting: &mut T, file_value: &mut Option<T>, value: T) {
+    file_value.get_or_insert_with(|| setting.clone());
+    *setting = value;
+}
+
+/// Collect the values of the settings with their dotted path, like
+/// `display.image_mode`.
+fn flatten_settings(prefix: &str, value: &toml::Value, settings: &mut BTreeMap<String, String>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten_settings(&path, value, settings);
+            }
+        }
+        value => {
+            settings.insert(prefix.to_owned(), value.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::display::formatting::MessageFormat;
+
+    #[test]
+    fn overrides_are_not_persisted() {
+        let mut config = Config {
+            homeserver: Some("https://file.example".to_owned()),
+            ..Config::default()
+        };
+        config.override_homeserver("https://env.example".to_owned());
+        config.override_homeserver("https://cli.example".to_owned());
+        config.override_user("alice".to_owned());
+        config.override_message_format(MessageFormat::Md);
+
+        assert_eq!(config.homeserver.as_deref(), Some("https://cli.example"));
+        let persisted = config.persisted();
+        assert_eq!(
+            persisted.homeserver.as_deref(),
+            Some("https://file.example")
+        );
+        assert_eq!(persisted.user, None);
+        assert_eq!(persisted.message_format, MessageFormat::Plain);
+    }
+}
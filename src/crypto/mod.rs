@@ -0,0 +1,141 @@
+pub mod verification;
+
+use anyhow::anyhow;
+use crossterm::style::Stylize;
+use matrix_sdk::{
+    encryption::{identities::Device, LocalTrust},
+    ruma::{
+        events::secret::request::{RequestAction, ToDeviceSecretRequestEvent},
+        DeviceId, UserId,
+    },
+    Client,
+};
+use tracing::{debug, info};
+
+/// List the devices of the user with their trust state.
+pub async fn list_devices(client: &Client, user_id: &UserId) -> anyhow::Result<()> {
+    let devices = client.encryption().get_user_devices(user_id).await?;
+
+    let mut count = 0;
+    for device in devices.devices() {
+        count += 1;
+        let trust = if device.is_blacklisted() {
+            "blocked".red()
+        } else if device.is_verified() {
+            "trusted".green()
+        } else {
+            "unverified".yellow()
+        };
+        println!(
+            "{:<20} {:<30} {trust}",
+            device.device_id().as_str(),
+            device.display_name().unwrap_or("")
+        );
+    }
+    println!("{count} devices");
+
+    Ok(())
+}
+
+/// Mark the device as trusted.
+///
+/// Our own devices are signed with our cross-signing key, the devices of other
+/// users are only trusted locally.
+pub async fn trust_device(
+    client: &Client,
+    user_id: &UserId,
+    device_id: &DeviceId,
+) -> anyhow::Result<()> {
+    let device = get_device(client, user_id, device_id).await?;
+    if client.user_id() == Some(user_id) {
+        device.verify().await?;
+    } else {
+        device.set_local_trust(LocalTrust::Verified).await?;
+    }
+    println!("Trusted {}", describe_device(&device));
+
+    Ok(())
+}
+
+/// Remove the local trust of the device.
+///
+/// A device that was signed with a cross-signing key stays verified.
+pub async fn untrust_device(
+    client: &Client,
+    user_id: &UserId,
+    device_id: &DeviceId,
+) -> anyhow::Result<()> {
+    let device = get_device(client, user_id, device_id).await?;
+    device.set_local_trust(LocalTrust::Unset).await?;
+    println!("Removed the trust of {}", describe_device(&device));
+
+    Ok(())
+}
+
+/// Block the device, the room keys are never shared with it.
+pub async fn block_device(
+    client: &Client,
+    user_id: &UserId,
+    device_id: &DeviceId,
+) -> anyhow::Result<()> {
+    let device = get_device(client, user_id, device_id).await?;
+    device.set_local_trust(LocalTrust::BlackListed).await?;
+    println!("Blocked {}", describe_device(&device));
+
+    Ok(())
+}
+
+/// Unblock the device.
+pub async fn unblock_device(
+    client: &Client,
+    user_id: &UserId,
+    device_id: &DeviceId,
+) -> anyhow::Result<()> {
+    let device = get_device(client, user_id, device_id).await?;
+    device.set_local_trust(LocalTrust::Unset).await?;
+    println!("Unblocked {}", describe_device(&device));
+
+    Ok(())
+}
+
+/// Log the requests for our secrets, like the cross-signing keys or the backup
+/// key, sent by other devices.
+///
+/// The SDK answers the requests itself and doesn't report whether it shared the
+/// secret, so only the request is logged.
+pub async fn on_secret_request(event: ToDeviceSecretRequestEvent) {
+    let RequestAction::Request(secret) = &event.content.action else {
+        debug!("Secret request {} cancelled", event.content.request_id);
+        return;
+    };
+
+    info!(
+        "Received a request for {secret} from the device {} of {}",
+        event.content.requesting_device_id, event.sender
+    );
+}
+
+/// Get the device of the user, or an error if it is unknown.
+async fn get_device(
+    client: &Client,
+    user_id: &UserId,
+    device_id: &DeviceId,
+) -> anyhow::Result<Device> {
+    client
+        .encryption()
+        .get_device(user_id, device_id)
+        .await?
+        .ok_or_else(|| anyhow!("Unknown device {device_id} of {user_id}, see /devices {user_id}"))
+}
+
+/// The display name and ID of the device, and its owner.
+fn describe_device(device: &Device) -> String {
+    match device.display_name() {
+        Some(name) => format!(
+            "device {name} ({}) of {}",
+            device.device_id(),
+            device.user_id()
+        ),
+        None => format!("device {} of {}", device.device_id(), device.user_id()),
+    }
+}
@@ -0,0 +1,285 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::bail;
+use futures::StreamExt;
+use matrix_sdk::{
+    encryption::{
+        identities::Device,
+        verification::{
+            SasState, SasVerification, Verification, VerificationRequest, VerificationRequestState,
+        },
+    },
+    event_handler::Ctx,
+    ruma::{
+        events::{
+            key::verification::request::ToDeviceKeyVerificationRequestEvent,
+            room::message::{MessageType, OriginalSyncRoomMessageEvent},
+        },
+        DeviceId, UserId,
+    },
+    Client,
+};
+
+use super::get_device;
+
+/// The verification waiting for an answer of the user, with `/verify-accept`
+/// or `/verify-reject`.
+///
+/// The event handlers can't wait for the answer, so they store the
+/// verification here and the REPL answers it. It can be cloned cheaply.
+#[derive(Debug, Clone, Default)]
+pub struct PendingVerifications {
+    inner: Arc<Mutex<Option<PendingVerification>>>,
+}
+
+/// A step of a verification where the user must answer.
+#[derive(Debug)]
+enum PendingVerification {
+    /// Another device sent a verification request.
+    Request(VerificationRequest),
+
+    /// The emojis are displayed and must be compared with the other device.
+    Emojis(SasVerification),
+}
+
+impl PendingVerifications {
+    /// Wait for the answer to the given step, replacing the previous one.
+    fn set(&self, pending: PendingVerification) {
+        *self.lock() = Some(pending);
+    }
+
+    /// Forget the step of the verification with the given flow ID, if it is
+    /// still waiting.
+    fn remove(&self, flow_id: &str) {
+        let mut pending = self.lock();
+        let is_flow = match &*pending {
+            Some(PendingVerification::Request(request)) => request.flow_id().as_str() == flow_id,
+            Some(PendingVerification::Emojis(sas)) => sas.flow_id().as_str() == flow_id,
+            None => false,
+        };
+        if is_flow {
+            *pending = None;
+        }
+    }
+
+    /// Take the step waiting for an answer, if any.
+    fn take(&self) -> Option<PendingVerification> {
+        self.lock().take()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<PendingVerification>> {
+        self.inner
+            .lock()
+            .expect("the pending verifications lock is not poisoned")
+    }
+
+    /// Accept the pending verification request, or confirm that the emojis
+    /// match.
+    ///
+    /// If `auto_cross_sign` is set, our own devices are cross-signed once they
+    /// are verified.
+    pub async fn accept(&self, client: &Client, auto_cross_sign: bool) -> anyhow::Result<()> {
+        match self.take() {
+            Some(PendingVerification::Request(request)) => {
+                request.accept().await?;
+                let client = client.clone();
+                let pending = self.clone();
+                tokio::spawn(async move {
+                    let other_user_id = request.other_user_id().to_owned();
+                    if let Err(error) =
+                        follow_verification(&client, request, &pending, auto_cross_sign).await
+                    {
+                        println!("Error during the verification with {other_user_id}: {error}");
+                    }
+                });
+            }
+            Some(PendingVerification::Emojis(sas)) => sas.confirm().await?,
+            None => bail!("No verification is waiting for an answer"),
+        }
+
+        Ok(())
+    }
+
+    /// Refuse the pending verification request, or report that the emojis
+    /// don't match.
+    pub async fn reject(&self) -> anyhow::Result<()> {
+        match self.take() {
+            Some(PendingVerification::Request(request)) => request.cancel().await?,
+            Some(PendingVerification::Emojis(sas)) => sas.mismatch().await?,
+            None => bail!("No verification is waiting for an answer"),
+        }
+
+        Ok(())
+    }
+}
+
+/// Handle verification requests sent to this device.
+pub async fn on_verification_request(
+    event: ToDeviceKeyVerificationRequestEvent,
+    client: Client,
+    pending: Ctx<PendingVerifications>,
+) {
+    let Some(request) = client
+        .encryption()
+        .get_verification_request(&event.sender, &event.content.transaction_id)
+        .await
+    else {
+        return;
+    };
+
+    offer_verification(request, &event.sender, &event.content.from_device, &pending);
+}
+
+/// Handle verification requests sent in a direct conversation.
+pub async fn on_room_verification_request(
+    event: OriginalSyncRoomMessageEvent,
+    client: Client,
+    pending: Ctx<PendingVerifications>,
+) {
+    let MessageType::VerificationRequest(content) = &event.content.msgtype else {
+        return;
+    };
+    if client.user_id() != Some(&content.to) {
+        return;
+    }
+
+    let Some(request) = client
+        .encryption()
+        .get_verification_request(&event.sender, &event.event_id)
+        .await
+    else {
+        return;
+    };
+
+    offer_verification(request, &event.sender, &content.from_device, &pending);
+}
+
+/// Let the user accept or refuse the verification request from the REPL.
+fn offer_verification(
+    request: VerificationRequest,
+    user_id: &UserId,
+    device_id: &DeviceId,
+    pending: &PendingVerifications,
+) {
+    pending.set(PendingVerification::Request(request));
+    println!(
+        "Device {device_id} of {user_id} wants to verify, accept with /verify-accept or refuse \
+         with /verify-reject"
+    );
+}
+
+/// Go through the emoji verification once the request is accepted.
+async fn follow_verification(
+    client: &Client,
+    request: VerificationRequest,
+    pending: &PendingVerifications,
+    auto_cross_sign: bool,
+) -> anyhow::Result<()> {
+    // The other device starts the emoji verification once the request is
+    // accepted.
+    let mut changes = request.changes();
+    while let Some(state) = changes.next().await {
+        match state {
+            VerificationRequestState::Transitioned {
+                verification: Verification::SasV1(sas),
+            } => return follow_sas_verification(client, sas, pending, auto_cross_sign).await,
+            VerificationRequestState::Transitioned { .. } => {
+                println!("Only the emoji verification is supported");
+                request.cancel().await?;
+                break;
+            }
+            VerificationRequestState::Cancelled(info) => {
+                println!("The verification was cancelled: {}", info.reason());
+                break;
+            }
+            VerificationRequestState::Done => break,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Show the emojis to compare with the other device, the user confirms whether
+/// they match from the REPL.
+async fn follow_sas_verification(
+    client: &Client,
+    sas: SasVerification,
+    pending: &PendingVerifications,
+    auto_cross_sign: bool,
+) -> anyhow::Result<()> {
+    sas.accept().await?;
+
+    let mut changes = sas.changes();
+    while let Some(state) = changes.next().await {
+        match state {
+            SasState::KeysExchanged {
+                emojis: Some(emojis),
+                ..
+            } => {
+                println!("Check that the other device shows the same emojis:");
+                let symbols = emojis.emojis.map(|emoji| emoji.symbol);
+                let descriptions = emojis.emojis.map(|emoji| emoji.description);
+                println!("{}", symbols.join("  "));
+                println!("{}", descriptions.join(", "));
+
+                pending.set(PendingVerification::Emojis(sas.clone()));
+                println!("Answer with /verify-accept if they match, /verify-reject otherwise");
+            }
+            SasState::Done { .. } => {
+                let device = sas.other_device();
+                println!(
+                    "Successfully verified device {} of {}",
+                    device.device_id(),
+                    device.user_id()
+                );
+
+                if auto_cross_sign && client.user_id() == Some(device.user_id()) {
+                    let device = get_device(client, device.user_id(), device.device_id()).await?;
+                    cross_sign_device(&device).await?;
+                }
+                break;
+            }
+            SasState::Cancelled(info) => {
+                pending.remove(sas.flow_id().as_str());
+                println!("The verification failed: {}", info.reason());
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Sign the current device with our cross-signing key if it is not signed yet
+/// and the key is available on this device.
+pub async fn cross_sign_current_device(client: &Client) -> anyhow::Result<()> {
+    let encryption = client.encryption();
+    let has_self_signing_key = encryption
+        .cross_signing_status()
+        .await
+        .is_some_and(|status| status.has_self_signing);
+    if !has_self_signing_key {
+        return Ok(());
+    }
+
+    if let Some(device) = encryption.get_own_device().await? {
+        cross_sign_device(&device).await?;
+    }
+
+    Ok(())
+}
+
+/// Sign one of our own devices with our cross-signing key, if it is not signed
+/// yet.
+async fn cross_sign_device(device: &Device) -> anyhow::Result<()> {
+    if device.is_cross_signed_by_owner() {
+        return Ok(());
+    }
+
+    device.verify().await?;
+    println!("Device cross-signed successfully");
+
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use std::{
+    env,
+    fs::Permissions,
+    os::unix::{fs::PermissionsExt, process::CommandExt},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Context};
+use matrix_sdk::{
+    ruma::{
+        events::room::message::{OriginalSyncRoomMessageEvent, RoomMessageEventContent},
+        OwnedRoomId, RoomId, RoomOrAliasId,
+    },
+    Client, Room, RoomState,
+};
+use tokio::{
+    fs,
+    io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{broadcast, mpsc},
+};
+use tracing::warn;
+
+use crate::rooms::aliases::resolve_alias;
+
+/// The name of the socket of a detached client, in the data directory.
+pub const SOCKET_FILE_NAME: &str = "detach.sock";
+
+/// The number of messages kept for an attached REPL that doesn't read them
+/// fast enough.
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// The line sent by an attached REPL to stop the detached client.
+const QUIT_COMMAND: &str = "/quit";
+
+/// The line sent by an attached REPL to disconnect, leaving the client running.
+const DETACH_COMMAND: &str = "/detach";
+
+/// The line sent by an attached REPL to change the room where its messages are
+/// sent.
+const SWITCH_COMMAND: &str = "/switch";
+
+/// Serve the REPLs attached with `--attach` on a Unix socket at the given path,
+/// while the sync keeps running.
+///
+/// The protocol is line-based: the REPL sends the lines typed by the user, and
+/// receives the messages of the joined rooms and the answers to its commands.
+/// Only the messages, `/switch <room>`, `/detach` and `/quit` are supported.
+///
+/// Returns when an attached REPL sends `/quit`.
+pub async fn serve(client: &Client, socket_path: &Path) -> anyhow::Result<()> {
+    // The socket of a previous detached client that didn't exit cleanly.
+    if socket_path.exists() {
+        fs::remove_file(socket_path).await?;
+    }
+    let listener = UnixListener::bind(socket_path)?;
+    // Anyone who can connect can send messages as the user, so only the user
+    // can.
+    fs::set_permissions(socket_path, Permissions::from_mode(0o600)).await?;
+
+    let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+    let sender = events.clone();
+    let handle =
+        client.add_event_handler(move |event: OriginalSyncRoomMessageEvent, room: Room| {
+            let sender = sender.clone();
+            async move {
+                if room.state() == RoomState::Joined {
+                    // There is no error if no REPL is attached.
+                    let _ = sender.send(format!(
+                        "[{}] {}: {}",
+                        room.room_id(),
+                        event.sender,
+                        event.content.body()
+                    ));
+                }
+            }
+        });
+
+    let (quit_sender, mut quit) = mpsc::channel(1);
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => accepted?.0,
+            _ = quit.recv() => break,
+        };
+
+        let client = client.clone();
+        let events = events.subscribe();
+        let quit_sender = quit_sender.clone();
+        tokio::spawn(async move {
+            match handle_connection(stream, &client, events).await {
+                Ok(true) => {
+                    let _ = quit_sender.send(()).await;
+                }
+                Ok(false) => {}
+                Err(error) => warn!("An attached REPL failed: {error}"),
+            }
+        });
+    }
+
+    client.remove_event_handler(handle);
+    fs::remove_file(socket_path).await?;
+
+    Ok(())
+}
+
+/// Restart the client in the background, detached from the terminal, to serve
+/// the REPLs attached with `--attach` while it keeps syncing.
+///
+/// The new process runs in its own session, without access to the terminal,
+/// and restores the session persisted in `session_file`. The current client
+/// must be dropped before, so the new one can open the store.
+pub fn spawn_detached(
+    data_dir: &Path,
+    session_file: &Path,
+    bot_mode: bool,
+    no_sound: bool,
+) -> anyhow::Result<()> {
+    let mut command = Command::new(env::current_exe()?);
+    command
+        .arg("--detached")
+        .arg("--data-dir")
+        .arg(data_dir)
+        .arg("--session-file")
+        .arg(session_file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if bot_mode {
+        command.arg("--bot-mode");
+    }
+    if no_sound {
+        command.arg("--no-sound");
+    }
+
+    // SAFETY: `setsid` and `umask` are async-signal-safe, they can be called
+    // between `fork` and `exec`.
+    unsafe {
+        command.pre_exec(|| {
+            // Leave the session of the terminal, so closing it doesn't stop
+            // the client.
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // The files of the detached client, like its socket, are private.
+            libc::umask(0o077);
+            Ok(())
+        });
+    }
+    command
+        .spawn()
+        .context("Could not start the detached client")?;
+
+    println!(
+        "Detached, run the client with --attach to get the REPL back ({})",
+        data_dir.join(SOCKET_FILE_NAME).display()
+    );
+
+    Ok(())
+}
+
+/// Forward the messages and commands of an attached REPL.
+///
+/// Returns whether the REPL asked to stop the detached client.
+async fn handle_connection(
+    stream: UnixStream,
+    client: &Client,
+    mut events: broadcast::Receiver<String>,
+) -> anyhow::Result<bool> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut current_room: Option<OwnedRoomId> = None;
+
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            event = events.recv() => {
+                match event {
+                    Ok(event) => writer.write_all(format!("{event}\n").as_bytes()).await?,
+                    Err(broadcast::error::RecvError::Lagged(count)) => {
+                        writer
+                            .write_all(format!("{count} messages were skipped\n").as_bytes())
+                            .await?;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(false),
+                }
+                continue;
+            }
+        };
+        // The REPL disconnected.
+        let Some(line) = line else {
+            return Ok(false);
+        };
+
+        let line = line.trim();
+        let answer = match line.split_once(char::is_whitespace).unwrap_or((line, "")) {
+            ("", _) => continue,
+            (QUIT_COMMAND, _) => return Ok(true),
+            (DETACH_COMMAND, _) => return Ok(false),
+            (SWITCH_COMMAND, room) => match joined_room(client, room.trim()).await {
+                Ok(room) => {
+                    current_room = Some(room.room_id().to_owned());
+                    format!("Messages are sent to {}", room.room_id())
+                }
+                Err(error) => format!("Error: {error}"),
+            },
+            (command, _) if command.starts_with('/') => {
+                format!("Error: {command} is not supported in an attached REPL")
+            }
+            _ => match current_room
+                .as_deref()
+                .and_then(|room_id| client.get_room(room_id))
+            {
+                Some(room) => match room.send(RoomMessageEventContent::text_plain(line)).await {
+                    Ok(_) => continue,
+                    Err(error) => format!("Error: {error}"),
+                },
+                None => "Error: No room selected, use /switch <room> first".to_owned(),
+            },
+        };
+        writer.write_all(format!("{answer}\n").as_bytes()).await?;
+    }
+}
+
+/// Get the joined room with the given ID or alias.
+async fn joined_room(client: &Client, room: &str) -> anyhow::Result<Room> {
+    let room = RoomOrAliasId::parse(room)?;
+    let room_id = match <&RoomId>::try_from(&*room) {
+        Ok(room_id) => room_id.to_owned(),
+        Err(alias) => resolve_alias(client, alias).await?.room_id,
+    };
+
+    client
+        .get_room(&room_id)
+        .filter(|room| room.state() == RoomState::Joined)
+        .ok_or_else(|| anyhow!("You are not a member of {room_id}"))
+}
+
+/// Connect a REPL to the detached client listening on the given socket.
+///
+/// Returns when the detached client stops or the user sends `/detach` or
+/// `/quit`.
+pub async fn attach(socket_path: &Path) -> anyhow::Result<()> {
+    let stream = UnixStream::connect(socket_path).await.with_context(|| {
+        format!(
+            "No detached client is listening on {}",
+            socket_path.display()
+        )
+    })?;
+    println!("Attached, type /detach to leave the client running or /quit to stop it");
+
+    let (reader, mut writer) = stream.into_split();
+    let mut received = BufReader::new(reader).lines();
+    let mut typed = BufReader::new(io::stdin()).lines();
+
+    loop {
+        tokio::select! {
+            line = received.next_line() => match line? {
+                Some(line) => println!("{line}"),
+                None => break,
+            },
+            line = typed.next_line() => {
+                let Some(line) = line? else {
+                    break;
+                };
+                writer.write_all(format!("{line}\n").as_bytes()).await?;
+                if matches!(line.trim(), QUIT_COMMAND | DETACH_COMMAND) {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
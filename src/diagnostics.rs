@@ -0,0 +1,223 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use matrix_sdk::{Client, RoomMemberships};
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{config::Config, display::formatting::format_size, rooms::timeline::TimelineCache};
+
+/// The versions of the client, the SDK and the Rust toolchain, as shown by
+/// `--version`.
+pub const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    "\nmatrix-sdk ",
+    env!("MATRIX_SDK_VERSION"),
+    "\n",
+    env!("RUSTC_VERSION"),
+);
+
+/// The number of log lines kept in memory to be included in bug reports.
+const RECENT_LOG_LINES: usize = 50;
+
+/// The most recent log lines.
+static RECENT_LOGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Setup logging to stdout, keeping the most recent lines in memory.
+pub fn init_logging() {
+    tracing_subscriber::fmt()
+        .with_writer(|| RecentLogsWriter)
+        .init();
+}
+
+/// The versions of the client, the SDK and the Rust toolchain it was built
+/// with.
+pub fn show_version_info() -> String {
+    [
+        ("Client", env!("CARGO_PKG_VERSION")),
+        ("matrix-sdk", env!("MATRIX_SDK_VERSION")),
+        ("Rust", env!("RUSTC_VERSION")),
+    ]
+    .iter()
+    .map(|(label, version)| format!("{label:<20} {version}"))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// A writer that forwards the logs to stdout and remembers the last ones.
+struct RecentLogsWriter;
+
+impl Write for RecentLogsWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut recent_logs = RECENT_LOGS
+            .lock()
+            .expect("the log buffer lock is not poisoned");
+        for line in String::from_utf8_lossy(buf).lines() {
+            if recent_logs.len() == RECENT_LOG_LINES {
+                recent_logs.pop_front();
+            }
+            recent_logs.push_back(line.to_owned());
+        }
+
+        io::stdout().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// The diagnostics collected in a bug report.
+#[derive(Debug, Serialize)]
+struct BugReport {
+    /// The version of this client.
+    client_version: &'static str,
+
+    /// The version of the Matrix SDK.
+    sdk_version: &'static str,
+
+    /// The operating system.
+    os: &'static str,
+
+    /// The homeserver URL, with its domain redacted.
+    homeserver: String,
+
+    /// The versions of the Matrix spec supported by the homeserver.
+    server_versions: Vec<String>,
+
+    /// The number of rooms known by the client.
+    room_count: usize,
+
+    /// Whether cross-signing is set up for this session.
+    e2ee_bootstrapped: bool,
+
+    /// The most recent log lines.
+    recent_logs: Vec<String>,
+
+    /// When the session file was last modified, in seconds since the Unix epoch.
+    session_file_modified: Option<u64>,
+}
+
+/// Collect diagnostics about the client and write them to a report file in the
+/// data directory.
+///
+/// Returns the path of the report file.
+pub async fn generate_bug_report(client: &Client, config: &Config) -> anyhow::Result<PathBuf> {
+    let mut homeserver = client.homeserver();
+    // The domain can identify the user, it is not needed to debug the client.
+    if homeserver.host().is_some() {
+        homeserver.set_host(Some("redacted.invalid"))?;
+    }
+
+    let server_versions = match client.server_versions().await {
+        Ok(versions) => versions
+            .iter()
+            .filter_map(|version| version.as_str())
+            .map(ToOwned::to_owned)
+            .collect(),
+        Err(error) => vec![format!("Error getting the server versions: {error}")],
+    };
+
+    let e2ee_bootstrapped = client
+        .encryption()
+        .cross_signing_status()
+        .await
+        .is_some_and(|status| status.is_complete());
+
+    let session_file_modified = fs::metadata(&config.session_file)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs());
+
+    let recent_logs = RECENT_LOGS
+        .lock()
+        .expect("the log buffer lock is not poisoned")
+        .iter()
+        .cloned()
+        .collect();
+
+    let report = BugReport {
+        client_version: env!("CARGO_PKG_VERSION"),
+        sdk_version: env!("MATRIX_SDK_VERSION"),
+        os: std::env::consts::OS,
+        homeserver: homeserver.to_string(),
+        server_versions,
+        room_count: client.rooms().len(),
+        e2ee_bootstrapped,
+        recent_logs,
+        session_file_modified,
+    };
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let report_file = config.data_dir.join(format!("bug_report_{timestamp}.json"));
+    fs::write(&report_file, serde_json::to_string_pretty(&report)?).await?;
+
+    Ok(report_file)
+}
+
+/// Display statistics about the data accumulated by the client: the rooms, the
+/// servers of their members, the events and the size of the databases.
+///
+/// Only the events received since the client started are counted, the SDK
+/// doesn't keep the timelines in its store.
+pub async fn show_global_stats(
+    client: &Client,
+    config: &Config,
+    cache: &TimelineCache,
+) -> anyhow::Result<()> {
+    let joined_rooms = client.joined_rooms();
+
+    // Only the members already in the store are used, to avoid a request per
+    // room.
+    let mut servers = HashSet::new();
+    for room in &joined_rooms {
+        for member in room.members_no_sync(RoomMemberships::JOIN).await? {
+            servers.insert(member.user_id().server_name().to_owned());
+        }
+    }
+
+    let rows = [
+        ("Joined rooms", joined_rooms.len().to_string()),
+        ("Invited rooms", client.invited_rooms().len().to_string()),
+        ("Left rooms", client.left_rooms().len().to_string()),
+        ("Servers", servers.len().to_string()),
+        ("Cached events", cache.total_event_count().to_string()),
+        (
+            "Database size",
+            format_size(databases_size(&config.data_dir).await?),
+        ),
+    ];
+    for (label, value) in rows {
+        println!("{label:<20} {value}");
+    }
+
+    Ok(())
+}
+
+/// The total size of the databases in the data directory, in bytes.
+async fn databases_size(data_dir: &Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    let mut db_dirs = fs::read_dir(data_dir).await?;
+    while let Some(db_dir) = db_dirs.next_entry().await? {
+        if !db_dir.file_type().await?.is_dir() {
+            continue;
+        }
+
+        let mut files = fs::read_dir(db_dir.path()).await?;
+        while let Some(file) = files.next_entry().await? {
+            let metadata = file.metadata().await?;
+            if metadata.is_file() {
+                size += metadata.len();
+            }
+        }
+    }
+
+    Ok(size)
+}
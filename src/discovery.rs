@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use crossterm::style::Stylize;
+use matrix_sdk::{
+    config::RequestConfig,
+    ruma::{api::client::discovery::get_supported_versions, ServerName},
+    Client,
+};
+use serde::Deserialize;
+
+/// The unstable features used by the client, highlighted in the list of
+/// features of the homeserver.
+const RELEVANT_UNSTABLE_FEATURES: &[&str] = &[
+    // Sliding sync.
+    "org.matrix.msc3575",
+    // Application service ping.
+    "fi.mau.msc2659",
+    // Authenticated media.
+    "org.matrix.msc3916",
+];
+
+/// How long to wait for the answer of a server before giving up.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The response of a homeserver to `GET /_matrix/client/versions`.
+#[derive(Debug, Deserialize)]
+pub struct ServerHealth {
+    /// The versions of the client-server API supported by the server.
+    pub versions: Vec<String>,
+
+    /// The unstable features advertised by the server, and whether they are
+    /// enabled.
+    #[serde(default)]
+    pub unstable_features: BTreeMap<String, bool>,
+}
+
+impl ServerHealth {
+    /// Print the versions and unstable features of the server.
+    pub fn show(&self) {
+        println!("{:<20} {}", "Versions", self.versions.join(", "));
+
+        let enabled_features = self
+            .unstable_features
+            .iter()
+            .filter(|(_, enabled)| **enabled)
+            .map(|(feature, _)| feature.as_str())
+            .collect::<Vec<_>>();
+        if enabled_features.is_empty() {
+            println!("{:<20} none", "Unstable features");
+        } else {
+            println!(
+                "{:<20} {}",
+                "Unstable features",
+                enabled_features.join(", ")
+            );
+        }
+    }
+}
+
+/// Measure the round-trip time of a request to the homeserver.
+pub async fn ping_homeserver(client: &Client) -> anyhow::Result<Duration> {
+    let start = Instant::now();
+    client
+        .send(
+            get_supported_versions::Request::new(),
+            Some(RequestConfig::new().timeout(REQUEST_TIMEOUT)),
+        )
+        .await?;
+
+    Ok(start.elapsed())
+}
+
+/// Display the unstable features advertised by the homeserver and whether they
+/// are enabled.
+///
+/// The features used by the client are in bold.
+pub async fn list_unstable_features(client: &Client) -> anyhow::Result<()> {
+    let response = client
+        .send(get_supported_versions::Request::new(), None)
+        .await?;
+
+    if response.unstable_features.is_empty() {
+        println!("The homeserver doesn't advertise any unstable feature");
+        return Ok(());
+    }
+
+    println!("{:<50} Status", "Feature");
+    for (feature, enabled) in &response.unstable_features {
+        let row = format!(
+            "{feature:<50} {}",
+            if *enabled { "enabled" } else { "disabled" }
+        );
+        if RELEVANT_UNSTABLE_FEATURES.contains(&feature.as_str()) {
+            println!("{}", row.bold());
+        } else {
+            println!("{row}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the homeserver at the given URL answers to the client-server API,
+/// without logging in.
+pub async fn health_check(homeserver_url: &str) -> anyhow::Result<ServerHealth> {
+    let url = format!(
+        "{}/_matrix/client/versions",
+        homeserver_url.trim_end_matches('/')
+    );
+
+    let response = http_client()?.get(url).send().await?;
+    let status = response.status();
+    if status != reqwest::StatusCode::OK {
+        bail!("The homeserver answered with {status}");
+    }
+
+    Ok(serde_json::from_str(&response.text().await?)?)
+}
+
+/// Measure the round-trip time of a request to the federation API of the given
+/// server.
+///
+/// The request is sent directly to the server name, it doesn't follow a
+/// delegation to another host.
+pub async fn ping_federation_server(server: &ServerName) -> anyhow::Result<Duration> {
+    let url = format!("https://{server}/_matrix/federation/v1/version");
+
+    let start = Instant::now();
+    http_client()?.get(url).send().await?.error_for_status()?;
+
+    Ok(start.elapsed())
+}
+
+/// An HTTP client for the requests sent outside of the Matrix client, that
+/// gives up after [`REQUEST_TIMEOUT`].
+fn http_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()
+}
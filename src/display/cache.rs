@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use matrix_sdk::{
+    ruma::{OwnedUserId, UserId},
+    Room,
+};
+
+/// How long a display name is used before it is resolved again.
+const DISPLAY_NAME_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// A display name and when it must be resolved again.
+#[derive(Debug, Clone)]
+pub struct CachedName {
+    /// The display name, or the localpart of the user ID if the user doesn't
+    /// have one.
+    pub name: String,
+
+    /// When the name expires.
+    pub expires_at: Instant,
+}
+
+/// The display names of the senders, to avoid reading the state store for every
+/// event that is displayed.
+///
+/// It is shared between all the rooms, so a user has the same name everywhere.
+#[derive(Debug, Default)]
+pub struct DisplayNameCache {
+    names: DashMap<OwnedUserId, CachedName>,
+}
+
+impl DisplayNameCache {
+    /// Get the display name of the given user, resolving it from the members of
+    /// the room if it is not cached.
+    pub async fn get(&self, room: &Room, user_id: &UserId) -> String {
+        if let Some(cached) = self.names.get(user_id) {
+            if cached.expires_at > Instant::now() {
+                return cached.name.clone();
+            }
+        }
+
+        let name = match room.get_member_no_sync(user_id).await {
+            Ok(Some(member)) => member.name().to_owned(),
+            // Don't cache the fallback, the member might be loaded later.
+            _ => return user_id.to_string(),
+        };
+        self.insert(user_id, name.clone());
+
+        name
+    }
+
+    /// Update the display name of the given user, after it changed.
+    ///
+    /// The localpart of the user ID is used if the display name was removed.
+    pub fn update(&self, user_id: &UserId, display_name: Option<&str>) {
+        let name = display_name.unwrap_or(user_id.localpart()).to_owned();
+        self.insert(user_id, name);
+    }
+
+    fn insert(&self, user_id: &UserId, name: String) {
+        self.names.insert(
+            user_id.to_owned(),
+            CachedName {
+                name,
+                expires_at: Instant::now() + DISPLAY_NAME_TTL,
+            },
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+
+use matrix_sdk::ruma::{OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+
+/// Events hidden from the timeline by the client, based on their sender or
+/// type.
+///
+/// Unlike the server-side ignore list, this only affects the display of this
+/// client.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventFilter {
+    /// The users whose events are hidden.
+    pub blocked_senders: HashSet<OwnedUserId>,
+
+    /// The event types or message types whose events are hidden.
+    pub blocked_types: HashSet<String>,
+
+    /// The users on the server-side ignore list of the account.
+    ///
+    /// They are kept in sync with the account data, so the ignores added by
+    /// other sessions apply immediately.
+    #[serde(skip)]
+    pub ignored_users: HashSet<OwnedUserId>,
+}
+
+impl EventFilter {
+    /// Whether an event with the given sender and types should be hidden.
+    ///
+    /// `types` should contain the event type and, for messages, the message
+    /// type.
+    pub fn is_filtered(&self, sender: &UserId, types: &[&str]) -> bool {
+        self.blocked_senders.contains(sender)
+            || self.ignored_users.contains(sender)
+            || types
+                .iter()
+                .any(|event_type| self.blocked_types.contains(*event_type))
+    }
+
+    /// Display the active filters.
+    pub fn show(&self) {
+        if self.blocked_senders.is_empty() && self.blocked_types.is_empty() {
+            println!("No active filter");
+            return;
+        }
+
+        let mut senders: Vec<_> = self.blocked_senders.iter().map(|s| s.as_str()).collect();
+        senders.sort_unstable();
+        let mut types: Vec<_> = self.blocked_types.iter().map(String::as_str).collect();
+        types.sort_unstable();
+
+        println!("Filtered senders:  {}", senders.join(", "));
+        println!("Filtered types:    {}", types.join(", "));
+    }
+}
+
+/// Hide the messages that don't mention the current user.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MentionFilter {
+    /// Whether only the messages mentioning the user are displayed.
+    pub enabled: bool,
+
+    /// The number of messages hidden since the last displayed one.
+    #[serde(skip)]
+    pub hidden_count: usize,
+}
+
+impl MentionFilter {
+    /// Whether the given message body mentions the user with the given ID and
+    /// display name.
+    ///
+    /// The user ID and its localpart must match exactly, the display name is
+    /// case-insensitive. The localpart and the display name must be whole
+    /// words, so `al` doesn't match `also`.
+    pub fn is_mention(body: &str, user_id: &UserId, display_name: Option<&str>) -> bool {
+        body.contains(user_id.as_str())
+            || contains_word(body, user_id.localpart())
+            || display_name.is_some_and(|display_name| {
+                contains_word(&body.to_lowercase(), &display_name.to_lowercase())
+            })
+    }
+
+    /// Get the number of messages hidden since the last call and reset it.
+    pub fn take_hidden_count(&mut self) -> usize {
+        std::mem::take(&mut self.hidden_count)
+    }
+}
+
+/// Whether `word` appears in `text` without a letter, a digit or an underscore
+/// right before or after it.
+fn contains_word(text: &str, word: &str) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+
+    !word.is_empty()
+        && text.match_indices(word).any(|(start, _)| {
+            let before = text[..start].chars().next_back();
+            let after = text[start + word.len()..].chars().next();
+            !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::ruma::user_id;
+
+    use super::MentionFilter;
+
+    #[test]
+    fn mention_by_user_id() {
+        let user_id = user_id!("@al:example.org");
+        assert!(MentionFilter::is_mention(
+            "ping @al:example.org",
+            user_id,
+            None
+        ));
+    }
+
+    #[test]
+    fn mention_by_whole_localpart() {
+        let user_id = user_id!("@al:example.org");
+        assert!(MentionFilter::is_mention(
+            "al: are you there?",
+            user_id,
+            None
+        ));
+        assert!(MentionFilter::is_mention("thanks al", user_id, None));
+        assert!(!MentionFilter::is_mention("I also think so", user_id, None));
+        assert!(!MentionFilter::is_mention("a normal day", user_id, None));
+        assert!(!MentionFilter::is_mention("al_bot is down", user_id, None));
+    }
+
+    #[test]
+    fn mention_by_whole_display_name() {
+        let user_id = user_id!("@alice:example.org");
+        assert!(MentionFilter::is_mention(
+            "what do you think, ann lee?",
+            user_id,
+            Some("Ann Lee")
+        ));
+        assert!(!MentionFilter::is_mention(
+            "it's annually",
+            user_id,
+            Some("Ann")
+        ));
+    }
+}
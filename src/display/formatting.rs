@@ -0,0 +1,268 @@
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use clap::ValueEnum;
+use matrix_sdk::ruma::{
+    events::room::message::RoomMessageEventContent, MilliSecondsSinceUnixEpoch,
+};
+use pulldown_cmark::{escape::escape_html, html, Event, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use textwrap::{core::display_width, Options};
+
+/// The width used when the size of the terminal is unknown.
+const DEFAULT_TERMINAL_WIDTH: usize = 80;
+
+/// The width of the terminal, in columns.
+pub fn terminal_width() -> usize {
+    crossterm::terminal::size()
+        .map(|(columns, _)| usize::from(columns))
+        .unwrap_or(DEFAULT_TERMINAL_WIDTH)
+}
+
+/// Wrap the text to the given width.
+///
+/// Lines are only broken where the Unicode line breaking algorithm allows it,
+/// never in the middle of a character.
+pub fn wrap_text(text: &str, width: usize) -> String {
+    textwrap::fill(text, width)
+}
+
+/// Wrap a message to the width of the terminal.
+///
+/// The lines after the first one are indented to align under the start of the
+/// body, past the `prefix` with the sender.
+pub fn wrap_message(prefix: &str, body: &str) -> String {
+    // The prefix might be colored, the escape codes don't count in its width.
+    let indent = " ".repeat(display_width(prefix) + 1);
+    let options = Options::new(terminal_width()).subsequent_indent(&indent);
+
+    textwrap::fill(&format!("{prefix} {body}"), options)
+}
+
+/// Format the time of an event relatively to `now` if it is recent, like `3
+/// minutes ago`, or as an ISO 8601 date and time otherwise.
+///
+/// The date and time is in the local timezone, unless `utc` is set.
+pub fn format_timestamp(ts: MilliSecondsSinceUnixEpoch, now: SystemTime, utc: bool) -> String {
+    let Some(time) = ts.to_system_time() else {
+        return "unknown".to_owned();
+    };
+    // Events from the future are considered as just sent, the clocks might not be
+    // exactly in sync.
+    let elapsed = now.duration_since(time).unwrap_or_default();
+
+    let minutes = elapsed.as_secs() / 60;
+    let hours = minutes / 60;
+    if elapsed < Duration::from_secs(60) {
+        "just now".to_owned()
+    } else if hours == 0 {
+        format!("{minutes} {} ago", plural(minutes, "minute"))
+    } else if hours < 24 {
+        format!("{hours} {} ago", plural(hours, "hour"))
+    } else if utc {
+        DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+    } else {
+        DateTime::<Local>::from(time).to_rfc3339_opts(SecondsFormat::Secs, false)
+    }
+}
+
+/// The given unit, in the plural if needed.
+fn plural(count: u64, unit: &str) -> String {
+    if count == 1 {
+        unit.to_owned()
+    } else {
+        format!("{unit}s")
+    }
+}
+
+/// Format a size in bytes with the largest unit that keeps it above 1, like
+/// `1.5 MB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB", "TB"];
+
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if size < 1000.0 {
+            break;
+        }
+        size /= 1000.0;
+        unit = next_unit;
+    }
+
+    format!("{size:.1} {unit}")
+}
+
+/// How the text typed by the user is sent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageFormat {
+    /// Send the text as is.
+    #[default]
+    Plain,
+
+    /// Render the text as Markdown, and send the HTML along with the text.
+    Md,
+}
+
+impl MessageFormat {
+    /// The content of a text message with the given body in this format.
+    pub fn text(self, body: &str) -> RoomMessageEventContent {
+        match self {
+            MessageFormat::Plain => RoomMessageEventContent::text_plain(body),
+            MessageFormat::Md => match markdown_to_html(body) {
+                Some(html) => RoomMessageEventContent::text_html(body, html),
+                None => RoomMessageEventContent::text_plain(body),
+            },
+        }
+    }
+
+    /// The content of a notice with the given body in this format.
+    pub fn notice(self, body: &str) -> RoomMessageEventContent {
+        match self {
+            MessageFormat::Plain => RoomMessageEventContent::notice_plain(body),
+            MessageFormat::Md => match markdown_to_html(body) {
+                Some(html) => RoomMessageEventContent::notice_html(body, html),
+                None => RoomMessageEventContent::notice_plain(body),
+            },
+        }
+    }
+}
+
+/// The URL schemes allowed in the links of the messages, the others are
+/// dropped. These are the schemes allowed by the Matrix specification.
+const ALLOWED_LINK_SCHEMES: &[&str] = &["http", "https", "ftp", "mailto", "magnet", "matrix"];
+
+/// The URL schemes allowed in the sources of the images of the messages.
+const ALLOWED_IMAGE_SCHEMES: &[&str] = &["mxc"];
+
+/// Render Markdown text to HTML.
+///
+/// The HTML of the text is dropped rather than passed through, so a message
+/// can't contain arbitrary markup, and so are the links and the images with a
+/// scheme that isn't allowed, like `javascript:`, keeping their text.
+///
+/// Returns `None` if the text has no formatting, so it is sent as plain text
+/// only.
+pub fn markdown_to_html(text: &str) -> Option<String> {
+    let events = Parser::new(text).filter(|event| match event {
+        Event::Html(_) => false,
+        Event::Start(Tag::Link(_, url, _)) | Event::End(Tag::Link(_, url, _)) => {
+            is_allowed_url(url, ALLOWED_LINK_SCHEMES)
+        }
+        Event::Start(Tag::Image(_, url, _)) | Event::End(Tag::Image(_, url, _)) => {
+            is_allowed_url(url, ALLOWED_IMAGE_SCHEMES)
+        }
+        _ => true,
+    });
+
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, events);
+
+    // A text without formatting is rendered as a single paragraph.
+    let mut plain = String::from("<p>");
+    escape_html(&mut plain, text.trim()).ok()?;
+    plain.push_str("</p>\n");
+
+    (rendered != plain).then_some(rendered)
+}
+
+/// Whether the URL has one of the given schemes. URLs without a scheme, like
+/// `#section`, are relative and allowed.
+fn is_allowed_url(url: &str, allowed_schemes: &[&str]) -> bool {
+    // Browsers ignore the whitespaces and control characters in the scheme.
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect();
+    let Some((scheme, _)) = url.split_once(':') else {
+        return true;
+    };
+    let is_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if !is_scheme {
+        // The colon is in the path, like `./a:b`.
+        return true;
+    }
+
+    allowed_schemes
+        .iter()
+        .any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_size, markdown_to_html};
+
+    #[test]
+    fn format_size_bytes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(999), "999 B");
+    }
+
+    #[test]
+    fn format_size_units() {
+        assert_eq!(format_size(1000), "1.0 KB");
+        assert_eq!(format_size(1_500_000), "1.5 MB");
+        assert_eq!(format_size(2_000_000_000), "2.0 GB");
+        assert_eq!(format_size(3_000_000_000_000), "3.0 TB");
+    }
+
+    #[test]
+    fn format_size_largest_unit() {
+        assert_eq!(format_size(5_000_000_000_000_000), "5000.0 TB");
+    }
+
+    #[test]
+    fn markdown_without_formatting_is_plain() {
+        assert_eq!(markdown_to_html("hello"), None);
+        assert_eq!(markdown_to_html("a < b & c"), None);
+        assert_eq!(markdown_to_html("first line\nsecond line"), None);
+    }
+
+    #[test]
+    fn markdown_is_rendered() {
+        assert_eq!(
+            markdown_to_html("**bold** and `code`").as_deref(),
+            Some("<p><strong>bold</strong> and <code>code</code></p>\n")
+        );
+        assert_eq!(
+            markdown_to_html("[site](https://example.org)").as_deref(),
+            Some("<p><a href=\"https://example.org\">site</a></p>\n")
+        );
+    }
+
+    #[test]
+    fn markdown_html_is_dropped() {
+        assert_eq!(
+            markdown_to_html("*a* <script>alert(1)</script>").as_deref(),
+            Some("<p><em>a</em> alert(1)</p>\n")
+        );
+    }
+
+    #[test]
+    fn markdown_unsafe_links_are_dropped() {
+        assert_eq!(
+            markdown_to_html("*a* [click](javascript:alert(1))").as_deref(),
+            Some("<p><em>a</em> click</p>\n")
+        );
+        assert_eq!(
+            markdown_to_html("*a* [click](JavaScript:alert(1))").as_deref(),
+            Some("<p><em>a</em> click</p>\n")
+        );
+        assert_eq!(
+            markdown_to_html("*a* <javascript:alert(1)>").as_deref(),
+            Some("<p><em>a</em> javascript:alert(1)</p>\n")
+        );
+        assert_eq!(
+            markdown_to_html("*a* ![cat](https://example.org/cat.png)").as_deref(),
+            Some("<p><em>a</em> cat</p>\n")
+        );
+    }
+}
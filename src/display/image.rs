@@ -0,0 +1,121 @@
+use std::fmt;
+
+use anyhow::bail;
+use clap::ValueEnum;
+use crossterm::style::{Color, Stylize};
+use image::{imageops::FilterType, GenericImageView};
+use matrix_sdk::{
+    media::MediaThumbnailSize,
+    ruma::{
+        api::client::media::get_content_thumbnail::v3::Method,
+        events::room::message::ImageMessageEventContent, UInt,
+    },
+    Client,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::media::download_to_temp;
+
+/// The width of the images rendered in the terminal, in columns.
+const ASCII_IMAGE_WIDTH: u32 = 40;
+
+/// The size of the thumbnail requested to render images in the terminal.
+const THUMBNAIL_SIZE: u32 = 96;
+
+/// How the images of received messages are displayed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageMode {
+    /// Render a thumbnail in the terminal with block characters.
+    Ascii,
+
+    /// Open the image with the default image viewer of the system.
+    External,
+
+    /// Don't display images.
+    #[default]
+    Off,
+}
+
+impl fmt::Display for ImageMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self {
+            ImageMode::Ascii => "ascii",
+            ImageMode::External => "external",
+            ImageMode::Off => "off",
+        };
+        f.write_str(mode)
+    }
+}
+
+impl std::str::FromStr for ImageMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(ImageMode::Ascii),
+            "external" => Ok(ImageMode::External),
+            "off" => Ok(ImageMode::Off),
+            _ => bail!("Unknown image mode {s}, expected ascii, external or off"),
+        }
+    }
+}
+
+/// Display the image of a message with the given mode.
+pub async fn show_image(
+    client: &Client,
+    content: &ImageMessageEventContent,
+    mode: ImageMode,
+) -> anyhow::Result<()> {
+    match mode {
+        ImageMode::Ascii => {
+            let size = MediaThumbnailSize {
+                method: Method::Scale,
+                width: UInt::from(THUMBNAIL_SIZE),
+                height: UInt::from(THUMBNAIL_SIZE),
+            };
+            let Some(thumbnail) = client.media().get_thumbnail(content, size, true).await? else {
+                bail!("This image doesn't have a file");
+            };
+            println!("{}", render_blocks(&image::load_from_memory(&thumbnail)?));
+        }
+        ImageMode::External => {
+            let path = download_to_temp(client, content, &content.body).await?;
+            open::that(path)?;
+        }
+        ImageMode::Off => {}
+    }
+
+    Ok(())
+}
+
+/// Render an image with half-block characters: each character shows two
+/// pixels, the upper one with the foreground color and the lower one with the
+/// background color.
+fn render_blocks(image: &image::DynamicImage) -> String {
+    let (width, height) = image.dimensions();
+    let scaled_height = (ASCII_IMAGE_WIDTH * height / width.max(1)).max(2);
+    let image = image
+        .resize_exact(ASCII_IMAGE_WIDTH, scaled_height, FilterType::Triangle)
+        .to_rgb8();
+
+    let mut rendered = String::new();
+    for y in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let color = |y| {
+                let [r, g, b] = image.get_pixel(x, y).0;
+                Color::Rgb { r, g, b }
+            };
+            let upper = color(y);
+            let lower = if y + 1 < image.height() {
+                color(y + 1)
+            } else {
+                Color::Reset
+            };
+            rendered.push_str(&"▀".with(upper).on(lower).to_string());
+        }
+        rendered.push('\n');
+    }
+
+    rendered
+}
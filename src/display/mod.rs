@@ -0,0 +1,7 @@
+pub mod cache;
+pub mod filter;
+pub mod formatting;
+pub mod image;
+pub mod scroll;
+pub mod status;
+pub mod timeline;
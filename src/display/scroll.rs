@@ -0,0 +1,46 @@
+use std::{collections::HashMap, path::Path};
+
+use matrix_sdk::ruma::{EventId, OwnedEventId, OwnedRoomId, RoomId};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// The name of the file where the scroll state is persisted, in the data
+/// directory.
+pub const SCROLL_STATE_FILE_NAME: &str = "scroll_state.json";
+
+/// The last event read by the user in each room, to display the timeline from
+/// there when switching to the room again.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ScrollState {
+    last_read: HashMap<OwnedRoomId, OwnedEventId>,
+}
+
+impl ScrollState {
+    /// Load the scroll state from the given file.
+    ///
+    /// The state is empty if the file doesn't exist yet.
+    pub async fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_str(&fs::read_to_string(path).await?)?)
+    }
+
+    /// Write the scroll state to the given file.
+    pub async fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string(self)?).await?;
+
+        Ok(())
+    }
+
+    /// The last event read in the room.
+    pub fn last_read(&self, room_id: &RoomId) -> Option<&EventId> {
+        self.last_read.get(room_id).map(|event_id| &**event_id)
+    }
+
+    /// Set the last event read in the room.
+    pub fn set_last_read(&mut self, room_id: OwnedRoomId, event_id: OwnedEventId) {
+        self.last_read.insert(room_id, event_id);
+    }
+}
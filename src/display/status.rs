@@ -0,0 +1,365 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    mem,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime},
+};
+
+use crossterm::{
+    cursor::{MoveToColumn, MoveToPreviousLine},
+    execute,
+    terminal::{Clear, ClearType},
+    Command,
+};
+use matrix_sdk::ruma::{MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedUserId, RoomId, UserId};
+use rustyline::ExternalPrinter;
+use tokio::{task::JoinHandle, time};
+
+/// How long a user is considered active after sending a message.
+const ACTIVITY_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// The interval between two updates of the active users when no message is
+/// received, so the users who stopped talking are removed.
+const ACTIVE_USERS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The number of active users named in the status line, the others are only
+/// counted.
+const MAX_NAMED_ACTIVE_USERS: usize = 3;
+
+/// A countdown displayed while the sync waits before retrying, like
+/// `Connection lost. Retrying in 15s…`.
+///
+/// The line is updated every second by a separate task. Once the REPL gives
+/// it a printer, the line is printed above the input line, which is redrawn
+/// with what the user was typing. It can be cloned cheaply.
+#[derive(Clone, Default)]
+pub struct CountdownDisplay {
+    inner: Arc<Mutex<Countdown>>,
+}
+
+/// The state of [`CountdownDisplay`].
+#[derive(Default)]
+struct Countdown {
+    /// The printer of the REPL, writing above the input line.
+    printer: Option<Box<dyn ExternalPrinter + Send>>,
+
+    /// The task updating the countdown.
+    task: Option<JoinHandle<()>>,
+
+    /// Whether the status line is displayed.
+    shown: bool,
+}
+
+impl CountdownDisplay {
+    /// Print the status line with the given printer from now on, so it doesn't
+    /// overwrite the input line.
+    pub fn set_printer(&self, printer: impl ExternalPrinter + Send + 'static) {
+        self.lock().printer = Some(Box::new(printer));
+    }
+
+    /// Start counting down from the given delay, replacing the previous
+    /// countdown.
+    pub fn start(&self, delay: Duration) {
+        let display = self.clone();
+        let task = tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_secs(1));
+            for remaining in (1..=delay.as_secs()).rev() {
+                interval.tick().await;
+                display.show(&format!("Connection lost. Retrying in {remaining}s…"));
+            }
+        });
+
+        if let Some(previous) = self.lock().task.replace(task) {
+            previous.abort();
+        }
+    }
+
+    /// Stop the countdown and replace the status line with `Reconnected`.
+    pub fn stop(&self) {
+        let mut countdown = self.lock();
+        if let Some(task) = countdown.task.take() {
+            task.abort();
+        }
+        if countdown.shown {
+            let _ = countdown.print("Reconnected", true);
+        }
+    }
+
+    /// Replace the status line with the given one.
+    fn show(&self, status: &str) {
+        let _ = self.lock().print(status, false);
+    }
+
+    /// Lock the state of the display.
+    fn lock(&self) -> MutexGuard<'_, Countdown> {
+        self.inner
+            .lock()
+            .expect("the countdown lock is not poisoned")
+    }
+}
+
+impl Countdown {
+    /// Print the line in place of the status line if it is displayed. The
+    /// status line is removed after the last line.
+    ///
+    /// Without printer there is no input line yet, so the status is written on
+    /// the current line of the terminal.
+    fn print(&mut self, line: &str, last: bool) -> anyhow::Result<()> {
+        let replace = mem::replace(&mut self.shown, !last);
+        let Some(printer) = &mut self.printer else {
+            let mut stdout = io::stdout();
+            execute!(stdout, MoveToColumn(0), Clear(ClearType::CurrentLine))?;
+            write!(stdout, "{line}")?;
+            if last {
+                writeln!(stdout)?;
+            }
+            stdout.flush()?;
+            return Ok(());
+        };
+
+        // The printer clears the input line, prints the message on its own line
+        // and redraws the input line below it, so the status line is the one
+        // above.
+        let mut message = String::new();
+        if replace {
+            // Writing to a string can't fail.
+            let _ = MoveToPreviousLine(1).write_ansi(&mut message);
+            let _ = Clear(ClearType::CurrentLine).write_ansi(&mut message);
+        }
+        message.push_str(line);
+        printer.print(message)?;
+        Ok(())
+    }
+}
+
+/// A status line listing the users who sent a message in the current room in
+/// the last minutes, like `Active (5 min): Alice, Bob, +3 others`.
+///
+/// It doesn't need presence to be enabled on the homeserver. The line is
+/// printed when the room is opened and again when the active users change. It
+/// can be cloned cheaply.
+#[derive(Debug, Clone, Default)]
+pub struct ActiveUsersDisplay {
+    inner: Arc<Mutex<ActiveUsers>>,
+}
+
+/// The state of [`ActiveUsersDisplay`].
+#[derive(Debug, Default)]
+struct ActiveUsers {
+    /// The display name of the users and when they last sent a message, per
+    /// room.
+    rooms: HashMap<OwnedRoomId, HashMap<OwnedUserId, (String, SystemTime)>>,
+
+    /// The room whose active users are displayed.
+    current_room: Option<OwnedRoomId>,
+
+    /// The status line that was printed last.
+    last_status: Option<String>,
+}
+
+impl ActiveUsersDisplay {
+    /// Remember that the user sent a message in the room at the given time.
+    ///
+    /// Messages older than the activity window, like the ones received after
+    /// a reconnection, are ignored.
+    pub fn record(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        name: String,
+        sent_at: MilliSecondsSinceUnixEpoch,
+    ) {
+        let Some(sent_at) = sent_at.to_system_time() else {
+            return;
+        };
+        if !is_recent(sent_at) {
+            return;
+        }
+
+        {
+            let mut active_users = self.lock();
+            let users = active_users.rooms.entry(room_id.to_owned()).or_default();
+            // The messages are not always received in order.
+            let is_latest = users
+                .get(user_id)
+                .map_or(true, |(_, last_active)| *last_active <= sent_at);
+            if is_latest {
+                users.insert(user_id.to_owned(), (name, sent_at));
+            }
+        }
+        self.refresh();
+    }
+
+    /// Display the active users of the given room from now on.
+    pub fn set_current_room(&self, room_id: &RoomId) {
+        {
+            let mut active_users = self.lock();
+            active_users.current_room = Some(room_id.to_owned());
+            active_users.last_status = None;
+        }
+        self.refresh();
+    }
+
+    /// Print the status line if the active users of the current room changed
+    /// since it was last printed.
+    pub fn refresh(&self) {
+        let mut active_users = self.lock();
+        let ActiveUsers {
+            rooms,
+            current_room,
+            last_status,
+        } = &mut *active_users;
+        let Some(room_id) = current_room.clone() else {
+            return;
+        };
+        let users = rooms.entry(room_id).or_default();
+
+        users.retain(|_, (_, last_active)| is_recent(*last_active));
+        let status = active_users_status(users);
+        if last_status.as_ref() != Some(&status) {
+            println!("{status}");
+            *last_status = Some(status);
+        }
+    }
+
+    /// Refresh the status line regularly, until the returned task is aborted.
+    pub fn start_refresh_task(&self) -> JoinHandle<()> {
+        let display = self.clone();
+        tokio::spawn(async move {
+            let mut interval = time::interval(ACTIVE_USERS_REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                display.refresh();
+            }
+        })
+    }
+
+    /// Lock the state of the display.
+    fn lock(&self) -> MutexGuard<'_, ActiveUsers> {
+        self.inner
+            .lock()
+            .expect("the active users lock is not poisoned")
+    }
+}
+
+/// Whether a message sent at the given time is in the activity window.
+fn is_recent(sent_at: SystemTime) -> bool {
+    // Messages from the future are considered as just sent, the clocks might
+    // not be exactly in sync.
+    SystemTime::now()
+        .duration_since(sent_at)
+        .unwrap_or_default()
+        < ACTIVITY_WINDOW
+}
+
+/// The status line listing the given active users, by name.
+///
+/// The names are sorted so the line only changes when the active users do.
+fn active_users_status(users: &HashMap<OwnedUserId, (String, SystemTime)>) -> String {
+    let minutes = ACTIVITY_WINDOW.as_secs() / 60;
+    if users.is_empty() {
+        return format!("Active ({minutes} min): nobody");
+    }
+
+    let mut names: Vec<_> = users.values().map(|(name, _)| name.clone()).collect();
+    names.sort_unstable();
+    let others = names.len().saturating_sub(MAX_NAMED_ACTIVE_USERS);
+    names.truncate(MAX_NAMED_ACTIVE_USERS);
+    if others > 0 {
+        names.push(format!("+{others} others"));
+    }
+
+    format!("Active ({minutes} min): {}", names.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    use matrix_sdk::ruma::{room_id, user_id, MilliSecondsSinceUnixEpoch};
+    use rustyline::ExternalPrinter;
+
+    use super::{ActiveUsersDisplay, CountdownDisplay, ACTIVITY_WINDOW};
+
+    /// A printer keeping the printed messages.
+    #[derive(Clone, Default)]
+    struct RecordingPrinter(Arc<Mutex<Vec<String>>>);
+
+    impl ExternalPrinter for RecordingPrinter {
+        fn print(&mut self, msg: String) -> rustyline::Result<()> {
+            self.0.lock().unwrap().push(msg);
+            Ok(())
+        }
+    }
+
+    fn sent_ago(ago: Duration) -> MilliSecondsSinceUnixEpoch {
+        MilliSecondsSinceUnixEpoch::from_system_time(SystemTime::now() - ago).unwrap()
+    }
+
+    #[test]
+    fn old_messages_are_ignored() {
+        let display = ActiveUsersDisplay::default();
+        let room_id = room_id!("!room:example.org");
+        display.record(
+            room_id,
+            user_id!("@alice:example.org"),
+            "Alice".to_owned(),
+            sent_ago(ACTIVITY_WINDOW + Duration::from_secs(60)),
+        );
+        display.record(
+            room_id,
+            user_id!("@bob:example.org"),
+            "Bob".to_owned(),
+            sent_ago(Duration::from_secs(60)),
+        );
+
+        let active_users = display.lock();
+        let users = &active_users.rooms[room_id];
+        assert_eq!(users.len(), 1);
+        assert!(users.contains_key(user_id!("@bob:example.org")));
+    }
+
+    #[test]
+    fn latest_message_is_kept() {
+        let display = ActiveUsersDisplay::default();
+        let room_id = room_id!("!room:example.org");
+        let user_id = user_id!("@alice:example.org");
+        let latest = sent_ago(Duration::from_secs(10));
+        display.record(room_id, user_id, "Alice".to_owned(), latest);
+        display.record(
+            room_id,
+            user_id,
+            "Alice".to_owned(),
+            sent_ago(Duration::from_secs(120)),
+        );
+
+        let active_users = display.lock();
+        let (_, last_active) = &active_users.rooms[room_id][user_id];
+        assert_eq!(Some(*last_active), latest.to_system_time());
+    }
+
+    #[test]
+    fn countdown_replaces_the_status_line() {
+        let display = CountdownDisplay::default();
+        let printer = RecordingPrinter::default();
+        display.set_printer(printer.clone());
+
+        display.show("Connection lost. Retrying in 2s…");
+        display.show("Connection lost. Retrying in 1s…");
+        display.stop();
+        // The status line was already removed.
+        display.stop();
+
+        let messages = printer.0.lock().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0], "Connection lost. Retrying in 2s…");
+        assert!(messages[1].ends_with("Connection lost. Retrying in 1s…"));
+        assert_ne!(messages[1], "Connection lost. Retrying in 1s…");
+        assert!(messages[2].ends_with("Reconnected"));
+        assert_ne!(messages[2], "Reconnected");
+    }
+}
@@ -0,0 +1,682 @@
+use std::{
+    fmt,
+    io::{self, IsTerminal},
+    time::SystemTime,
+};
+
+use crossterm::style::{Color, Stylize};
+use matrix_sdk::{
+    deserialized_responses::EncryptionInfo,
+    room::MessagesOptions,
+    ruma::{
+        events::{
+            room::{
+                member::{MembershipChange, SyncRoomMemberEvent},
+                message::{
+                    AudioMessageEventContent, MessageType, Relation, VideoMessageEventContent,
+                },
+            },
+            sticker::StickerEventContent,
+            AnyMessageLikeEvent, AnyStateEvent, AnyTimelineEvent, MessageLikeEvent, StateEvent,
+        },
+        serde::Raw,
+        EventId, OwnedEventId, UInt, UserId,
+    },
+    Client, Room,
+};
+use serde::Deserialize;
+
+use crate::{
+    config::DisplayConfig,
+    display::cache::DisplayNameCache,
+    display::formatting::{format_size, format_timestamp, terminal_width, wrap_message, wrap_text},
+    media::voice::VoiceDetails,
+    rooms::export::body_summary,
+};
+
+/// The number of events displayed before the last read event of a room.
+const LAST_READ_CONTEXT_COUNT: usize = 3;
+
+/// The colors used for the names of the senders.
+const SENDER_COLORS: &[Color] = &[
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::DarkRed,
+    Color::DarkGreen,
+    Color::DarkYellow,
+    Color::DarkMagenta,
+    Color::DarkCyan,
+];
+
+/// The indentation of the body of replies.
+const REPLY_INDENT: &str = "    ";
+
+/// How much a message of an encrypted room can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityShield {
+    /// The room is not encrypted, or the message was sent by a verified
+    /// device.
+    None,
+
+    /// The message was decrypted but the device of the sender is not verified.
+    Grey,
+
+    /// The message was not encrypted although the room is.
+    Red,
+}
+
+impl fmt::Display for SecurityShield {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecurityShield::None => Ok(()),
+            SecurityShield::Grey => f.write_str("🔓 "),
+            SecurityShield::Red => f.write_str("🔴 "),
+        }
+    }
+}
+
+/// Compute the shield of a message of the room from the information about its
+/// decryption, which is `None` if the message was not encrypted.
+pub async fn compute_shield(
+    encryption_info: Option<&EncryptionInfo>,
+    room: &Room,
+    client: &Client,
+) -> SecurityShield {
+    if !room.is_encrypted().await.unwrap_or(false) {
+        return SecurityShield::None;
+    }
+    let Some(info) = encryption_info else {
+        return SecurityShield::Red;
+    };
+
+    match client
+        .encryption()
+        .get_device(&info.sender, &info.sender_device)
+        .await
+    {
+        Ok(Some(device)) if device.is_verified() => SecurityShield::None,
+        _ => SecurityShield::Grey,
+    }
+}
+
+/// An event with the aggregations bundled by the homeserver applied.
+#[derive(Debug)]
+pub struct RenderedEvent {
+    /// The body of the latest version of the event.
+    pub body: String,
+
+    /// Whether the event was edited.
+    pub edited: bool,
+
+    /// The reactions to the event, with their count.
+    pub reactions: Vec<(String, u64)>,
+
+    /// The number of replies in the thread started by the event, if any.
+    pub thread_replies: Option<UInt>,
+}
+
+impl RenderedEvent {
+    /// The markers of the aggregations to display after the body: whether the
+    /// event was edited, its reactions and its thread.
+    pub fn annotations(&self) -> String {
+        let mut annotations = String::new();
+        if self.edited {
+            annotations.push_str(" [edited]");
+        }
+
+        if !self.reactions.is_empty() {
+            let reactions: Vec<_> = self
+                .reactions
+                .iter()
+                .map(|(key, count)| format!("{key} {count}"))
+                .collect();
+            annotations.push_str(&format!("\n    {}", reactions.join("  ")));
+        }
+
+        if let Some(count) = self.thread_replies {
+            let plural = if count == UInt::from(1_u32) {
+                "reply"
+            } else {
+                "replies"
+            };
+            annotations.push_str(&format!("\n    ↳ {count} {plural}"));
+        }
+
+        annotations
+    }
+}
+
+impl fmt::Display for RenderedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.body, self.annotations())
+    }
+}
+
+/// A group of identical reactions bundled by the homeserver.
+#[derive(Debug, Deserialize)]
+struct AnnotationChunk {
+    key: String,
+    count: u64,
+}
+
+/// Apply the aggregations bundled with the event when it was received, to
+/// display its latest state without fetching its related events.
+///
+/// `raw` must be the raw JSON of `event`, because reactions are not part of the
+/// deserialized event.
+pub fn apply_aggregations(event: &AnyTimelineEvent, raw: &Raw<AnyTimelineEvent>) -> RenderedEvent {
+    let mut rendered = RenderedEvent {
+        body: body_summary(event),
+        edited: false,
+        reactions: Vec::new(),
+        thread_replies: None,
+    };
+
+    if let AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+        MessageLikeEvent::Original(event),
+    )) = event
+    {
+        let relations = &event.unsigned.relations;
+
+        if let Some(Relation::Replacement(replacement)) = relations
+            .replace
+            .as_ref()
+            .and_then(|edit| edit.content.relates_to.as_ref())
+        {
+            rendered.body = replacement.new_content.msgtype.body().to_owned();
+            rendered.edited = true;
+        }
+
+        rendered.thread_replies = relations.thread.as_ref().map(|thread| thread.count);
+    }
+
+    // Reactions are only bundled by some homeservers and are not supported by
+    // the event types.
+    if let Ok(Some(unsigned)) = raw.get_field::<serde_json::Value>("unsigned") {
+        if let Some(chunk) = unsigned.pointer("/m.relations/m.annotation/chunk") {
+            if let Ok(chunk) = Vec::<AnnotationChunk>::deserialize(chunk) {
+                rendered.reactions = chunk
+                    .into_iter()
+                    .map(|annotation| (annotation.key, annotation.count))
+                    .collect();
+            }
+        }
+    }
+
+    rendered
+}
+
+/// Replace the content of a message with the content of its edit bundled by the
+/// homeserver, if any.
+fn with_latest_content(mut event: AnyTimelineEvent) -> AnyTimelineEvent {
+    if let AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+        MessageLikeEvent::Original(event),
+    )) = &mut event
+    {
+        if let Some(Relation::Replacement(replacement)) = event
+            .unsigned
+            .relations
+            .replace
+            .as_ref()
+            .and_then(|edit| edit.content.relates_to.as_ref())
+        {
+            event.content.msgtype = replacement.new_content.msgtype.clone();
+        }
+    }
+
+    event
+}
+
+/// Render an event of the timeline on a single line, with its time, sender and
+/// a description of its content.
+///
+/// Replies are rendered on a second line, indented under the event they reply
+/// to.
+///
+/// The names of the senders are resolved with `names`.
+pub async fn render_timeline_event(
+    event: &AnyTimelineEvent,
+    raw: &Raw<AnyTimelineEvent>,
+    room: &Room,
+    config: &DisplayConfig,
+    names: &DisplayNameCache,
+) -> String {
+    let prefix = event_prefix(event, room, config, names).await;
+    render_with_prefix(event, raw, &prefix, config)
+}
+
+/// Render an event received during the sync like [`render_timeline_event()`],
+/// with the name of its room before the time.
+pub async fn render_live_event(
+    event: &AnyTimelineEvent,
+    raw: &Raw<AnyTimelineEvent>,
+    room: &Room,
+    room_name: &str,
+    config: &DisplayConfig,
+    names: &DisplayNameCache,
+) -> String {
+    let prefix = format!(
+        "[{room_name}] {}",
+        event_prefix(event, room, config, names).await
+    );
+    render_with_prefix(event, raw, &prefix, config)
+}
+
+/// Render an event after the given prefix, with a description of its content.
+fn render_with_prefix(
+    event: &AnyTimelineEvent,
+    raw: &Raw<AnyTimelineEvent>,
+    prefix: &str,
+    config: &DisplayConfig,
+) -> String {
+    let body = match event {
+        AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+            MessageLikeEvent::Original(event),
+        )) => {
+            let body = match &event.content.msgtype {
+                MessageType::Image(content) => format!("[image: {}]", content.body),
+                MessageType::File(content) => format!("[file: {}]", content.body),
+                MessageType::Video(content) => format_video(content),
+                MessageType::Audio(content) => match VoiceDetails::from_raw(raw) {
+                    Some(voice) => format_voice(content, &voice),
+                    None => format_audio(content),
+                },
+                // The sender is part of the prefix for emotes.
+                MessageType::Emote(content) => content.body.clone(),
+                msgtype => msgtype.body().to_owned(),
+            };
+
+            if let Some(Relation::Reply { in_reply_to }) = &event.content.relates_to {
+                let body = truncate_body(
+                    strip_reply_fallback(&body),
+                    &event.event_id,
+                    config.max_message_display_length,
+                );
+                let body = wrap_text(&body, terminal_width().saturating_sub(REPLY_INDENT.len()));
+                return format!(
+                    "{prefix} in reply to {}:\n{REPLY_INDENT}{}",
+                    in_reply_to.event_id,
+                    body.replace('\n', &format!("\n{REPLY_INDENT}"))
+                );
+            }
+            let body = truncate_body(&body, &event.event_id, config.max_message_display_length);
+            // Notices are styled after truncating, the escape codes must not be cut.
+            if matches!(event.content.msgtype, MessageType::Notice(_)) {
+                body.italic().grey().to_string()
+            } else {
+                body
+            }
+        }
+        AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::Sticker(
+            MessageLikeEvent::Original(event),
+        )) => format_sticker(&event.content),
+        AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::Reaction(
+            MessageLikeEvent::Original(event),
+        )) => format!(
+            "reacted with {} to {}",
+            event.content.relates_to.key, event.content.relates_to.event_id
+        ),
+        AnyTimelineEvent::State(AnyStateEvent::RoomMember(StateEvent::Original(event))) => {
+            match event.membership_change() {
+                MembershipChange::Joined | MembershipChange::InvitationAccepted => {
+                    "joined the room".to_owned()
+                }
+                MembershipChange::Left => "left the room".to_owned(),
+                MembershipChange::Invited => format!("invited {}", event.state_key),
+                MembershipChange::Kicked => format!("removed {}", event.state_key),
+                MembershipChange::Banned | MembershipChange::KickedAndBanned => {
+                    format!("banned {}", event.state_key)
+                }
+                MembershipChange::ProfileChanged { .. } => "changed their profile".to_owned(),
+                _ => format!("changed the membership of {}", event.state_key),
+            }
+        }
+        AnyTimelineEvent::State(AnyStateEvent::RoomTopic(StateEvent::Original(event))) => {
+            format!("changed the topic to: {}", event.content.topic)
+        }
+        AnyTimelineEvent::MessageLike(event) if event.original_content().is_none() => {
+            "(redacted)".to_owned()
+        }
+        event => format!("sent a {} event", event.event_type()),
+    };
+
+    wrap_message(prefix, &body)
+}
+
+/// Render an audio message with its file name, duration and size.
+fn format_audio(content: &AudioMessageEventContent) -> String {
+    let info = content.info.as_deref();
+    let details = [
+        info.and_then(|info| info.duration)
+            .map(|duration| format!("{}s", duration.as_secs())),
+        info.and_then(|info| info.size)
+            .map(|size| format_size(size.into())),
+    ];
+
+    format!("🎵 [audio] {}{}", content.body, format_details(&details))
+}
+
+/// Render a voice message with its duration and waveform.
+fn format_voice(content: &AudioMessageEventContent, voice: &VoiceDetails) -> String {
+    let duration = voice
+        .duration
+        .or_else(|| content.info.as_deref().and_then(|info| info.duration))
+        .map(|duration| format!("{}s", duration.as_secs()));
+
+    let mut rendered = format!("🎤 [voice]{}", format_details(&[duration]));
+    if !voice.waveform.is_empty() {
+        rendered.push(' ');
+        rendered.push_str(&voice.render_waveform());
+    }
+    rendered
+}
+
+/// Render a video message with its file name, duration, dimensions and size.
+fn format_video(content: &VideoMessageEventContent) -> String {
+    let info = content.info.as_deref();
+    let details = [
+        info.and_then(|info| info.duration)
+            .map(|duration| format!("{}s", duration.as_secs())),
+        info.and_then(|info| info.width.zip(info.height))
+            .map(|(width, height)| format!("{width}x{height}")),
+        info.and_then(|info| info.size)
+            .map(|size| format_size(size.into())),
+    ];
+
+    format!("🎬 [video] {}{}", content.body, format_details(&details))
+}
+
+/// The known details of a media, in parentheses.
+fn format_details(details: &[Option<String>]) -> String {
+    let details: Vec<_> = details.iter().flatten().map(String::as_str).collect();
+    if details.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", details.join(", "))
+    }
+}
+
+/// Render a sticker with its alt text, and its URI to open it elsewhere.
+fn format_sticker(content: &StickerEventContent) -> String {
+    format!(
+        "[sticker: {}] {}",
+        content.body.as_str().bold(),
+        content.url
+    )
+}
+
+/// Render a change of membership as a notice, like `→ Alice joined`.
+///
+/// Returns `None` for changes that are not worth a notice, like changes of
+/// profile.
+///
+/// The name of the sender is resolved with `names`.
+pub async fn format_member_event(
+    event: &SyncRoomMemberEvent,
+    room: &Room,
+    names: &DisplayNameCache,
+) -> Option<String> {
+    let SyncRoomMemberEvent::Original(event) = event else {
+        return None;
+    };
+
+    let target = event
+        .content
+        .displayname
+        .as_deref()
+        .or_else(|| {
+            event
+                .unsigned
+                .prev_content
+                .as_ref()
+                .and_then(|prev_content| prev_content.displayname.as_deref())
+        })
+        .unwrap_or(event.state_key.as_str())
+        .to_owned();
+    let sender = names.get(room, &event.sender).await;
+    let reason = event
+        .content
+        .reason
+        .as_ref()
+        .map(|reason| format!(" (reason: {reason})"))
+        .unwrap_or_default();
+
+    let notice = match event.membership_change() {
+        MembershipChange::Joined | MembershipChange::InvitationAccepted => {
+            format!("→ {target} joined").green()
+        }
+        MembershipChange::Left => format!("← {target} left").yellow(),
+        MembershipChange::Invited => format!("+ {target} was invited by {sender}").cyan(),
+        MembershipChange::Kicked => format!("⊘ {target} was kicked by {sender}{reason}").magenta(),
+        MembershipChange::Banned | MembershipChange::KickedAndBanned => {
+            format!("🚫 {target} was banned by {sender}{reason}").red()
+        }
+        MembershipChange::Unbanned => format!("{target} was unbanned by {sender}").blue(),
+        _ => return None,
+    };
+
+    Some(notice.to_string())
+}
+
+/// The time and sender of an event, colored if enabled and the output is a
+/// terminal.
+///
+/// Emotes are prefixed IRC-style, like `* Alice` instead of `Alice:`.
+async fn event_prefix(
+    event: &AnyTimelineEvent,
+    room: &Room,
+    config: &DisplayConfig,
+    names: &DisplayNameCache,
+) -> String {
+    let sender = event.sender();
+    let name = names.get(room, sender).await;
+    // The escape codes would end up in the output of pipes and redirections.
+    let name = if !config.colored_senders || !io::stdout().is_terminal() {
+        name
+    } else if sender == room.own_user_id() {
+        name.with(config.self_message_color).to_string()
+    } else {
+        name.with(sender_color(sender)).to_string()
+    };
+
+    let time = format_timestamp(
+        event.origin_server_ts(),
+        SystemTime::now(),
+        config.utc_timestamps,
+    );
+    let is_emote = matches!(
+        event,
+        AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+            MessageLikeEvent::Original(event),
+        )) if matches!(event.content.msgtype, MessageType::Emote(_))
+    );
+    if is_emote {
+        format!("{time} * {name}")
+    } else {
+        format!("{time} {name}:")
+    }
+}
+
+/// The color of the given sender.
+///
+/// It is derived from the user ID with a stable hash, so a user always gets the
+/// same color.
+fn sender_color(user_id: &UserId) -> Color {
+    // FNV-1a, the hasher of the standard library is not guaranteed to be stable.
+    let hash = user_id
+        .as_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+
+    SENDER_COLORS[(hash % SENDER_COLORS.len() as u64) as usize]
+}
+
+/// Truncate the body of the given event to `max_length` characters, with a hint
+/// to display the full body.
+///
+/// The body is not truncated if `max_length` is 0.
+fn truncate_body(body: &str, event_id: &EventId, max_length: usize) -> String {
+    match body.char_indices().nth(max_length) {
+        Some((end, _)) if max_length > 0 => {
+            format!("{}… [/expand {event_id}]", &body[..end])
+        }
+        _ => body.to_owned(),
+    }
+}
+
+/// Remove the quote of the original message that clients add at the start of
+/// the body of replies.
+fn strip_reply_fallback(body: &str) -> &str {
+    let mut rest = body;
+    while let Some(line) = rest.strip_prefix("> ") {
+        rest = line.split_once('\n').map_or("", |(_, rest)| rest);
+    }
+
+    match rest.trim_start_matches('\n') {
+        "" => body,
+        stripped => stripped,
+    }
+}
+
+/// Display the last events of the room, in chronological order.
+///
+/// If the `last_read` event is among them, the display starts a few events
+/// before it and a marker is shown after it.
+///
+/// The aggregations bundled with messages are applied. Returns the ID of the
+/// latest event.
+pub async fn show_last_messages(
+    room: &Room,
+    count: u32,
+    last_read: Option<&EventId>,
+    config: &DisplayConfig,
+    names: &DisplayNameCache,
+) -> anyhow::Result<Option<OwnedEventId>> {
+    let mut options = MessagesOptions::backward();
+    options.limit = count.into();
+    let messages = room.messages(options).await?;
+
+    let chunk: Vec<_> = messages.chunk.iter().rev().collect();
+    let last_read_index = last_read.and_then(|last_read| {
+        chunk
+            .iter()
+            .position(|event| event.event_id().as_deref() == Some(last_read))
+    });
+    let start = last_read_index.map_or(0, |index| index.saturating_sub(LAST_READ_CONTEXT_COUNT));
+
+    for (index, timeline_event) in chunk.iter().enumerate().skip(start) {
+        let Ok(event) = timeline_event.event.deserialize() else {
+            continue;
+        };
+
+        let rendered = apply_aggregations(&event, &timeline_event.event);
+        let shield = compute_shield(
+            timeline_event.encryption_info.as_ref(),
+            room,
+            &room.client(),
+        )
+        .await;
+        // Edits are rendered with their new content, like the other messages.
+        let event = with_latest_content(event);
+        println!(
+            "{shield}{}{}",
+            render_timeline_event(&event, &timeline_event.event, room, config, names).await,
+            rendered.annotations()
+        );
+
+        if Some(index) == last_read_index && index + 1 < chunk.len() {
+            println!("{}", "── last read ──".dark_grey());
+        }
+    }
+
+    Ok(chunk.last().and_then(|event| event.event_id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::ruma::{
+        event_id,
+        events::{AnyMessageLikeEvent, AnyTimelineEvent, MessageLikeEvent},
+    };
+    use serde_json::json;
+
+    use super::{truncate_body, with_latest_content};
+
+    #[test]
+    fn truncate_body_short() {
+        let event_id = event_id!("$event:example.org");
+        assert_eq!(truncate_body("hello", event_id, 5), "hello");
+        assert_eq!(truncate_body("", event_id, 5), "");
+    }
+
+    #[test]
+    fn truncate_body_long() {
+        let event_id = event_id!("$event:example.org");
+        assert_eq!(
+            truncate_body("hello world", event_id, 5),
+            "hello… [/expand $event:example.org]"
+        );
+    }
+
+    #[test]
+    fn truncate_body_multibyte() {
+        let event_id = event_id!("$event:example.org");
+        assert_eq!(
+            truncate_body("héllo wörld", event_id, 7),
+            "héllo w… [/expand $event:example.org]"
+        );
+    }
+
+    #[test]
+    fn truncate_body_disabled() {
+        let event_id = event_id!("$event:example.org");
+        let body = "a".repeat(1000);
+        assert_eq!(truncate_body(&body, event_id, 0), body);
+    }
+
+    #[test]
+    fn edited_message_has_the_latest_content() {
+        let event: AnyTimelineEvent = serde_json::from_value(json!({
+            "type": "m.room.message",
+            "event_id": "$original:example.org",
+            "room_id": "!room:example.org",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 1,
+            "content": { "msgtype": "m.notice", "body": "old" },
+            "unsigned": {
+                "m.relations": {
+                    "m.replace": {
+                        "type": "m.room.message",
+                        "event_id": "$edit:example.org",
+                        "sender": "@alice:example.org",
+                        "origin_server_ts": 2,
+                        "content": {
+                            "msgtype": "m.notice",
+                            "body": "* new",
+                            "m.new_content": { "msgtype": "m.notice", "body": "new" },
+                            "m.relates_to": {
+                                "rel_type": "m.replace",
+                                "event_id": "$original:example.org",
+                            },
+                        },
+                    },
+                },
+            },
+        }))
+        .unwrap();
+
+        let AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+            MessageLikeEvent::Original(event),
+        )) = with_latest_content(event)
+        else {
+            panic!("the event should still be a message");
+        };
+        assert_eq!(event.content.body(), "new");
+    }
+}
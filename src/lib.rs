@@ -0,0 +1,26 @@
+//! A Matrix client for the terminal.
+//!
+//! The binary runs the REPL, the modules are exposed so the benchmarks can
+//! drive the same code.
+
+pub mod bot;
+pub mod cli;
+pub mod config;
+pub mod crypto;
+pub mod detach;
+pub mod diagnostics;
+pub mod discovery;
+pub mod display;
+pub mod login;
+pub mod media;
+pub mod notifications;
+pub mod plugins;
+pub mod profile;
+pub mod repl;
+pub mod rooms;
+pub mod spaces;
+pub mod sync;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod ui_elements;
+pub mod utils;
@@ -1,21 +1,35 @@
 use std::fmt;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use matrix_sdk::{
     self,
-    ruma::api::client::session::get_login_types::v3::{IdentityProvider, LoginType},
-    Client,
+    ruma::{
+        api::client::session::get_login_types::v3::{IdentityProvider, LoginType},
+        MilliSecondsSinceUnixEpoch,
+    },
+    Client, SessionMeta,
 };
 
 /// The initial device name when logging in with a device for the first time.
 const INITIAL_DEVICE_DISPLAY_NAME: &str = "login client";
 
-use std::path::Path;
+/// The maximum number of SSO identity providers listed with the other login
+/// choices, more are listed in a separate menu.
+const MAX_LISTED_IDPS: usize = 5;
+
+use std::path::{Path, PathBuf};
 
+use reqwest::Url;
 use tokio::fs;
 
 use crate::{
-    login::persist_session::{build_client, FullSession},
+    config::{Config, SharedConfig},
+    login::{
+        login,
+        persist_session::{
+            build_client, is_bot_session, session_path, FullSession, SERVERS_DIR_NAME,
+        },
+    },
     ui_elements::{
         info_popup::{info_popup, Type},
         input_popup::input_popup,
@@ -45,6 +59,42 @@ impl LoginChoice {
     }
 }
 
+/// The login choices offered to the user.
+#[derive(Debug)]
+struct GroupedChoices {
+    /// The choices of the first menu.
+    choices: Vec<LoginChoice>,
+
+    /// The SSO identity providers offered in a separate menu, if there are too
+    /// many to list them with the other choices.
+    other_providers: Vec<LoginChoice>,
+}
+
+/// Move the SSO identity providers to a separate menu if there are more than
+/// [`MAX_LISTED_IDPS`].
+///
+/// They are kept in the first menu if there is no other choice, so the user
+/// doesn't have to go through a menu with a single entry.
+fn group_login_choices(choices: Vec<LoginChoice>) -> GroupedChoices {
+    let (providers, others): (Vec<_>, Vec<_>) = choices
+        .into_iter()
+        .partition(|choice| matches!(choice, LoginChoice::SsoIdp(_)));
+
+    if providers.len() <= MAX_LISTED_IDPS || others.is_empty() {
+        let mut choices = others;
+        choices.extend(providers);
+        GroupedChoices {
+            choices,
+            other_providers: Vec::new(),
+        }
+    } else {
+        GroupedChoices {
+            choices: others,
+            other_providers: providers,
+        }
+    }
+}
+
 impl fmt::Display for LoginChoice {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -56,13 +106,200 @@ impl fmt::Display for LoginChoice {
 }
 
 /// Log in to the given homeserver and sync.
-pub async fn login_new(data_dir: &Path, session_file: &Path) -> anyhow::Result<Client> {
-    let (client, client_session) = build_client(data_dir).await?;
+///
+/// If the config has a username and a password, they are used directly
+/// without any prompt.
+pub async fn login_new(
+    data_dir: &Path,
+    session_file: &Path,
+    config: &Config,
+) -> anyhow::Result<Client> {
+    let credentials = config.user.as_deref().zip(config.password.as_deref());
+    if credentials.is_some() && config.homeserver.is_none() {
+        bail!(
+            "The homeserver must be set with MATRIX_HOMESERVER or --homeserver to log in \
+             non-interactively"
+        );
+    }
+
+    let (client, client_session) = build_client(data_dir, config.homeserver.as_deref()).await?;
 
     let matrix_auth = client.matrix_auth();
+
+    if let Some((username, password)) = credentials {
+        login_noninteractive(&client, username, password).await?;
+    } else {
+        login_interactive(&client).await?;
+    }
+
+    // Persist the session to reuse it later.
+    // This is not very secure, for simplicity. If the system provides a way of
+    // storing secrets securely, it should be used instead.
+    // Note that we could also build the user session from the login response.
+    let user_session = matrix_auth
+        .session()
+        .expect("A logged-in client should have a session");
+    let serialized_session = serde_json::to_string(&FullSession {
+        client_session,
+        user_session,
+        sync_token: None,
+        created: Some(MilliSecondsSinceUnixEpoch::now()),
+    })?;
+    fs::write(session_file, serialized_session).await?;
+
+    println!("Session persisted in {}", session_file.to_string_lossy());
+
+    // After logging in, you might want to verify this session with another one (see
+    // the `emoji_verification` example), or bootstrap cross-signing if this is your
+    // first session with encryption, or if you need to reset cross-signing because
+    // you don't have access to your old sessions (see the
+    // `cross_signing_bootstrap` example).
+
+    Ok(client)
+}
+
+/// Log in to another homeserver, replacing the current client.
+///
+/// Each homeserver has its own data directory, named after the server, with its
+/// own session and database. The homeserver of the session persisted in
+/// `root_session_file` keeps using the root `data_dir`. The session is restored
+/// if the user already logged in to this homeserver, otherwise the login flow
+/// starts.
+///
+/// The data directory and the session file of the config are updated to the
+/// ones of the new homeserver.
+///
+/// The sync loop of the current client must be stopped before, so the client
+/// and its store are closed when it is dropped here.
+///
+/// Returns the new client, the sync token persisted with its session and its
+/// session file.
+pub async fn switch_homeserver(
+    current_client: Client,
+    new_homeserver: &str,
+    data_dir: &Path,
+    root_session_file: &Path,
+    config: &SharedConfig,
+) -> anyhow::Result<(Client, Option<String>, PathBuf)> {
+    let url = if new_homeserver.contains("://") {
+        Url::parse(new_homeserver)?
+    } else {
+        Url::parse(&format!("https://{new_homeserver}"))?
+    };
+    let server_name = url
+        .host_str()
+        .ok_or_else(|| anyhow!("{new_homeserver} is not a valid homeserver URL"))?;
+
+    drop(current_client);
+
+    let (server_dir, session_file) = if is_root_homeserver(root_session_file, server_name).await {
+        (data_dir.to_owned(), root_session_file.to_owned())
+    } else {
+        let server_dir = data_dir.join(SERVERS_DIR_NAME).join(server_name);
+        fs::create_dir_all(&server_dir).await?;
+        let session_file = session_path(&server_dir);
+        (server_dir, session_file)
+    };
+
+    // The credentials of the configuration are for the previous homeserver.
+    let mut server_config = config.read().await.clone();
+    server_config.homeserver = Some(url.to_string());
+    server_config.user = None;
+    server_config.password = None;
+
+    let (client, sync_token) = login(&server_dir, &session_file, &server_config).await?;
+    {
+        let mut config = config.write().await;
+        config.data_dir = server_dir;
+        config.session_file = session_file.clone();
+    }
+
+    Ok((client, sync_token, session_file))
+}
+
+/// Whether the session persisted in `root_session_file` is on the homeserver
+/// with the given server name.
+async fn is_root_homeserver(root_session_file: &Path, server_name: &str) -> bool {
+    let Ok(serialized_session) = fs::read_to_string(root_session_file).await else {
+        return false;
+    };
+    let Ok(FullSession { client_session, .. }) = serde_json::from_str(&serialized_session) else {
+        return false;
+    };
+
+    Url::parse(client_session.homeserver()).is_ok_and(|url| url.host_str() == Some(server_name))
+}
+
+/// Log in again with the device of the expired session persisted in
+/// `session_file`, so the encryption keys are kept, and update the session
+/// file.
+///
+/// The user is asked for their password, or goes through SSO if the
+/// homeserver doesn't support passwords. The session of the bot mode can't
+/// log in, its access token must be changed in the config.
+pub async fn relogin(client: &Client, session_file: &Path) -> anyhow::Result<()> {
+    if is_bot_session(session_file) {
+        bail!("The access token of the bot mode expired, update `bot.access_token` in the config");
+    }
+
+    let serialized_session = fs::read_to_string(session_file).await?;
+    let mut full_session: FullSession = serde_json::from_str(&serialized_session)?;
+    let SessionMeta { user_id, device_id } = &full_session.user_session.meta;
+
+    let login_types = client.matrix_auth().get_login_types().await?.flows;
+    if login_types
+        .iter()
+        .any(|login_type| matches!(login_type, LoginType::Password(_)))
+    {
+        let password = input_popup(
+            "Password:",
+            &format!("The session expired, logging in again as {user_id}…"),
+        )?;
+        client
+            .matrix_auth()
+            .login_username(user_id, password.trim())
+            .device_id(device_id.as_str())
+            .await?;
+    } else if login_types
+        .iter()
+        .any(|login_type| matches!(login_type, LoginType::Sso(_)))
+    {
+        println!("The session expired, logging in again as {user_id} with SSO…");
+        client
+            .matrix_auth()
+            .login_sso(|url| async move {
+                open::that(&url)?;
+
+                println!("\nOpen this URL in your browser: {url}\n");
+                println!("Waiting for login token…");
+                Ok(())
+            })
+            .device_id(device_id.as_str())
+            .send()
+            .await?;
+    } else {
+        bail!("Homeserver login types incompatible with this client");
+    }
+
+    full_session.user_session = client
+        .matrix_auth()
+        .session()
+        .ok_or_else(|| anyhow!("The client is not logged in"))?;
+    fs::write(session_file, serde_json::to_string(&full_session)?).await?;
+    println!(
+        "Logged in again as {}",
+        full_session.user_session.meta.user_id
+    );
+
+    Ok(())
+}
+
+/// Let the user choose among the login types supported by the homeserver and
+/// login with it.
+async fn login_interactive(client: &Client) -> anyhow::Result<()> {
     // First, let's figure out what login types are supported by the homeserver.
     let mut choices = Vec::new();
-    let login_types = matrix_auth.get_login_types().await?.flows;
+    let login_types = client.matrix_auth().get_login_types().await?.flows;
 
     for login_type in login_types {
         match login_type {
@@ -91,64 +328,85 @@ pub async fn login_new(data_dir: &Path, session_file: &Path) -> anyhow::Result<C
                 "Homeserver login types incompatible with this client"
             ))
         }
-        1 => choices[0].login(&client).await?,
-        _ => offer_choices_and_login(&client, choices).await?,
+        1 => choices[0].login(client).await?,
+        _ => offer_choices_and_login(client, group_login_choices(choices)).await?,
     }
 
-    // Persist the session to reuse it later.
-    // This is not very secure, for simplicity. If the system provides a way of
-    // storing secrets securely, it should be used instead.
-    // Note that we could also build the user session from the login response.
-    let user_session = matrix_auth
-        .session()
-        .expect("A logged-in client should have a session");
-    let serialized_session = serde_json::to_string(&FullSession {
-        client_session,
-        user_session,
-        sync_token: None,
-    })?;
-    fs::write(session_file, serialized_session).await?;
-
-    println!("Session persisted in {}", session_file.to_string_lossy());
+    Ok(())
+}
 
-    // After logging in, you might want to verify this session with another one (see
-    // the `emoji_verification` example), or bootstrap cross-signing if this is your
-    // first session with encryption, or if you need to reset cross-signing because
-    // you don't have access to your old sessions (see the
-    // `cross_signing_bootstrap` example).
+/// Log in with the given username and password, without any prompt.
+///
+/// Unlike the interactive login, this doesn't retry on failure.
+pub async fn login_noninteractive(
+    client: &Client,
+    username: &str,
+    password: &str,
+) -> anyhow::Result<()> {
+    client
+        .matrix_auth()
+        .login_username(username, password)
+        .initial_device_display_name(INITIAL_DEVICE_DISPLAY_NAME)
+        .await?;
+
+    println!("Logged in as {username}");
 
-    Ok(client)
+    Ok(())
 }
 
 /// Offer the given choices to the user and login with the selected option.
-async fn offer_choices_and_login(client: &Client, choices: Vec<LoginChoice>) -> anyhow::Result<()> {
-    let choice = loop {
-        let mut body = vec!["Several options are available to login with this homeserver:".into()];
-        for (idx, login_choice) in choices.iter().enumerate() {
-            body.push(format!("{idx}) {login_choice}"));
+async fn offer_choices_and_login(client: &Client, grouped: GroupedChoices) -> anyhow::Result<()> {
+    let mut labels: Vec<_> = grouped.choices.iter().map(ToString::to_string).collect();
+    if !grouped.other_providers.is_empty() {
+        labels.push("SSO via other provider…".to_owned());
+    }
+
+    let choice = select_choice(
+        "Several options are available to login with this homeserver:",
+        &labels,
+    )?;
+    if let Some(choice) = grouped.choices.get(choice) {
+        return choice.login(client).await;
+    }
+
+    let labels: Vec<_> = grouped
+        .other_providers
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    let choice = select_choice("Choose the SSO provider to login with:", &labels)?;
+    grouped.other_providers[choice].login(client).await
+}
+
+/// Ask the user to choose among the given labels until the answer is valid.
+///
+/// Returns the index of the chosen label.
+fn select_choice(intro: &str, labels: &[String]) -> anyhow::Result<usize> {
+    loop {
+        let mut body = vec![intro.to_owned()];
+        for (idx, label) in labels.iter().enumerate() {
+            body.push(format!("{idx}) {label}"));
         }
         let header = "Enter your choice:";
 
         let choice_str = input_popup(header, body.join("\n").as_str())?;
-        match choice_str.trim().parse::<usize>() {
-            Ok(choice) => {
-                if choice >= choices.len() {
-                    info_popup(Type::Error, "Error", "This is not a valid choice")?;
-                } else {
-                    break choice;
-                }
-            }
-            Err(_) => info_popup(
-                Type::Error,
-                "Error",
-                "This is not a valid choice. Try again.",
-            )?,
+        match parse_choice(&choice_str, labels.len()) {
+            Ok(choice) => return Ok(choice),
+            Err(message) => info_popup(Type::Error, "Error", message)?,
         };
-    };
-
-    choices[choice].login(client).await?;
+    }
+}
 
-    Ok(())
+/// Parse the index entered by the user when offered `choices_count` choices.
+///
+/// Returns the error message to display if the input is not a number or is out
+/// of range, so the caller can never index past the end of the choices.
+fn parse_choice(input: &str, choices_count: usize) -> Result<usize, &'static str> {
+    match input.trim().parse::<usize>() {
+        Ok(choice) if choice < choices_count => Ok(choice),
+        Ok(_) => Err("This is not a valid choice"),
+        Err(_) => Err("This is not a valid choice. Try again."),
+    }
 }
 
 /// Login with a username and password.
@@ -205,3 +463,173 @@ async fn login_with_sso(client: &Client, idp: Option<&IdentityProvider>) -> anyh
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use matrix_sdk::ruma::api::client::session::get_login_types::v3::IdentityProvider;
+    use proptest::prelude::*;
+    use tokio::sync::RwLock;
+
+    use super::{
+        group_login_choices, is_root_homeserver, login_new, parse_choice, switch_homeserver,
+        LoginChoice, MAX_LISTED_IDPS,
+    };
+    use crate::{
+        config::Config,
+        login::persist_session::{restore_session, session_path, SERVERS_DIR_NAME},
+        testing::mock_server::{MockServer, MOCK_DEVICE_ID, MOCK_USER_ID},
+    };
+
+    fn idp(name: &str) -> LoginChoice {
+        LoginChoice::SsoIdp(IdentityProvider::new(name.to_owned(), name.to_owned()))
+    }
+
+    /// A config logging in non-interactively on the mock homeserver.
+    fn mock_config(server: &MockServer, password: &str) -> Config {
+        Config {
+            homeserver: Some(server.uri()),
+            user: Some("alice".to_owned()),
+            password: Some(password.to_owned()),
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn login_with_password_on_mock_server() {
+        let server = MockServer::with_password_login("alice", "secret").await;
+        let data_dir = tempfile::tempdir().unwrap();
+        let session_file = session_path(data_dir.path());
+
+        let client = login_new(
+            data_dir.path(),
+            &session_file,
+            &mock_config(&server, "secret"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(client.user_id().unwrap(), MOCK_USER_ID);
+        assert_eq!(client.device_id().unwrap(), MOCK_DEVICE_ID);
+        drop(client);
+
+        // The persisted session is restored without logging in again.
+        let (client, sync_token) = restore_session(&session_file).await.unwrap();
+        assert_eq!(client.user_id().unwrap(), MOCK_USER_ID);
+        assert_eq!(sync_token, None);
+    }
+
+    #[tokio::test]
+    async fn switch_back_to_the_root_homeserver() {
+        let server = MockServer::with_password_login("alice", "secret").await;
+        let data_dir = tempfile::tempdir().unwrap();
+        let session_file = session_path(data_dir.path());
+        let client = login_new(
+            data_dir.path(),
+            &session_file,
+            &mock_config(&server, "secret"),
+        )
+        .await
+        .unwrap();
+        assert!(is_root_homeserver(&session_file, "127.0.0.1").await);
+        assert!(!is_root_homeserver(&session_file, "example.org").await);
+
+        // The config was left on another homeserver.
+        let server_dir = data_dir.path().join(SERVERS_DIR_NAME).join("example.org");
+        let config = Config {
+            session_file: session_path(&server_dir),
+            data_dir: server_dir,
+            ..Config::default()
+        };
+        let config = Arc::new(RwLock::new(config));
+
+        let (client, _, new_session_file) = switch_homeserver(
+            client,
+            &server.uri(),
+            data_dir.path(),
+            &session_file,
+            &config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(client.user_id().unwrap(), MOCK_USER_ID);
+        assert_eq!(new_session_file, session_file);
+        let config = config.read().await;
+        assert_eq!(config.data_dir, data_dir.path());
+        assert_eq!(config.session_file, session_file);
+    }
+
+    #[tokio::test]
+    async fn login_with_wrong_password_on_mock_server() {
+        let server = MockServer::with_password_login("alice", "secret").await;
+        let data_dir = tempfile::tempdir().unwrap();
+        let session_file = session_path(data_dir.path());
+
+        let result = login_new(
+            data_dir.path(),
+            &session_file,
+            &mock_config(&server, "wrong"),
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(!session_file.exists());
+    }
+
+    #[tokio::test]
+    async fn sync_on_mock_server() {
+        let server = MockServer::with_password_login("alice", "secret").await;
+        let client = server.logged_in_client().await.unwrap();
+
+        let response = client.sync_once(Default::default()).await.unwrap();
+        assert_eq!(response.next_batch, "s1_mock");
+    }
+
+    #[test]
+    fn display_login_choices() {
+        assert_eq!(LoginChoice::Password.to_string(), "Username and password");
+        assert_eq!(LoginChoice::Sso.to_string(), "SSO");
+        assert_eq!(idp("GitHub").to_string(), "SSO via GitHub");
+    }
+
+    proptest! {
+        #[test]
+        fn display_idp_with_any_name(name in any::<String>()) {
+            prop_assert_eq!(idp(&name).to_string(), format!("SSO via {name}"));
+        }
+
+        #[test]
+        fn parse_choice_in_range(choices_count in 1..100usize, choice in 0..200usize) {
+            let parsed = parse_choice(&format!(" {choice}\n"), choices_count);
+            if choice < choices_count {
+                prop_assert_eq!(parsed, Ok(choice));
+            } else {
+                prop_assert!(parsed.is_err());
+            }
+        }
+
+        #[test]
+        fn parse_choice_any_input(input in any::<String>(), choices_count in 0..100usize) {
+            if let Ok(choice) = parse_choice(&input, choices_count) {
+                prop_assert!(choice < choices_count);
+            }
+        }
+
+        #[test]
+        fn group_keeps_every_choice(idps_count in 0..20usize, with_password in any::<bool>()) {
+            let mut choices: Vec<_> = (0..idps_count).map(|i| idp(&i.to_string())).collect();
+            if with_password {
+                choices.insert(0, LoginChoice::Password);
+            }
+            let total = choices.len();
+
+            let grouped = group_login_choices(choices);
+            prop_assert_eq!(grouped.choices.len() + grouped.other_providers.len(), total);
+            if grouped.other_providers.is_empty() {
+                prop_assert!(idps_count <= MAX_LISTED_IDPS || !with_password);
+            } else {
+                prop_assert_eq!(grouped.other_providers.len(), idps_count);
+                prop_assert!(matches!(grouped.choices[..], [LoginChoice::Password]));
+            }
+        }
+    }
+}
@@ -1,13 +1,55 @@
-use matrix_sdk::{self, Client};
+use anyhow::{anyhow, Context};
+use matrix_sdk::{
+    self,
+    ruma::{api::client::uiaa, MilliSecondsSinceUnixEpoch, OwnedDeviceId},
+    Client,
+};
 
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
 
-use matrix_sdk::matrix_auth::MatrixSession;
+use matrix_sdk::{
+    matrix_auth::{MatrixSession, MatrixSessionTokens},
+    SessionMeta,
+};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::{Deserialize, Serialize};
-use tokio::fs;
+use tokio::{fs, sync::Notify};
+use tracing::warn;
 
-use crate::ui_elements::input_popup::input_popup;
+use crate::{config::BotConfig, ui_elements::input_popup::input_popup};
+
+/// The name of the folder containing the data of the client, in the XDG data
+/// directory.
+const APP_DIR_NAME: &str = "matrix-client";
+
+/// The name of the session file, in the data directory.
+const SESSION_FILE_NAME: &str = "session";
+
+/// The name of the session file of the bot mode, in the data directory.
+const BOT_SESSION_FILE_NAME: &str = "bot_session";
+
+/// The name of the folder containing the data of the other homeservers than the
+/// one of the main session, in the data directory.
+pub(crate) const SERVERS_DIR_NAME: &str = "servers";
+
+/// The name of the database folder of the bot mode, in the data directory.
+const BOT_DB_DIR_NAME: &str = "bot";
+
+/// The prefix of the files of the SQLite state store, in a database folder.
+///
+/// The crypto store lives in the same folder and must be kept, or the
+/// encryption setup would be lost.
+const STATE_STORE_FILE_PREFIX: &str = "matrix-sdk-state";
+
+/// The name of the file of the SQLite state store, in a database folder.
+const STATE_STORE_FILE_NAME: &str = "matrix-sdk-state.sqlite3";
+
+/// The name of the file marking a data directory whose state stores must be
+/// deleted before they are opened again.
+const CLEAR_CACHE_MARKER_FILE_NAME: &str = "clear-cache";
 
 /// The data needed to re-build a client.
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,8 +60,16 @@ pub struct ClientSession {
     /// The path of the database.
     db_path: PathBuf,
 
-    /// The passphrase of the database.
-    passphrase: String,
+    /// The passphrase of the database, if it is encrypted.
+    #[serde(default)]
+    passphrase: Option<String>,
+}
+
+impl ClientSession {
+    /// The URL of the homeserver of the user.
+    pub fn homeserver(&self) -> &str {
+        &self.homeserver
+    }
 }
 
 /// The full session to persist.
@@ -38,6 +88,93 @@ pub struct FullSession {
     /// again.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sync_token: Option<String>,
+
+    /// When the session was created.
+    ///
+    /// Sessions persisted by older versions of the client don't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+/// The default folder containing the data of the client.
+///
+/// This follows the XDG Base Directory specification: it is
+/// `$XDG_DATA_HOME/matrix-client/`, or `~/.local/share/matrix-client/` if
+/// `XDG_DATA_HOME` is not set.
+pub fn default_data_dir() -> PathBuf {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        // The spec says relative paths are invalid and should be ignored.
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| {
+            dirs::home_dir()
+                .expect("no home directory found")
+                .join(".local/share")
+        });
+
+    data_home.join(APP_DIR_NAME)
+}
+
+/// The folder containing the data of the versions of the client that didn't
+/// follow the XDG Base Directory specification, in the platform data directory.
+const LEGACY_DATA_DIR_NAME: &str = "persist_session";
+
+/// Move the data of a version of the client that didn't follow the XDG Base
+/// Directory specification to the given default data directory, so its session
+/// is kept.
+///
+/// Nothing is moved if the data directory already exists.
+pub async fn migrate_legacy_data_dir(data_dir: &Path) -> anyhow::Result<()> {
+    let Some(legacy_dir) = dirs::data_dir()
+        .map(|dir| dir.join(LEGACY_DATA_DIR_NAME))
+        .filter(|dir| dir.is_dir())
+    else {
+        return Ok(());
+    };
+    if data_dir.exists() {
+        warn!(
+            "The data of a previous version in {} is not used, move it to {} to use it",
+            legacy_dir.display(),
+            data_dir.display()
+        );
+        return Ok(());
+    }
+
+    if let Some(parent) = data_dir.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::rename(&legacy_dir, data_dir).await.with_context(|| {
+        format!(
+            "Could not move the data of a previous version from {} to {}, move it manually",
+            legacy_dir.display(),
+            data_dir.display()
+        )
+    })?;
+
+    // The session contains the path of the database.
+    let session_file = session_path(data_dir);
+    if session_file.exists() {
+        let mut full_session: FullSession =
+            serde_json::from_str(&fs::read_to_string(&session_file).await?)?;
+        let db_path = &full_session.client_session.db_path;
+        if let Ok(relative_path) = db_path.strip_prefix(&legacy_dir) {
+            full_session.client_session.db_path = data_dir.join(relative_path);
+            fs::write(&session_file, serde_json::to_string(&full_session)?).await?;
+        }
+    }
+
+    println!(
+        "Moved the data of the previous version from {} to {}",
+        legacy_dir.display(),
+        data_dir.display()
+    );
+
+    Ok(())
+}
+
+/// The file where the session is persisted in the given data directory.
+pub fn session_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(SESSION_FILE_NAME)
 }
 
 /// Restore a previous session.
@@ -53,12 +190,13 @@ pub async fn restore_session(session_file: &Path) -> anyhow::Result<(Client, Opt
         client_session,
         user_session,
         sync_token,
+        ..
     } = serde_json::from_str(&serialized_session)?;
 
     // Build the client with the previous settings from the session.
     let client = Client::builder()
         .homeserver_url(client_session.homeserver)
-        .sqlite_store(client_session.db_path, Some(&client_session.passphrase))
+        .sqlite_store(client_session.db_path, client_session.passphrase.as_deref())
         .build()
         .await?;
 
@@ -70,8 +208,358 @@ pub async fn restore_session(session_file: &Path) -> anyhow::Result<(Client, Opt
     Ok((client, sync_token))
 }
 
+/// Resume the session after the homeserver returned a soft logout, by
+/// restoring the session persisted in `session_file` in case its tokens are
+/// still valid.
+///
+/// Returns `false` if the user must log in again, with [`relogin()`].
+///
+/// [`relogin()`]: super::relogin
+pub async fn recover_soft_logout(client: &Client, session_file: &Path) -> anyhow::Result<bool> {
+    let serialized_session = fs::read_to_string(session_file).await?;
+    let full_session: FullSession = serde_json::from_str(&serialized_session)?;
+
+    let restored = client
+        .matrix_auth()
+        .restore_session(full_session.user_session)
+        .await
+        .is_ok();
+    if restored && client.whoami().await.is_ok() {
+        println!("Session restored");
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// The session whose access token expired, waiting for the user to log in
+/// again with `/relogin`.
+///
+/// The sync can't ask for the credentials, so it stops until the REPL logs in
+/// again. It can be cloned cheaply.
+#[derive(Debug, Clone, Default)]
+pub struct ExpiredSession {
+    inner: Arc<ExpiredSessionInner>,
+}
+
+#[derive(Debug, Default)]
+struct ExpiredSessionInner {
+    /// The session file of the expired session.
+    session_file: Mutex<Option<PathBuf>>,
+
+    /// Notified when the user logged in again.
+    renewed: Notify,
+}
+
+impl ExpiredSession {
+    /// Mark the session persisted in `session_file` as expired and wait until
+    /// the user logs in again.
+    pub async fn wait_for_relogin(&self, session_file: &Path) {
+        *self.lock() = Some(session_file.to_owned());
+        println!("The session expired, type /relogin to log in again");
+        self.inner.renewed.notified().await;
+    }
+
+    /// The session file of the expired session, if the session expired.
+    pub fn session_file(&self) -> Option<PathBuf> {
+        self.lock().clone()
+    }
+
+    /// Resume the sync after the user logged in again.
+    pub fn renewed(&self) {
+        *self.lock() = None;
+        self.inner.renewed.notify_one();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<PathBuf>> {
+        self.inner
+            .session_file
+            .lock()
+            .expect("the expired session lock is not poisoned")
+    }
+}
+
+/// Delete the SQLite state stores of the data directory before a client opens
+/// them again, with [`clear_scheduled_state_cache()`].
+///
+/// The stores can't be deleted while a client uses them, the client should
+/// exit after this.
+pub async fn schedule_state_cache_clear(data_dir: &Path) -> anyhow::Result<()> {
+    fs::write(data_dir.join(CLEAR_CACHE_MARKER_FILE_NAME), b"").await?;
+
+    Ok(())
+}
+
+/// Delete the SQLite state stores of the data directory, including the ones of
+/// the other homeservers, and forget the sync token of their sessions, if it
+/// was scheduled with [`schedule_state_cache_clear()`].
+///
+/// The room state, members and timeline events will be fetched again from the
+/// homeserver. This must be called before a client opens the stores.
+pub async fn clear_scheduled_state_cache(
+    data_dir: &Path,
+    session_file: &Path,
+) -> anyhow::Result<()> {
+    let marker = data_dir.join(CLEAR_CACHE_MARKER_FILE_NAME);
+    if !marker.exists() {
+        return Ok(());
+    }
+
+    clear_state_cache(data_dir).await?;
+    forget_sync_token(session_file).await?;
+    for server_dir in server_data_dirs(data_dir).await? {
+        forget_sync_token(&session_path(&server_dir)).await?;
+    }
+    fs::remove_file(marker).await?;
+    println!("State cache cleared");
+
+    Ok(())
+}
+
+/// Delete the SQLite state stores of all the databases in the data directory,
+/// including the ones of the other homeservers, except the one of the bot mode.
+async fn clear_state_cache(data_dir: &Path) -> anyhow::Result<()> {
+    let bot_db_dir = data_dir.join(BOT_DB_DIR_NAME);
+    for db_dir in database_dirs(data_dir).await? {
+        if db_dir == bot_db_dir {
+            continue;
+        }
+
+        let mut files = fs::read_dir(db_dir).await?;
+        while let Some(file) = files.next_entry().await? {
+            if file
+                .file_name()
+                .to_string_lossy()
+                .starts_with(STATE_STORE_FILE_PREFIX)
+            {
+                fs::remove_file(file.path()).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The database folders in the data directory, and in the data directories of
+/// the other homeservers in [`SERVERS_DIR_NAME`].
+async fn database_dirs(data_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let servers_dir = data_dir.join(SERVERS_DIR_NAME);
+    let mut data_dirs = vec![data_dir.to_owned()];
+    data_dirs.extend(server_data_dirs(data_dir).await?);
+
+    let mut db_dirs = Vec::new();
+    for data_dir in data_dirs {
+        db_dirs.extend(
+            sub_dirs(&data_dir)
+                .await?
+                .into_iter()
+                .filter(|dir| *dir != servers_dir),
+        );
+    }
+
+    Ok(db_dirs)
+}
+
+/// The data directories of the other homeservers, in [`SERVERS_DIR_NAME`].
+async fn server_data_dirs(data_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let servers_dir = data_dir.join(SERVERS_DIR_NAME);
+    if !servers_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    sub_dirs(&servers_dir).await
+}
+
+/// The folders directly in the given folder.
+async fn sub_dirs(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+
+    Ok(dirs)
+}
+
+/// Compact the SQLite state stores of all the databases in the data directory,
+/// including the ones of the other homeservers.
+///
+/// Returns the number of bytes freed on disk.
+pub async fn compact_state_store(data_dir: &Path) -> anyhow::Result<u64> {
+    let mut freed = 0;
+    for db_dir in database_dirs(data_dir).await? {
+        let db_file = db_dir.join(STATE_STORE_FILE_NAME);
+        if !db_file.exists() {
+            continue;
+        }
+
+        let size_before = state_store_size(&db_file).await?;
+        let connection_file = db_file.clone();
+        tokio::task::spawn_blocking(move || -> rusqlite::Result<()> {
+            let connection = rusqlite::Connection::open(connection_file)?;
+            connection.execute_batch("PRAGMA wal_checkpoint(FULL); VACUUM;")
+        })
+        .await??;
+        freed += size_before.saturating_sub(state_store_size(&db_file).await?);
+    }
+
+    Ok(freed)
+}
+
+/// The size of the SQLite database, including its write-ahead log.
+async fn state_store_size(db_file: &Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = db_file.as_os_str().to_owned();
+        file.push(suffix);
+        if let Ok(metadata) = fs::metadata(&file).await {
+            size += metadata.len();
+        }
+    }
+
+    Ok(size)
+}
+
+/// Forget the sync token persisted in the session file, so the next sync starts
+/// from scratch.
+async fn forget_sync_token(session_file: &Path) -> anyhow::Result<()> {
+    if !session_file.exists() {
+        return Ok(());
+    }
+
+    let serialized_session = fs::read_to_string(session_file).await?;
+    let mut full_session: FullSession = serde_json::from_str(&serialized_session)?;
+
+    full_session.sync_token = None;
+    fs::write(session_file, serde_json::to_string(&full_session)?).await?;
+
+    Ok(())
+}
+
+/// The file where the session of the bot mode is persisted in the given data
+/// directory.
+pub fn bot_session_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(BOT_SESSION_FILE_NAME)
+}
+
+/// Whether `session_file` is the session of the bot mode, that can't log in.
+pub fn is_bot_session(session_file: &Path) -> bool {
+    session_file.file_name() == Some(BOT_SESSION_FILE_NAME.as_ref())
+}
+
+/// Restore the session of an application service account from the bot
+/// settings, without going through the login flow.
+///
+/// The session is persisted in `session_file` so the sync token can be saved
+/// like for a normal session.
+pub async fn restore_bot_session(
+    bot: &BotConfig,
+    data_dir: &Path,
+    session_file: &Path,
+) -> anyhow::Result<Client> {
+    let missing =
+        |field: &str| anyhow!("`bot.{field}` must be set in the config to use the bot mode");
+    let homeserver = bot
+        .homeserver
+        .clone()
+        .ok_or_else(|| missing("homeserver"))?;
+    let user_id = bot.user_id.clone().ok_or_else(|| missing("user_id"))?;
+    let device_id = bot.device_id.clone().ok_or_else(|| missing("device_id"))?;
+    let access_token = bot
+        .access_token
+        .clone()
+        .ok_or_else(|| missing("access_token"))?;
+
+    println!("Restoring bot session for {user_id}…");
+
+    let client_session = ClientSession {
+        homeserver,
+        db_path: data_dir.join(BOT_DB_DIR_NAME),
+        passphrase: None,
+    };
+    let client = Client::builder()
+        .homeserver_url(&client_session.homeserver)
+        .sqlite_store(
+            &client_session.db_path,
+            client_session.passphrase.as_deref(),
+        )
+        .build()
+        .await?;
+
+    let user_session = MatrixSession {
+        meta: SessionMeta { user_id, device_id },
+        tokens: MatrixSessionTokens {
+            access_token,
+            refresh_token: None,
+        },
+    };
+    client.restore_session(user_session.clone()).await?;
+
+    let serialized_session = serde_json::to_string(&FullSession {
+        client_session,
+        user_session,
+        sync_token: None,
+        created: Some(MilliSecondsSinceUnixEpoch::now()),
+    })?;
+    fs::write(session_file, serialized_session).await?;
+
+    Ok(client)
+}
+
+/// Log out all the sessions of the user except the current one.
+///
+/// The homeserver requires the password of the user to delete devices, it is
+/// asked only if needed. Returns the IDs of the devices that were logged out.
+pub async fn logout_all_other_sessions(client: &Client) -> anyhow::Result<Vec<OwnedDeviceId>> {
+    let user_id = client
+        .user_id()
+        .ok_or_else(|| anyhow!("The client is not logged in"))?;
+    let current_device_id = client.device_id();
+
+    let devices: Vec<OwnedDeviceId> = client
+        .devices()
+        .await?
+        .devices
+        .into_iter()
+        .map(|device| device.device_id)
+        .filter(|device_id| Some(&**device_id) != current_device_id)
+        .collect();
+    if devices.is_empty() {
+        return Ok(devices);
+    }
+
+    if let Err(error) = client.delete_devices(&devices, None).await {
+        let Some(uiaa_info) = error.as_uiaa_response() else {
+            return Err(error.into());
+        };
+
+        let password = input_popup(
+            "Password:",
+            "Confirm your identity to log out the other sessions",
+        )?;
+        let mut auth = uiaa::Password::new(
+            uiaa::UserIdentifier::UserIdOrLocalpart(user_id.to_string()),
+            password,
+        );
+        auth.session = uiaa_info.session.clone();
+        client
+            .delete_devices(&devices, Some(uiaa::AuthData::Password(auth)))
+            .await?;
+    }
+
+    Ok(devices)
+}
+
 /// Build a new client.
-pub async fn build_client(data_dir: &Path) -> anyhow::Result<(Client, ClientSession)> {
+///
+/// The user is asked for the URL of the homeserver, unless `homeserver` is set.
+/// In that case, errors are returned instead of asking again.
+pub async fn build_client(
+    data_dir: &Path,
+    homeserver: Option<&str>,
+) -> anyhow::Result<(Client, ClientSession)> {
     let mut rng = thread_rng();
 
     // Generating a subfolder for the database is not mandatory, but it is useful if
@@ -91,9 +579,15 @@ pub async fn build_client(data_dir: &Path) -> anyhow::Result<(Client, ClientSess
         .map(char::from)
         .collect();
 
+    let preset_homeserver = homeserver.is_some();
+    let mut homeserver = homeserver.map(ToOwned::to_owned);
+
     // We create a loop here so the user can retry if an error happens.
     loop {
-        let homeserver = input_popup("Homeserver URL", "Please Input your homeserver URL here.")?;
+        let homeserver = match homeserver.take() {
+            Some(homeserver) => homeserver,
+            None => input_popup("Homeserver URL", "Please Input your homeserver URL here.")?,
+        };
 
         println!("\nChecking homeserver…");
 
@@ -112,10 +606,11 @@ pub async fn build_client(data_dir: &Path) -> anyhow::Result<(Client, ClientSess
                     ClientSession {
                         homeserver,
                         db_path,
-                        passphrase,
+                        passphrase: Some(passphrase),
                     },
                 ))
             }
+            Err(error) if preset_homeserver => return Err(error.into()),
             Err(error) => match &error {
                 matrix_sdk::ClientBuildError::AutoDiscovery(_)
                 | matrix_sdk::ClientBuildError::Url(_)
@@ -131,3 +626,41 @@ pub async fn build_client(data_dir: &Path) -> anyhow::Result<(Client, ClientSess
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::fs;
+
+    use super::{
+        clear_state_cache, database_dirs, BOT_DB_DIR_NAME, SERVERS_DIR_NAME, STATE_STORE_FILE_NAME,
+    };
+
+    #[tokio::test]
+    async fn clear_state_cache_of_every_homeserver() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let main_db = data_dir.path().join("db");
+        let server_db = data_dir
+            .path()
+            .join(SERVERS_DIR_NAME)
+            .join("example.org")
+            .join("db");
+        let bot_db = data_dir.path().join(BOT_DB_DIR_NAME);
+        for db_dir in [&main_db, &server_db, &bot_db] {
+            fs::create_dir_all(db_dir).await.unwrap();
+            fs::write(db_dir.join(STATE_STORE_FILE_NAME), b"")
+                .await
+                .unwrap();
+        }
+
+        let mut db_dirs = database_dirs(data_dir.path()).await.unwrap();
+        db_dirs.sort();
+        let mut expected = vec![bot_db.clone(), main_db.clone(), server_db.clone()];
+        expected.sort();
+        assert_eq!(db_dirs, expected);
+
+        clear_state_cache(data_dir.path()).await.unwrap();
+        assert!(!main_db.join(STATE_STORE_FILE_NAME).exists());
+        assert!(!server_db.join(STATE_STORE_FILE_NAME).exists());
+        assert!(bot_db.join(STATE_STORE_FILE_NAME).exists());
+    }
+}
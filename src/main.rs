@@ -1,7 +1,39 @@
-mod login;
-pub mod ui_elements;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
-use self::login::login;
+use anyhow::{anyhow, Context};
+use clap::Parser;
+use matrix_sdk::{config::SyncSettings, ruma::api::client::filter::FilterDefinition, Client};
+use tokio::{fs, sync::RwLock};
+
+use matrix_client::{
+    bot::Bot,
+    cli::{Cli, Command},
+    config::{Config, SharedConfig},
+    crypto::verification::PendingVerifications,
+    detach::{self, SOCKET_FILE_NAME},
+    diagnostics,
+    discovery::health_check,
+    display::{
+        cache::DisplayNameCache,
+        status::{ActiveUsersDisplay, CountdownDisplay},
+    },
+    login::{
+        login,
+        persist_session::{
+            bot_session_path, default_data_dir, migrate_legacy_data_dir, restore_bot_session,
+            schedule_state_cache_clear, session_path, ExpiredSession,
+        },
+        switch_homeserver,
+    },
+    notifications::sound::SoundNotifier,
+    plugins::PluginRegistry,
+    repl::{Repl, ReplExit},
+    rooms::{
+        send::{send_lines, send_message},
+        timeline::TimelineCache,
+    },
+    sync,
+};
 
 /// A simple program that adapts to the different login methods offered by a
 /// Matrix homeserver.
@@ -10,9 +42,238 @@ use self::login::login;
 /// or both.
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
+    let cli = Cli::parse();
+    diagnostics::init_logging();
+
+    // The folder containing the data of the client.
+    let data_dir = match cli.data_dir {
+        Some(data_dir) => data_dir,
+        None => {
+            let data_dir = default_data_dir();
+            migrate_legacy_data_dir(&data_dir).await?;
+            data_dir
+        }
+    };
+    // The file where the session is persisted.
+    let session_file = cli.session_file.unwrap_or_else(|| session_path(&data_dir));
+    fs::create_dir_all(&data_dir).await?;
+
+    if cli.attach {
+        return detach::attach(&data_dir.join(SOCKET_FILE_NAME)).await;
+    }
+
+    if cli.clear_cache {
+        schedule_state_cache_clear(&data_dir).await?;
+    }
+
+    let mut config = Config::load(&data_dir, &session_file).await?;
+    config.apply_env();
+    if let Some(homeserver) = cli.homeserver {
+        config.override_homeserver(homeserver);
+    }
+    if cli.utc_timestamps {
+        config.override_utc_timestamps(true);
+    }
+    if let Some(image_mode) = cli.image_mode {
+        config.override_image_mode(image_mode);
+    }
+    if let Some(format) = cli.format {
+        config.override_message_format(format);
+    }
+    if let Some(user) = cli.user {
+        config.override_user(user);
+    }
+    if let Some(password) = cli.password {
+        config.password = Some(password);
+    }
+    if let Some(Command::HealthCheck) = cli.command {
+        let homeserver = config.homeserver.as_deref().ok_or_else(|| {
+            anyhow!("The homeserver must be set with MATRIX_HOMESERVER or --homeserver")
+        })?;
+        let health = health_check(homeserver)
+            .await
+            .with_context(|| format!("Health check of {homeserver} failed"))?;
+        println!("{homeserver} is healthy");
+        health.show();
+        return Ok(());
+    }
+
+    let (mut client, mut sync_token, mut current_session_file) = if cli.bot_mode {
+        let session_file = bot_session_path(&data_dir);
+        let client = restore_bot_session(&config.bot, &data_dir, &session_file).await?;
+        (client, None, session_file)
+    } else {
+        let (client, sync_token) = login(&data_dir, &session_file, &config).await?;
+        (client, sync_token, session_file.clone())
+    };
+
+    if let Some(command) = cli.command {
+        // Only sync once to know the joined rooms.
+        let filter = FilterDefinition::with_lazy_loading();
+        client
+            .sync_once(SyncSettings::default().filter(filter.into()))
+            .await?;
+
+        match command {
+            Command::Send {
+                room,
+                message,
+                file,
+                delay_ms,
+                message_type,
+            } => {
+                if let Some(file) = file {
+                    let delay = Duration::from_millis(delay_ms);
+                    let sent = send_lines(
+                        &client,
+                        &room,
+                        &file,
+                        delay,
+                        message_type,
+                        config.message_format,
+                    )
+                    .await?;
+                    println!("All {sent} messages sent");
+                } else {
+                    let message = message.expect("clap requires a message without a file");
+                    let content = message_type.content(&message, config.message_format);
+                    let event_id = send_message(&client, &room, content).await?;
+                    println!("Message sent: {event_id}");
+                }
+            }
+            Command::HealthCheck => unreachable!("the health check doesn't need to log in"),
+        }
+        return Ok(());
+    }
 
-    login().await?;
+    let config = Arc::new(RwLock::new(config));
+    loop {
+        let exit = run_session(
+            client.clone(),
+            sync_token,
+            current_session_file.clone(),
+            config.clone(),
+            cli.no_sound,
+            cli.detached,
+        )
+        .await?;
+
+        match exit {
+            ReplExit::Quit => break,
+            ReplExit::Detach => {
+                // The detached client opens the store again.
+                drop(client);
+                detach::spawn_detached(
+                    &data_dir,
+                    &current_session_file,
+                    cli.bot_mode,
+                    cli.no_sound,
+                )?;
+                break;
+            }
+            ReplExit::SwitchServer(homeserver) => {
+                (client, sync_token, current_session_file) =
+                    switch_homeserver(client, &homeserver, &data_dir, &session_file, &config)
+                        .await?;
+            }
+        }
+    }
 
     Ok(())
 }
+
+/// Sync in the background and run the REPL until the user exits it.
+///
+/// A `detached` client serves the REPLs attached with `--attach` instead, until
+/// one of them quits.
+async fn run_session(
+    client: Client,
+    sync_token: Option<String>,
+    session_file: PathBuf,
+    config: SharedConfig,
+    no_sound: bool,
+    detached: bool,
+) -> anyhow::Result<ReplExit> {
+    let (notification_sound_path, timeline_cache_size, plugin_names) = {
+        let config = config.read().await;
+        (
+            config.notification_sound_path.clone(),
+            config.timeline_cache_size,
+            config.plugins.clone(),
+        )
+    };
+
+    let notifier = if no_sound {
+        SoundNotifier::disabled()
+    } else {
+        SoundNotifier::new(notification_sound_path.as_deref())?
+    };
+    client.add_event_handler_context(notifier);
+    let timeline_cache = TimelineCache::new(timeline_cache_size);
+    client.add_event_handler_context(timeline_cache.clone());
+    let display_names = Arc::new(DisplayNameCache::default());
+    client.add_event_handler_context(display_names.clone());
+    let active_users = ActiveUsersDisplay::default();
+    client.add_event_handler_context(active_users.clone());
+    let active_users_task = active_users.start_refresh_task();
+    let bot = Bot::with_builtin_commands();
+    client.add_event_handler_context(bot.clone());
+    let plugins = PluginRegistry::load(&plugin_names);
+    for name in plugins.names() {
+        println!("Loaded plugin {name}");
+    }
+    client.add_event_handler_context(plugins);
+    let pending_verifications = PendingVerifications::default();
+    client.add_event_handler_context(pending_verifications.clone());
+    let expired_session = ExpiredSession::default();
+    let countdown = CountdownDisplay::default();
+
+    // Keep syncing in the background while the user types commands.
+    let sync_client = client.clone();
+    let sync_config = config.clone();
+    let sync_expired_session = expired_session.clone();
+    let sync_countdown = countdown.clone();
+    let sync_task = tokio::spawn(async move {
+        if let Err(error) = sync::sync(
+            sync_client,
+            sync_token,
+            session_file,
+            sync_config,
+            sync_expired_session,
+            sync_countdown,
+        )
+        .await
+        {
+            println!("The sync stopped: {error}");
+        }
+    });
+
+    let exit = if detached {
+        let socket_path = config.read().await.data_dir.join(SOCKET_FILE_NAME);
+        detach::serve(&client, &socket_path)
+            .await
+            .map(|()| ReplExit::Quit)
+    } else {
+        Repl::new(
+            client.clone(),
+            config,
+            timeline_cache,
+            bot,
+            display_names,
+            active_users,
+            pending_verifications,
+            expired_session,
+            countdown,
+        )
+        .await?
+        .run()
+        .await
+    };
+    // The sync must be stopped before dropping the client when switching
+    // homeservers or detaching.
+    sync_task.abort();
+    let _ = sync_task.await;
+    active_users_task.abort();
+
+    exit
+}
@@ -0,0 +1,480 @@
+pub mod progress;
+pub mod voice;
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::bail;
+use matrix_sdk::{
+    attachment::AttachmentConfig,
+    media::{MediaEventContent, MediaFormat, MediaRequest},
+    ruma::{
+        api::client::media::get_media_config,
+        events::{
+            room::{message::MessageType, MediaSource},
+            AnyMessageLikeEvent, AnySyncMessageLikeEvent, AnySyncTimelineEvent, AnyTimelineEvent,
+            MessageLikeEvent, SyncMessageLikeEvent,
+        },
+        EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId, RoomId,
+    },
+    Client, Room, TransmissionProgress,
+};
+use mime::Mime;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use tracing::{debug, warn};
+
+use self::progress::upload_progress_bar;
+use crate::{
+    display::formatting::format_size,
+    rooms::{format_date, timeline::TimelineCache},
+};
+
+/// The number of bytes read at the start of a file to detect its type.
+const MAGIC_BYTES_LENGTH: usize = 512;
+
+/// The maximum length of the file names chosen by the senders, in the names of
+/// the temporary files.
+const MAX_TEMP_FILE_NAME_LENGTH: usize = 64;
+
+/// The media config of the homeserver, it is only requested once.
+static MEDIA_CONFIG: OnceLock<MediaConfig> = OnceLock::new();
+
+/// The settings of the media repository of the homeserver.
+#[derive(Debug, Clone, Copy)]
+pub struct MediaConfig {
+    /// The maximum size of an upload, in bytes, if the server advertises it.
+    pub upload_size: Option<u64>,
+}
+
+/// Get the settings of the media repository of the homeserver.
+///
+/// They are cached for the whole session.
+pub async fn get_media_config(client: &Client) -> anyhow::Result<MediaConfig> {
+    if let Some(config) = MEDIA_CONFIG.get() {
+        return Ok(*config);
+    }
+
+    let response = client
+        .send(get_media_config::v3::Request::new(), None)
+        .await?;
+    let config = MediaConfig {
+        upload_size: response.upload_size.map(Into::into),
+    };
+
+    Ok(*MEDIA_CONFIG.get_or_init(|| config))
+}
+
+/// Detect the MIME type of the file from its first bytes, or from its extension
+/// if they are not recognized.
+///
+/// Falls back to `application/octet-stream` if the type is unknown.
+pub async fn detect_mime_type(path: &Path) -> anyhow::Result<Mime> {
+    let mut header = Vec::with_capacity(MAGIC_BYTES_LENGTH);
+    fs::File::open(path)
+        .await?
+        .take(MAGIC_BYTES_LENGTH as u64)
+        .read_to_end(&mut header)
+        .await?;
+
+    let mime = infer::get(&header)
+        .and_then(|kind| kind.mime_type().parse().ok())
+        .or_else(|| mime_guess::from_path(path).first())
+        .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+    debug!("Detected type of {}: {mime}", path.display());
+
+    Ok(mime)
+}
+
+/// Upload the given file and send it to the room in a message of the type
+/// matching the file.
+///
+/// In encrypted rooms, the file is encrypted before the upload and the key is
+/// embedded in the `file` field of the message.
+///
+/// Fails if the file is larger than the upload limit of the homeserver.
+pub async fn upload_file(room: &Room, path: &Path) -> anyhow::Result<OwnedEventId> {
+    let size = fs::metadata(path).await?.len();
+    let file_name = path
+        .file_name()
+        .map_or_else(|| "file".into(), |name| name.to_string_lossy());
+
+    check_upload_size(&room.client(), size).await?;
+
+    let mime = detect_mime_type(path).await?;
+
+    let data = fs::read(path).await?;
+
+    let progress_bar = upload_progress_bar(&file_name, size);
+    // The SDK checks whether the room is encrypted again when sending the
+    // attachment, this is only to tell the user.
+    if room.is_encrypted().await? {
+        progress_bar.println(format!("Encrypting {file_name}…"));
+    }
+
+    // The progress is reported by the SDK while the request is sent, the total
+    // includes the overhead of the encryption.
+    let send_attachment = room.send_attachment(&file_name, &mime, data, AttachmentConfig::new());
+    let mut send_progress = send_attachment.subscribe_to_send_progress();
+    let task_progress_bar = progress_bar.clone();
+    let progress_task = tokio::spawn(async move {
+        while let Some(TransmissionProgress { current, total }) = send_progress.next().await {
+            task_progress_bar.set_length(total as u64);
+            task_progress_bar.set_position(current as u64);
+        }
+    });
+    let response = send_attachment.await;
+    progress_task.abort();
+    progress_bar.finish_and_clear();
+
+    Ok(response?.event_id)
+}
+
+/// Fail if a file of the given size is larger than the upload limit of the
+/// homeserver.
+///
+/// The file is accepted if the limit can't be known.
+pub async fn check_upload_size(client: &Client, size: u64) -> anyhow::Result<()> {
+    match get_media_config(client).await {
+        Ok(MediaConfig {
+            upload_size: Some(max_size),
+        }) => {
+            if size > max_size {
+                bail!(
+                    "File is {} but server allows max {}",
+                    format_size(size),
+                    format_size(max_size)
+                );
+            }
+        }
+        Ok(MediaConfig { upload_size: None }) => {
+            warn!("The homeserver doesn't advertise its upload size limit");
+        }
+        Err(error) => warn!("Could not get the upload size limit of the homeserver: {error}"),
+    }
+
+    Ok(())
+}
+
+/// Download the file of a media event to a new file of the temporary
+/// directory.
+///
+/// `file_name` is the name of the file sent by the user, it is sanitized and
+/// only used as the end of the name of the temporary file, so its extension is
+/// kept for the application opening it.
+///
+/// Files sent in encrypted rooms have a `file` field with the key instead of a
+/// `url` field, they are decrypted after the download.
+pub async fn download_to_temp(
+    client: &Client,
+    content: &impl MediaEventContent,
+    file_name: &str,
+) -> anyhow::Result<PathBuf> {
+    match content.source() {
+        Some(MediaSource::Encrypted(_)) => debug!("Downloading an encrypted file"),
+        Some(MediaSource::Plain(_)) => debug!("Downloading an unencrypted file"),
+        None => bail!("This event doesn't have a file"),
+    }
+
+    // The SDK takes the appropriate path from the media source.
+    let Some(data) = client.media().get_file(content, true).await? else {
+        bail!("This event doesn't have a file");
+    };
+
+    // The file is created with a random name that no other user can take
+    // beforehand, and only we can read it.
+    let (file, path) = tempfile::Builder::new()
+        .prefix("matrix-client-")
+        .suffix(&format!("-{}", sanitize_file_name(file_name)))
+        .tempfile()?
+        .keep()?;
+    let mut file = fs::File::from_std(file);
+    file.write_all(&data).await?;
+    file.flush().await?;
+
+    Ok(path)
+}
+
+/// Keep only the characters of the last component of the file name that are
+/// safe in a path on every system, and at most
+/// [`MAX_TEMP_FILE_NAME_LENGTH`] of them from the end.
+fn sanitize_file_name(file_name: &str) -> String {
+    let file_name = Path::new(file_name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let safe_chars = file_name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .collect::<Vec<_>>();
+    let start = safe_chars.len().saturating_sub(MAX_TEMP_FILE_NAME_LENGTH);
+    let sanitized = safe_chars[start..]
+        .iter()
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_owned();
+
+    if sanitized.is_empty() {
+        "download".to_owned()
+    } else {
+        sanitized
+    }
+}
+
+/// Download the audio or video file of the given event and open it with the
+/// default application of the system.
+pub async fn play_media(room: &Room, event_id: &EventId) -> anyhow::Result<()> {
+    let event = room.event(event_id).await?.event.deserialize()?;
+    let AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+        MessageLikeEvent::Original(event),
+    )) = event
+    else {
+        bail!("{event_id} is not a message");
+    };
+
+    let client = room.client();
+    let path = match &event.content.msgtype {
+        MessageType::Audio(content) => download_to_temp(&client, content, &content.body).await?,
+        MessageType::Video(content) => download_to_temp(&client, content, &content.body).await?,
+        _ => bail!("{event_id} is not an audio or video message"),
+    };
+
+    println!("Opening {}", path.display());
+    open::that(&path)?;
+
+    Ok(())
+}
+
+/// A media message received in a room.
+#[derive(Debug, Clone)]
+pub struct MediaEventInfo {
+    /// The ID of the event.
+    pub event_id: OwnedEventId,
+
+    /// The sender of the event.
+    pub sender: OwnedUserId,
+
+    /// When the event was sent.
+    pub origin_server_ts: MilliSecondsSinceUnixEpoch,
+
+    /// The name of the file.
+    pub file_name: String,
+
+    /// The MIME type of the file, if the sender set it.
+    pub mimetype: Option<String>,
+
+    /// The size of the file in bytes, if the sender set it.
+    pub size: Option<u64>,
+
+    /// Where the file can be downloaded.
+    pub source: MediaSource,
+}
+
+impl MediaEventInfo {
+    /// Display the media event on a single line.
+    pub fn show(&self, index: usize) {
+        let details = [self.mimetype.clone(), self.size.map(format_size)]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        println!(
+            "{index:>3}. {} {}: {} ({}) {}",
+            format_date(self.origin_server_ts),
+            self.sender,
+            self.file_name,
+            if details.is_empty() {
+                "unknown type".to_owned()
+            } else {
+                details.join(", ")
+            },
+            self.event_id
+        );
+    }
+}
+
+/// List the images, videos, audio files, files and stickers received in the
+/// room since the client started, in chronological order.
+///
+/// The events that can't be deserialized are skipped.
+pub fn list_room_media(room_id: &RoomId, cache: &TimelineCache) -> Vec<MediaEventInfo> {
+    let mut media = Vec::new();
+    for cached in cache.events(room_id) {
+        let event = match cached.raw.deserialize() {
+            Ok(AnySyncTimelineEvent::MessageLike(event)) => event,
+            Ok(_) => continue,
+            Err(error) => {
+                warn!("Skipping the invalid event {}: {error}", cached.event_id);
+                continue;
+            }
+        };
+
+        let (file_name, mimetype, size, source) = match event {
+            AnySyncMessageLikeEvent::RoomMessage(SyncMessageLikeEvent::Original(event)) => {
+                match event.content.msgtype {
+                    MessageType::Image(content) => {
+                        let info = content.info.unwrap_or_default();
+                        (content.body, info.mimetype, info.size, content.source)
+                    }
+                    MessageType::Video(content) => {
+                        let info = content.info.unwrap_or_default();
+                        (content.body, info.mimetype, info.size, content.source)
+                    }
+                    MessageType::Audio(content) => {
+                        let info = content.info.unwrap_or_default();
+                        (content.body, info.mimetype, info.size, content.source)
+                    }
+                    MessageType::File(content) => {
+                        let info = content.info.unwrap_or_default();
+                        (
+                            content.filename.unwrap_or(content.body),
+                            info.mimetype,
+                            info.size,
+                            content.source,
+                        )
+                    }
+                    _ => continue,
+                }
+            }
+            AnySyncMessageLikeEvent::Sticker(SyncMessageLikeEvent::Original(event)) => (
+                event.content.body,
+                event.content.info.mimetype,
+                event.content.info.size,
+                MediaSource::Plain(event.content.url),
+            ),
+            _ => continue,
+        };
+
+        media.push(MediaEventInfo {
+            event_id: cached.event_id,
+            sender: cached.sender,
+            origin_server_ts: cached.origin_server_ts,
+            file_name,
+            mimetype,
+            size: size.map(Into::into),
+            source,
+        });
+    }
+
+    media
+}
+
+/// Download the file of a media event to the given directory.
+///
+/// Existing files are not overwritten, a number is added to the name of the
+/// file instead, like `photo-1.png`. Returns the path of the downloaded file.
+pub async fn download_media(
+    client: &Client,
+    media: &MediaEventInfo,
+    dest_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let request = MediaRequest {
+        source: media.source.clone(),
+        format: MediaFormat::File,
+    };
+    let data = client.media().get_media_content(&request, true).await?;
+
+    let file_name = Path::new(&media.file_name)
+        .file_name()
+        .map_or_else(|| media.event_id.as_str().into(), ToOwned::to_owned);
+    let (mut file, path) = create_new_file(dest_dir, Path::new(&file_name)).await?;
+    file.write_all(&data).await?;
+    file.flush().await?;
+
+    Ok(path)
+}
+
+/// Create a file with the given name in the directory, or with the first
+/// number after its stem that is not taken.
+async fn create_new_file(dir: &Path, file_name: &Path) -> anyhow::Result<(fs::File, PathBuf)> {
+    let stem = file_name.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = file_name
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut index = 0;
+    loop {
+        let path = if index == 0 {
+            dir.join(file_name)
+        } else {
+            dir.join(format!("{stem}-{index}{extension}"))
+        };
+
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+        {
+            Ok(file) => return Ok((file, path)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => index += 1,
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::{
+        media::{MediaFormat, MediaRequest},
+        ruma::events::room::MediaSource,
+    };
+
+    use super::{sanitize_file_name, MAX_TEMP_FILE_NAME_LENGTH};
+    use crate::testing::mock_server::MockServer;
+
+    #[tokio::test]
+    async fn encrypted_file_round_trip() {
+        let server = MockServer::with_password_login("alice", "secret").await;
+        let uploaded = server.mount_media_repository().await;
+        let client = server.logged_in_client().await.unwrap();
+        let data = b"A small file sent to an encrypted room".to_vec();
+
+        let file = client
+            .prepare_encrypted_file(&mime::TEXT_PLAIN, &mut data.as_slice())
+            .await
+            .unwrap();
+        // Only the encrypted file reaches the homeserver.
+        assert_ne!(*uploaded.lock().unwrap(), data);
+
+        let request = MediaRequest {
+            source: MediaSource::Encrypted(Box::new(file)),
+            format: MediaFormat::File,
+        };
+        let downloaded = client
+            .media()
+            .get_media_content(&request, false)
+            .await
+            .unwrap();
+        assert_eq!(downloaded, data);
+    }
+
+    #[test]
+    fn sanitize_file_name_keeps_safe_names() {
+        assert_eq!(sanitize_file_name("photo.png"), "photo.png");
+        assert_eq!(sanitize_file_name("my_file-2.tar.gz"), "my_file-2.tar.gz");
+    }
+
+    #[test]
+    fn sanitize_file_name_strips_paths_and_unsafe_chars() {
+        assert_eq!(sanitize_file_name("../../.bashrc"), "bashrc");
+        assert_eq!(sanitize_file_name("/etc/passwd"), "passwd");
+        assert_eq!(sanitize_file_name("a b;$(rm).jpg"), "abrm.jpg");
+        assert_eq!(sanitize_file_name(""), "download");
+        assert_eq!(sanitize_file_name(".."), "download");
+    }
+
+    #[test]
+    fn sanitize_file_name_keeps_the_extension_of_long_names() {
+        let name = format!("{}.png", "a".repeat(100));
+        let sanitized = sanitize_file_name(&name);
+        assert_eq!(sanitized.len(), MAX_TEMP_FILE_NAME_LENGTH);
+        assert!(sanitized.ends_with(".png"));
+    }
+}
@@ -0,0 +1,17 @@
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// The template of the progress bar shown while uploading a file.
+const UPLOAD_TEMPLATE: &str =
+    "Uploading {msg} [{bar:30}] {percent}% ({bytes} / {total_bytes}) ETA {eta}";
+
+/// Create a progress bar for the upload of a file of the given size.
+pub fn upload_progress_bar(file_name: &str, file_size: u64) -> ProgressBar {
+    let progress_bar = ProgressBar::new(file_size);
+    progress_bar.set_style(
+        ProgressStyle::with_template(UPLOAD_TEMPLATE)
+            .expect("the upload template is valid")
+            .progress_chars("=>-"),
+    );
+    progress_bar.set_message(file_name.to_owned());
+    progress_bar
+}
@@ -0,0 +1,188 @@
+use std::{io::Cursor, path::Path, time::Duration};
+
+use anyhow::bail;
+use matrix_sdk::{ruma::serde::Raw, Room};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::fs;
+use tracing::debug;
+
+use super::{check_upload_size, detect_mime_type};
+
+/// The number of amplitudes in the waveform of the voice messages that are
+/// sent.
+const WAVEFORM_LENGTH: usize = 30;
+
+/// The maximum amplitude in a waveform, as defined by MSC3246.
+const MAX_AMPLITUDE: u16 = 1024;
+
+/// The characters used to draw a waveform, from the lowest to the highest
+/// amplitude.
+const WAVEFORM_BARS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The unstable fields of the content of a voice message.
+#[derive(Debug, Deserialize)]
+struct VoiceMessageContent {
+    /// Marks the audio message as a voice message (MSC3245).
+    #[serde(rename = "org.matrix.msc3245.voice")]
+    voice: Option<serde_json::Value>,
+
+    /// The details of the audio (MSC3246).
+    #[serde(rename = "org.matrix.msc1767.audio")]
+    audio: Option<AudioDetails>,
+}
+
+/// The details of an audio file, as defined by MSC3246.
+#[derive(Debug, Default, Deserialize)]
+struct AudioDetails {
+    /// The duration of the audio, in milliseconds.
+    duration: Option<u64>,
+
+    /// The amplitudes of the audio over time, between 0 and 1024.
+    #[serde(default)]
+    waveform: Vec<u16>,
+}
+
+/// The details of a received voice message.
+#[derive(Debug)]
+pub struct VoiceDetails {
+    /// The duration of the message, if the sender set it.
+    pub duration: Option<Duration>,
+
+    /// The amplitudes of the message over time, between 0 and 1024.
+    pub waveform: Vec<u16>,
+}
+
+impl VoiceDetails {
+    /// Get the voice details of an audio message, or `None` if it is not a
+    /// voice message.
+    pub fn from_raw<T>(raw: &Raw<T>) -> Option<Self> {
+        let content = raw
+            .get_field::<VoiceMessageContent>("content")
+            .ok()
+            .flatten()?;
+        content.voice?;
+
+        let audio = content.audio.unwrap_or_default();
+        Some(Self {
+            duration: audio.duration.map(Duration::from_millis),
+            waveform: audio.waveform,
+        })
+    }
+
+    /// Draw the waveform with bar characters, or an empty string if there is no
+    /// waveform.
+    pub fn render_waveform(&self) -> String {
+        self.waveform
+            .iter()
+            .map(|amplitude| {
+                let level = usize::from(*amplitude.min(&MAX_AMPLITUDE)) * (WAVEFORM_BARS.len() - 1)
+                    / usize::from(MAX_AMPLITUDE);
+                WAVEFORM_BARS[level]
+            })
+            .collect()
+    }
+}
+
+/// Send an OGG/Opus or WAV file to the room as a voice message.
+///
+/// The waveform and duration are only computed for WAV files, other files are
+/// sent without them.
+///
+/// Fails if the file is larger than the upload limit of the homeserver.
+pub async fn send_voice_message(room: &Room, path: &Path) -> anyhow::Result<()> {
+    let data = fs::read(path).await?;
+    let mime = detect_mime_type(path).await?;
+    if mime.type_() != mime::AUDIO {
+        bail!("{} is not an audio file", path.display());
+    }
+    let file_name = path
+        .file_name()
+        .map_or_else(|| "voice message".into(), |name| name.to_string_lossy());
+
+    let analysis = analyze_wav(&data);
+    if analysis.is_none() {
+        debug!(
+            "Could not decode {} to compute its waveform",
+            path.display()
+        );
+    }
+
+    let size = data.len();
+    let client = room.client();
+    check_upload_size(&client, size as u64).await?;
+    let mut content = json!({
+        "msgtype": "m.audio",
+        "body": file_name,
+        "info": {
+            "mimetype": mime.essence_str(),
+            "size": size,
+        },
+        "org.matrix.msc3245.voice": {},
+    });
+
+    if room.is_encrypted().await? {
+        let file = client
+            .prepare_encrypted_file(&mime, &mut Cursor::new(data))
+            .await?;
+        content["file"] = serde_json::to_value(file)?;
+    } else {
+        let response = client.media().upload(&mime, data).await?;
+        content["url"] = response.content_uri.to_string().into();
+    }
+
+    if let Some((duration, waveform)) = analysis {
+        let duration = duration.as_millis() as u64;
+        content["info"]["duration"] = duration.into();
+        content["org.matrix.msc1767.audio"] = json!({
+            "duration": duration,
+            "waveform": waveform,
+        });
+    }
+
+    room.send_raw(content, "m.room.message").await?;
+
+    Ok(())
+}
+
+/// Compute the duration and waveform of a WAV file.
+///
+/// Returns `None` if the file can't be decoded.
+fn analyze_wav(data: &[u8]) -> Option<(Duration, Vec<u16>)> {
+    let reader = hound::WavReader::new(Cursor::new(data)).ok()?;
+    let spec = reader.spec();
+
+    // The samples are normalized between 0 and 1, whatever their format.
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .filter_map(Result::ok)
+            .map(f32::abs)
+            .collect(),
+        hound::SampleFormat::Int => {
+            let max = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .filter_map(Result::ok)
+                .map(|sample| (sample as f32 / max).abs())
+                .collect()
+        }
+    };
+    if samples.is_empty() {
+        return None;
+    }
+
+    let frames = samples.len() / usize::from(spec.channels.max(1));
+    let duration = Duration::from_secs_f64(frames as f64 / f64::from(spec.sample_rate.max(1)));
+
+    let chunk_size = samples.len().div_ceil(WAVEFORM_LENGTH);
+    let waveform = samples
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let peak = chunk.iter().copied().fold(0.0, f32::max).min(1.0);
+            (peak * f32::from(MAX_AMPLITUDE)) as u16
+        })
+        .collect();
+
+    Some((duration, waveform))
+}
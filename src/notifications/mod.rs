@@ -0,0 +1,18 @@
+pub mod sound;
+pub mod watcher;
+
+use matrix_sdk::Room;
+
+use crate::display::filter::MentionFilter;
+
+/// Whether the given message body mentions the current user, by their user ID,
+/// its localpart or their display name in the room.
+pub async fn is_mention(body: &str, room: &Room) -> bool {
+    let Some(user_id) = room.client().user_id().map(ToOwned::to_owned) else {
+        return false;
+    };
+
+    let member = room.get_member_no_sync(&user_id).await.ok().flatten();
+    let display_name = member.as_ref().and_then(|member| member.display_name());
+    MentionFilter::is_mention(body, &user_id, display_name)
+}
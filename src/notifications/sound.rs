@@ -0,0 +1,58 @@
+use std::{io::Cursor, path::Path, sync::Arc, thread};
+
+use rodio::{Decoder, OutputStream, Sink};
+use tracing::warn;
+
+/// The sound played by default, generated by the build script.
+const DEFAULT_SOUND: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/beep.wav"));
+
+/// Plays a sound when the current user is mentioned.
+#[derive(Debug, Clone)]
+pub struct SoundNotifier {
+    /// The content of the WAV or OGG file to play, or `None` if the sound is
+    /// disabled.
+    sound: Option<Arc<[u8]>>,
+}
+
+impl SoundNotifier {
+    /// Create a notifier playing the given sound file, or the default sound if
+    /// `path` is `None`.
+    pub fn new(path: Option<&Path>) -> anyhow::Result<Self> {
+        let sound = match path {
+            Some(path) => std::fs::read(path)?.into(),
+            None => DEFAULT_SOUND.into(),
+        };
+
+        Ok(Self { sound: Some(sound) })
+    }
+
+    /// Create a notifier that doesn't play any sound.
+    pub fn disabled() -> Self {
+        Self { sound: None }
+    }
+
+    /// Play the sound, without waiting for it to finish.
+    pub fn play(&self) {
+        let Some(sound) = self.sound.clone() else {
+            return;
+        };
+
+        // The audio output can't be shared between threads, and playing the sound
+        // blocks, so it is done in a dedicated thread.
+        thread::spawn(move || {
+            if let Err(error) = play_sound(sound) {
+                warn!("Could not play the notification sound: {error}");
+            }
+        });
+    }
+}
+
+/// Play the given sound and wait for it to finish.
+fn play_sound(sound: Arc<[u8]>) -> anyhow::Result<()> {
+    let (_stream, stream_handle) = OutputStream::try_default()?;
+    let sink = Sink::try_new(&stream_handle)?;
+    sink.append(Decoder::new(Cursor::new(sound))?);
+    sink.sleep_until_end();
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+
+use matrix_sdk::{
+    event_handler::Ctx,
+    ruma::{
+        events::room::member::{MembershipChange, OriginalSyncRoomMemberEvent},
+        OwnedUserId, UserId,
+    },
+    Room, RoomState,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::SharedConfig;
+
+/// The users whose joins are announced, in any of the joined rooms.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UserWatcher {
+    users: HashSet<OwnedUserId>,
+}
+
+impl UserWatcher {
+    /// Start watching the user.
+    ///
+    /// Returns `false` if the user was already watched.
+    pub fn watch(&mut self, user_id: OwnedUserId) -> bool {
+        self.users.insert(user_id)
+    }
+
+    /// Stop watching the user.
+    ///
+    /// Returns `false` if the user was not watched.
+    pub fn unwatch(&mut self, user_id: &UserId) -> bool {
+        self.users.remove(user_id)
+    }
+
+    /// Whether the user is watched.
+    pub fn is_watched(&self, user_id: &UserId) -> bool {
+        self.users.contains(user_id)
+    }
+
+    /// Display the watched users.
+    pub fn show(&self) {
+        if self.users.is_empty() {
+            println!("No user is watched, add one with /watch-user <user-id>");
+            return;
+        }
+
+        let mut users: Vec<_> = self.users.iter().collect();
+        users.sort_unstable();
+        for user_id in users {
+            println!("{user_id}");
+        }
+    }
+}
+
+/// Announce the watched users joining one of the joined rooms.
+///
+/// Only the events with the previous membership of the user are announced. The
+/// state of a limited sync doesn't have it and would announce users who joined
+/// long ago as if they just joined.
+pub async fn on_room_member(
+    event: OriginalSyncRoomMemberEvent,
+    room: Room,
+    config: Ctx<SharedConfig>,
+) {
+    if room.state() != RoomState::Joined
+        || event.unsigned.prev_content.is_none()
+        || !matches!(event.membership_change(), MembershipChange::Joined)
+        || !config
+            .read()
+            .await
+            .watched_users
+            .is_watched(&event.state_key)
+    {
+        return;
+    }
+
+    let room_name = match room.canonical_alias() {
+        Some(alias) => alias.to_string(),
+        None => match room.display_name().await {
+            Ok(name) => name.to_string(),
+            Err(_) => room.room_id().to_string(),
+        },
+    };
+    println!("👀 {} joined {room_name}", event.state_key);
+}
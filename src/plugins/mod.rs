@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use matrix_sdk::{
+    event_handler::Ctx,
+    ruma::events::{
+        room::message::{MessageType, OriginalSyncRoomMessageEvent, RoomMessageEventContent},
+        AnySyncStateEvent,
+    },
+    Client, Room, RoomState,
+};
+use tracing::warn;
+
+/// A custom handler for the events received during the sync.
+pub trait Plugin {
+    /// The name of the plugin, as used in the config.
+    fn name(&self) -> &str;
+
+    /// Handle a message received in a joined room.
+    fn on_message<'a>(
+        &'a self,
+        _event: &'a OriginalSyncRoomMessageEvent,
+        _room: &'a Room,
+        _client: &'a Client,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    /// Handle a state event received in a joined room.
+    fn on_state_event<'a>(
+        &'a self,
+        _event: &'a AnySyncStateEvent,
+        _room: &'a Room,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// The plugins enabled in the config.
+///
+/// It is shared with the event handlers, it can be cloned cheaply.
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+    plugins: Arc<Vec<Box<dyn Plugin + Send + Sync>>>,
+}
+
+impl PluginRegistry {
+    /// Load the plugins with the given names.
+    ///
+    /// Unknown plugins are ignored with a warning.
+    pub fn load(names: &[String]) -> Self {
+        let plugins = names
+            .iter()
+            .filter_map(|name| {
+                let plugin: Box<dyn Plugin + Send + Sync> = match name.as_str() {
+                    "echo" => Box::new(EchoPlugin),
+                    _ => {
+                        warn!("Unknown plugin: {name}");
+                        return None;
+                    }
+                };
+                Some(plugin)
+            })
+            .collect();
+
+        Self {
+            plugins: Arc::new(plugins),
+        }
+    }
+
+    /// The names of the loaded plugins.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|plugin| plugin.name())
+    }
+}
+
+/// Dispatch the messages received in joined rooms to all the plugins.
+pub async fn on_room_message(
+    event: OriginalSyncRoomMessageEvent,
+    room: Room,
+    client: Client,
+    plugins: Ctx<PluginRegistry>,
+) {
+    if room.state() != RoomState::Joined {
+        return;
+    }
+
+    for plugin in plugins.plugins.iter() {
+        plugin.on_message(&event, &room, &client).await;
+    }
+}
+
+/// Dispatch the state events received in joined rooms to all the plugins.
+pub async fn on_state_event(event: AnySyncStateEvent, room: Room, plugins: Ctx<PluginRegistry>) {
+    if room.state() != RoomState::Joined {
+        return;
+    }
+
+    for plugin in plugins.plugins.iter() {
+        plugin.on_state_event(&event, &room).await;
+    }
+}
+
+/// A plugin that replies to the messages starting with `!echo` with the rest of
+/// the message.
+pub struct EchoPlugin;
+
+impl Plugin for EchoPlugin {
+    fn name(&self) -> &str {
+        "echo"
+    }
+
+    fn on_message<'a>(
+        &'a self,
+        event: &'a OriginalSyncRoomMessageEvent,
+        room: &'a Room,
+        client: &'a Client,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            // Don't echo ourselves.
+            if client.user_id() == Some(&*event.sender) {
+                return;
+            }
+            let MessageType::Text(text_content) = &event.content.msgtype else {
+                return;
+            };
+            let Some(text) = text_content.body.strip_prefix("!echo ") else {
+                return;
+            };
+
+            if let Err(error) = room.send(RoomMessageEventContent::text_plain(text)).await {
+                warn!("Error sending echo: {error}");
+            }
+        })
+    }
+}
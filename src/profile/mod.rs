@@ -0,0 +1,138 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::anyhow;
+use matrix_sdk::{
+    ruma::{
+        api::client::account::request_openid_token,
+        events::ignored_user_list::IgnoredUserListEventContent, OwnedUserId,
+    },
+    Client,
+};
+use tokio::fs;
+
+use crate::{login::persist_session::FullSession, rooms::format_date};
+
+/// Display information about the logged-in user and the current session.
+pub async fn show_current_user(client: &Client, session_file: &Path) -> anyhow::Result<()> {
+    // Ask the homeserver, it is the authority on who we are.
+    let whoami = client.whoami().await?;
+    let display_name = client.account().get_display_name().await?;
+
+    let device_display_name = match &whoami.device_id {
+        Some(device_id) => client
+            .devices()
+            .await?
+            .devices
+            .into_iter()
+            .find(|device| device.device_id == *device_id)
+            .and_then(|device| device.display_name),
+        None => None,
+    };
+
+    let session_created = match fs::read_to_string(session_file).await {
+        Ok(serialized_session) => serde_json::from_str::<FullSession>(&serialized_session)?.created,
+        Err(_) => None,
+    };
+
+    let cross_signing = match client.encryption().cross_signing_status().await {
+        Some(status) if status.is_complete() => "fully set up",
+        Some(_) => "partially set up",
+        None => "not set up",
+    };
+
+    let unknown = || "unknown".to_owned();
+    let rows = [
+        ("User ID", whoami.user_id.to_string()),
+        ("Display name", display_name.unwrap_or_else(unknown)),
+        ("Homeserver", client.homeserver().to_string()),
+        (
+            "Device ID",
+            whoami.device_id.map_or_else(unknown, |id| id.to_string()),
+        ),
+        ("Device name", device_display_name.unwrap_or_else(unknown)),
+        (
+            "Session created",
+            session_created.map_or_else(unknown, format_date),
+        ),
+        ("Cross-signing", cross_signing.to_owned()),
+    ];
+
+    for (label, value) in rows {
+        println!("{label:<20} {value}");
+    }
+
+    Ok(())
+}
+
+/// An OpenID token proving the identity of the user to third-party services.
+pub type OpenIdToken = request_openid_token::v3::Response;
+
+/// Request an OpenID token for the current user from the homeserver.
+pub async fn get_openid_token(client: &Client) -> anyhow::Result<OpenIdToken> {
+    let user_id = client
+        .user_id()
+        .ok_or_else(|| anyhow!("The client is not logged in"))?
+        .to_owned();
+
+    Ok(client
+        .send(request_openid_token::v3::Request::new(user_id), None)
+        .await?)
+}
+
+/// Display the OpenID token.
+///
+/// Unless `full` is set, only the last 8 characters of the token are displayed.
+pub fn show_openid_token(token: &OpenIdToken, full: bool) {
+    let access_token = if full {
+        token.access_token.clone()
+    } else {
+        let visible_start = token
+            .access_token
+            .char_indices()
+            .rev()
+            .nth(7)
+            .map_or(0, |(idx, _)| idx);
+        format!("…{}", &token.access_token[visible_start..])
+    };
+
+    let rows = [
+        ("Token", access_token),
+        ("Token type", token.token_type.to_string()),
+        ("Server", token.matrix_server_name.to_string()),
+        ("Expires in", format!("{}s", token.expires_in.as_secs())),
+    ];
+
+    for (label, value) in rows {
+        println!("{label:<20} {value}");
+    }
+}
+
+/// Get the users on the ignore list of the account.
+pub async fn get_ignored_users(client: &Client) -> anyhow::Result<HashSet<OwnedUserId>> {
+    let Some(raw_content) = client
+        .account()
+        .account_data::<IgnoredUserListEventContent>()
+        .await?
+    else {
+        return Ok(HashSet::new());
+    };
+
+    Ok(raw_content
+        .deserialize()?
+        .ignored_users
+        .into_keys()
+        .collect())
+}
+
+/// Display the users on the ignore list of the account.
+pub async fn list_ignored_users(client: &Client) -> anyhow::Result<()> {
+    let mut ignored_users: Vec<_> = get_ignored_users(client).await?.into_iter().collect();
+    ignored_users.sort_unstable();
+
+    for user_id in &ignored_users {
+        println!("{user_id}");
+    }
+    println!("{} ignored users", ignored_users.len());
+
+    Ok(())
+}
@@ -0,0 +1,1759 @@
+use std::{
+    collections::HashMap,
+    io,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail};
+use matrix_sdk::{
+    ruma::{
+        events::room::ImageInfo, DeviceId, EventId, MxcUri, OwnedRoomId, OwnedUserId, RoomAliasId,
+        RoomId, RoomOrAliasId, RoomVersionId, ServerName,
+    },
+    Client, Room, RoomMemberships, RoomState,
+};
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    history::FileHistory,
+    Context, Editor, Helper, Highlighter, Hinter, Validator,
+};
+use tokio::{fs, task::JoinHandle};
+
+use crate::{
+    bot::Bot,
+    config::SharedConfig,
+    crypto::{
+        block_device, list_devices, trust_device, unblock_device, untrust_device,
+        verification::PendingVerifications,
+    },
+    diagnostics::{generate_bug_report, show_global_stats, show_version_info},
+    discovery::{list_unstable_features, ping_federation_server, ping_homeserver},
+    display::{
+        cache::DisplayNameCache,
+        formatting::format_size,
+        image::ImageMode,
+        scroll::{ScrollState, SCROLL_STATE_FILE_NAME},
+        status::{ActiveUsersDisplay, CountdownDisplay},
+        timeline::{compute_shield, render_timeline_event, show_last_messages},
+    },
+    login::{
+        persist_session::{
+            compact_state_store, logout_all_other_sessions, schedule_state_cache_clear,
+            ExpiredSession,
+        },
+        relogin,
+    },
+    media::{download_media, list_room_media, play_media, upload_file, voice::send_voice_message},
+    profile::{get_openid_token, list_ignored_users, show_current_user, show_openid_token},
+    rooms::{
+        aliases::resolve_alias,
+        bridge::bridge_rooms,
+        create::create_room,
+        direct::list_contacts,
+        export::{export_room_history, export_timeline_json, transfer_history},
+        find_server_notice_room, format_date,
+        invites::{list_sent_invites, rescind_invite},
+        leave_muted_rooms,
+        members::{members_joined_since, show_join_history},
+        messaging::{send_custom_event, send_emote, send_notice, send_sticker},
+        moderation::{audit_room_acl, set_acl, show_acl},
+        parse_date,
+        permalinks::{
+            find_matrix_uri, generate_invite_link, parse_matrix_uri, MatrixUri, MatrixUriTarget,
+            MATRIX_URI_PREFIX,
+        },
+        receipts::{update_fully_read, ReceiptType},
+        search::search_local,
+        settings::{check_room_version, room_version, show_power_level_requirements, who_can},
+        show_room_info,
+        state::{
+            copy_event_json, list_federated_servers, show_federated_servers, show_room_creation,
+        },
+        stats::{count_room_events, show_room_stats},
+        timeline::{show_recent_across_rooms, summarize_since_last_read, TimelineCache},
+        upgrade::{follow_tombstone, upgrade_room},
+    },
+    spaces::explore_space,
+    ui_elements::confirm::confirm,
+    utils::{clipboard::copy_to_clipboard, editor::open_editor, mxid::parse_mxid},
+};
+
+/// The prompt displayed while waiting for a command.
+const PROMPT: &str = "> ";
+
+/// The depth of the space hierarchy shown by `/space-explore` by default.
+const DEFAULT_SPACE_DEPTH: u32 = 3;
+
+/// The number of rooms shown by `/recent` by default.
+const DEFAULT_RECENT_COUNT: usize = 20;
+
+/// The number of messages copied by `/transfer-history` by default.
+const DEFAULT_TRANSFER_LIMIT: u32 = 100;
+
+/// The number of characters of the body shown next to the completed event IDs.
+const EVENT_EXCERPT_LENGTH: usize = 30;
+
+/// The number of media events listed per page by `/media-list`.
+const MEDIA_LIST_PAGE_SIZE: usize = 20;
+
+/// The name of the file where the command history is persisted, in the data
+/// directory.
+const HISTORY_FILE_NAME: &str = "repl_history.txt";
+
+/// The commands that are never saved in the history because they contain
+/// sensitive data.
+const SENSITIVE_COMMANDS: &[&str] = &["/set-passphrase", "/login", "/logout-all"];
+
+/// The number of messages displayed by `/history` by default.
+const DEFAULT_HISTORY_COUNT: u32 = 20;
+
+/// The commands available in the REPL, with their description.
+const COMMANDS: &[(&str, &str)] = &[
+    ("/help", "Show this list of commands"),
+    (
+        "//",
+        "Send a message starting with a slash to the current room: //<text>",
+    ),
+    ("/whoami", "Show the current user and session"),
+    (
+        "/relogin",
+        "Log in again after the session expired, the sync resumes after",
+    ),
+    (
+        "/me",
+        "Describe what you are doing in the current room: /me <action>",
+    ),
+    (
+        "/upload",
+        "Send a file to the current room: /upload <file>",
+    ),
+    (
+        "/voice",
+        "Send an OGG/Opus or WAV file as a voice message to the current room: /voice <file>",
+    ),
+    (
+        "/sticker",
+        "Send an uploaded sticker to the current room: /sticker <mxc-uri> <alt-text>",
+    ),
+    (
+        "/notice",
+        "Send a notice, that doesn't trigger notifications, to the current room: /notice <text>",
+    ),
+    (
+        "/set-message-body",
+        "Compose a long message in $EDITOR and send it to the current room: /set-message-body --editor",
+    ),
+    (
+        "/set-topic",
+        "Set the topic of the current room, or edit it in $EDITOR: /set-topic <text>|--editor",
+    ),
+    (
+        "/send-raw",
+        "Send an event of any type to the current room: /send-raw <event-type> <json>",
+    ),
+    (
+        "/set-state",
+        "Send a state event of any type to the current room: /set-state <event-type> [state-key] <json>",
+    ),
+    (
+        "/switch",
+        "Select the room where messages are sent: /switch <room-id-or-alias>",
+    ),
+    (
+        "/openid-token",
+        "Request an OpenID token to prove your identity to a third-party service: /openid-token [--full]",
+    ),
+    (
+        "/join",
+        "Join a room and switch to it: /join <room-id|alias|matrix:-URI>",
+    ),
+    (
+        "/contacts",
+        "List the users you have direct conversations with",
+    ),
+    (
+        "/ping",
+        "Measure the latency of the homeserver, or of another server: /ping [server]",
+    ),
+    ("/version", "Show the versions of the client, the SDK and Rust"),
+    (
+        "/matrix-version",
+        "List the unstable features supported by the homeserver",
+    ),
+    ("/report-bug", "Write a diagnostics report to share in a bug report"),
+    ("/stats", "Show statistics about a room: /stats [room]"),
+    (
+        "/who-can",
+        "List the members of the current room allowed to perform an action: /who-can <kick|ban|redact|invite|event-type>",
+    ),
+    (
+        "/invite-link",
+        "Show a matrix.to link to join a room: /invite-link [room] [--copy]",
+    ),
+    (
+        "/copy-room-id",
+        "Copy the ID of a room to the clipboard: /copy-room-id [room]",
+    ),
+    (
+        "/copy-user-id",
+        "Copy the ID of a user, yours by default, to the clipboard: /copy-user-id [user-id]",
+    ),
+    (
+        "/space-explore",
+        "Show the rooms of a space as a tree and join one of them: /space-explore <room-id> [--depth N]",
+    ),
+    (
+        "/creation-info",
+        "Show how a room was created and the room it replaces: /creation-info [room]",
+    ),
+    (
+        "/event-count",
+        "Count the events of a room since its creation, slow in large rooms: /event-count [room]",
+    ),
+    (
+        "/room-info",
+        "Show all the metadata of a room: /room-info [room]",
+    ),
+    (
+        "/stats-global",
+        "Show statistics about the rooms and the local data of the client",
+    ),
+    (
+        "/perms",
+        "Show the power levels required for common actions in a room: /perms [room]",
+    ),
+    (
+        "/room-version",
+        "Show the version of a room: /room-version [room]",
+    ),
+    (
+        "/upgrade",
+        "Upgrade a room to a new version, the recommended one by default: /upgrade [room] [version]",
+    ),
+    (
+        "/follow-upgrade",
+        "Join the room that replaces an upgraded room: /follow-upgrade [room]",
+    ),
+    (
+        "/history",
+        "Show the last messages of a room: /history [room] [count]",
+    ),
+    (
+        "/expand",
+        "Show the full content of a truncated message: /expand [room] <event-id>",
+    ),
+    (
+        "/event-json",
+        "Print the JSON of an event of the current room: /event-json <event-id> [--copy]",
+    ),
+    (
+        "/copy",
+        "Copy the JSON of an event of the current room to the clipboard: /copy <event-id>",
+    ),
+    (
+        "/play",
+        "Download an audio or video message and open it: /play [room] <event-id>",
+    ),
+    (
+        "/media-list",
+        "List the media received in the current room, 20 per page: /media-list [page]",
+    ),
+    (
+        "/download-all-media",
+        "Download the media received in the current room: /download-all-media [dest-dir]",
+    ),
+    (
+        "/logout-all",
+        "Log out all your other sessions, for example after a compromise of the account",
+    ),
+    (
+        "/devices",
+        "List the devices of a user and whether they are trusted: /devices <user-id>",
+    ),
+    (
+        "/trust",
+        "Trust a device of a user: /trust <user-id> <device-id>",
+    ),
+    (
+        "/untrust",
+        "Remove the local trust of a device: /untrust <user-id> <device-id>",
+    ),
+    ("/block", "Block a device of a user: /block <user-id> <device-id>"),
+    ("/unblock", "Unblock a device: /unblock <user-id> <device-id>"),
+    (
+        "/verify-accept",
+        "Accept the verification request of another device, or confirm that the emojis match",
+    ),
+    (
+        "/verify-reject",
+        "Refuse the verification request of another device, or report that the emojis don't match",
+    ),
+    (
+        "/server-notices",
+        "Switch to the room of the administrative messages of the homeserver",
+    ),
+    (
+        "/recent",
+        "Show the latest message of the most recently active rooms: /recent [count]",
+    ),
+    (
+        "/mark-read",
+        "Mark a room as read up to the latest received event: /mark-read [room]",
+    ),
+    (
+        "/set-receipt-type",
+        "Choose the read receipts sent by /mark-read: /set-receipt-type <public|private|off>",
+    ),
+    (
+        "/summarize",
+        "Summarize the activity in a room since your last read: /summarize [room]",
+    ),
+    (
+        "/pending-invites",
+        "List the users invited to a room who have not joined yet: /pending-invites [room]",
+    ),
+    (
+        "/rescind-invite",
+        "Cancel the invite of a user to the current room: /rescind-invite <user-id>",
+    ),
+    (
+        "/grep",
+        "Search the messages received in the current room: /grep [--case-insensitive] [--sender <user-id>] <pattern>",
+    ),
+    (
+        "/join-history",
+        "Show when you were invited to and joined a room: /join-history [room]",
+    ),
+    (
+        "/joined-since",
+        "List the members who joined a room after a date: /joined-since [room] <ISO-date>",
+    ),
+    (
+        "/export-history",
+        "Export the history of a room: /export-history [room] <file> [--format csv|json] [--since <ISO-date>]",
+    ),
+    ("/acl", "Show the server ACL of a room: /acl [room]"),
+    (
+        "/acl-set",
+        "Set the server ACL of a room: /acl-set [room] [--allow <glob>]… [--deny <glob>]… [--allow-ip-literals]",
+    ),
+    (
+        "/acl-audit",
+        "Warn about a server ACL that lets in too many servers: /acl-audit [room]",
+    ),
+    (
+        "/federation-info",
+        "Show the servers of the members of a room: /federation-info [room]",
+    ),
+    (
+        "/timeline-export",
+        "Export the events of the current room received since the client started, as JSON: /timeline-export <file>",
+    ),
+    (
+        "/transfer-history",
+        "Copy the history of a room to another one: /transfer-history <source-room-id> <dest-room-id> [--limit N]",
+    ),
+    (
+        "/resolve",
+        "Look up the room an alias points to: /resolve <#alias:server>",
+    ),
+    (
+        "/create",
+        "Create a room: /create [--preset private|public|trusted] [--invite <user-id>]… <name>",
+    ),
+    (
+        "/leave-muted",
+        "Leave all the low priority and muted rooms",
+    ),
+    (
+        "/ignore",
+        "Add a user to the ignore list of the account: /ignore <user-id>",
+    ),
+    (
+        "/unignore",
+        "Remove a user from the ignore list of the account: /unignore <user-id>",
+    ),
+    ("/ignored", "List the users ignored by the account"),
+    (
+        "/watch-user",
+        "Announce when a user joins one of your rooms: /watch-user <user-id>",
+    ),
+    (
+        "/unwatch-user",
+        "Stop announcing the joins of a user: /unwatch-user <user-id>",
+    ),
+    ("/watching", "List the users whose joins are announced"),
+    (
+        "/filter-sender",
+        "Hide the events of a user: /filter-sender <user-id>",
+    ),
+    (
+        "/unfilter-sender",
+        "Show the events of a user again: /unfilter-sender <user-id>",
+    ),
+    (
+        "/filter-type",
+        "Hide the events of a type: /filter-type <event-type>",
+    ),
+    (
+        "/unfilter-type",
+        "Show the events of a type again: /unfilter-type <event-type>",
+    ),
+    ("/filters", "List the active filters"),
+    (
+        "/image-mode",
+        "Change how images are displayed until the client exits: /image-mode <ascii|external|off>",
+    ),
+    (
+        "/mentions-only",
+        "Only show the messages mentioning you: /mentions-only [off]",
+    ),
+    (
+        "/listen-room",
+        "Respond to the bot commands starting with `!` in a room: /listen-room [room]",
+    ),
+    (
+        "/bridge",
+        "Re-send the text messages of a room to another room: /bridge <source-room-id> <dest-room-id>",
+    ),
+    (
+        "/unbridge",
+        "Stop re-sending the messages of a room: /unbridge <source-room-id>",
+    ),
+    ("/history-clear", "Delete the history of commands"),
+    (
+        "/save-settings",
+        "Write the current settings to the config file, showing what changed",
+    ),
+    (
+        "/compact-db",
+        "Reclaim the disk space of deleted data in the local state store",
+    ),
+    (
+        "/clear-cache",
+        "Delete the local state store and exit, it is fetched again on the next start",
+    ),
+    (
+        "/detach",
+        "Leave the REPL but keep syncing, get it back with --attach",
+    ),
+    (
+        "/switch-server",
+        "Log in to another homeserver, each one has its own session: /switch-server <homeserver-url>",
+    ),
+    ("/quit", "Exit the client"),
+];
+
+/// Why the REPL stopped.
+#[derive(Debug)]
+pub enum ReplExit {
+    /// The user wants to exit the client.
+    Quit,
+
+    /// The user wants to connect to the homeserver at the given URL.
+    SwitchServer(String),
+
+    /// The user wants to keep the client running and attach a REPL to it
+    /// later.
+    Detach,
+}
+
+/// The interactive command loop, run once the client is logged in.
+pub struct Repl {
+    client: Client,
+    config: SharedConfig,
+    editor: Editor<MatrixCompleter, FileHistory>,
+
+    /// The file where the command history is persisted.
+    history_file: PathBuf,
+
+    /// The room where messages are sent.
+    current_room: Option<OwnedRoomId>,
+
+    /// The events received since the client started.
+    timeline_cache: TimelineCache,
+
+    /// The bot handling commands in the rooms it listens to.
+    bot: Bot,
+
+    /// The tasks re-sending the messages of the bridged rooms, by source room.
+    bridges: HashMap<OwnedRoomId, JoinHandle<()>>,
+
+    /// The display names of the senders.
+    display_names: Arc<DisplayNameCache>,
+
+    /// The last event read in each room.
+    scroll_state: ScrollState,
+
+    /// The users who recently sent a message in the current room.
+    active_users: ActiveUsersDisplay,
+
+    /// The verification waiting for an answer.
+    pending_verifications: PendingVerifications,
+
+    /// The session waiting for the user to log in again.
+    expired_session: ExpiredSession,
+
+    /// The prompt, showing the current room.
+    prompt: String,
+}
+
+impl Repl {
+    pub async fn new(
+        client: Client,
+        config: SharedConfig,
+        timeline_cache: TimelineCache,
+        bot: Bot,
+        display_names: Arc<DisplayNameCache>,
+        active_users: ActiveUsersDisplay,
+        pending_verifications: PendingVerifications,
+        expired_session: ExpiredSession,
+        countdown: CountdownDisplay,
+    ) -> anyhow::Result<Self> {
+        let (max_history_size, history_file, completed_events_count, scroll_state_file) = {
+            let config = config.read().await;
+            (
+                config.max_history_size,
+                config.data_dir.join(HISTORY_FILE_NAME),
+                config.completed_events_count,
+                config.data_dir.join(SCROLL_STATE_FILE_NAME),
+            )
+        };
+
+        let editor_config = rustyline::Config::builder()
+            .max_history_size(max_history_size)?
+            .build();
+        let mut editor = Editor::with_config(editor_config)?;
+        editor.set_helper(Some(MatrixCompleter {
+            client: client.clone(),
+            current_room: None,
+            timeline_cache: timeline_cache.clone(),
+            completed_events_count,
+        }));
+
+        match editor.load_history(&history_file) {
+            Ok(()) => {}
+            // There is no history yet.
+            Err(ReadlineError::Io(error)) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => println!("Error loading the command history: {error}"),
+        }
+        // The countdown of the sync is printed above the input line.
+        match editor.create_external_printer() {
+            Ok(printer) => countdown.set_printer(printer),
+            Err(error) => println!("Error setting up the status line: {error}"),
+        }
+        let scroll_state = ScrollState::load(&scroll_state_file)
+            .await
+            .unwrap_or_else(|error| {
+                println!("Error loading the scroll positions: {error}");
+                ScrollState::default()
+            });
+
+        Ok(Self {
+            client,
+            config,
+            editor,
+            history_file,
+            current_room: None,
+            timeline_cache,
+            bot,
+            bridges: HashMap::new(),
+            display_names,
+            scroll_state,
+            active_users,
+            pending_verifications,
+            expired_session,
+            prompt: PROMPT.to_owned(),
+        })
+    }
+
+    /// Read and execute commands until the user quits.
+    pub async fn run(mut self) -> anyhow::Result<ReplExit> {
+        let exit = loop {
+            // Reading a line blocks, make sure the sync keeps running meanwhile.
+            let line = match tokio::task::block_in_place(|| self.editor.readline(&self.prompt)) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => break ReplExit::Quit,
+                Err(error) => return Err(error.into()),
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if !SENSITIVE_COMMANDS
+                .iter()
+                .any(|command| line.starts_with(command))
+            {
+                self.editor.add_history_entry(line)?;
+                // Saved right away, so it is kept if the client is killed.
+                if let Err(error) = self.editor.save_history(&self.history_file) {
+                    println!("Error saving the command history: {error}");
+                }
+            }
+
+            match self.execute(line).await {
+                Ok(ControlFlow::Continue(())) => {}
+                Ok(ControlFlow::Break(exit)) => break exit,
+                Err(error) => println!("Error: {error}"),
+            }
+        };
+
+        self.update_scroll_position();
+        let scroll_state_file = self
+            .config
+            .read()
+            .await
+            .data_dir
+            .join(SCROLL_STATE_FILE_NAME);
+        self.scroll_state.save(&scroll_state_file).await?;
+
+        Ok(exit)
+    }
+
+    /// Execute a single line of input.
+    async fn execute(&mut self, line: &str) -> anyhow::Result<ControlFlow<ReplExit>> {
+        // A message starting with `/` is typed with a second one, like `//shrug`.
+        let message = match line.strip_prefix('/') {
+            Some(rest) if rest.starts_with('/') => Some(rest),
+            Some(_) => None,
+            None => Some(line),
+        };
+        if let Some(line) = message {
+            if let Some(uri) = find_matrix_uri(line).and_then(|uri| parse_matrix_uri(uri).ok()) {
+                if !matches!(uri.target, MatrixUriTarget::User(_))
+                    && confirm(&format!(
+                        "Go to the {} instead of sending the message?",
+                        uri.target
+                    ))?
+                {
+                    self.open_matrix_uri(uri).await?;
+                    return Ok(ControlFlow::Continue(()));
+                }
+            }
+
+            let room = self.joined_room("").await?;
+            let format = self.config.read().await.message_format;
+            room.send(format.text(line)).await?;
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        let (command, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+
+        match command {
+            "/help" => print_help(),
+            "/me" => {
+                if args.is_empty() {
+                    bail!("Usage: /me <action>");
+                }
+                send_emote(&self.joined_room("").await?, args).await?;
+            }
+            "/upload" => {
+                if args.is_empty() {
+                    bail!("Usage: /upload <file>");
+                }
+                let event_id = upload_file(&self.joined_room("").await?, Path::new(args)).await?;
+                println!("File sent: {event_id}");
+            }
+            "/voice" => {
+                if args.is_empty() {
+                    bail!("Usage: /voice <file>");
+                }
+                send_voice_message(&self.joined_room("").await?, Path::new(args)).await?;
+            }
+            "/sticker" => {
+                let Some((mxc_uri, alt_text)) = args.split_once(char::is_whitespace) else {
+                    bail!("Usage: /sticker <mxc-uri> <alt-text>");
+                };
+                let mxc_uri = <&MxcUri>::from(mxc_uri);
+                if !mxc_uri.is_valid() {
+                    bail!("{mxc_uri} is not a valid MXC URI");
+                }
+
+                let room = self.joined_room("").await?;
+                send_sticker(&room, mxc_uri, alt_text.trim(), ImageInfo::new()).await?;
+            }
+            "/notice" => {
+                if args.is_empty() {
+                    bail!("Usage: /notice <text>");
+                }
+                send_notice(&self.joined_room("").await?, args).await?;
+            }
+            "/set-message-body" => {
+                if args != "--editor" {
+                    bail!("Usage: /set-message-body --editor");
+                }
+                let room = self.joined_room("").await?;
+                let body = tokio::task::block_in_place(|| open_editor(""))?;
+                if body.trim().is_empty() {
+                    println!("The message is empty, nothing was sent");
+                    return Ok(ControlFlow::Continue(()));
+                }
+                let format = self.config.read().await.message_format;
+                room.send(format.text(&body)).await?;
+            }
+            "/set-topic" => {
+                let room = self.joined_room("").await?;
+                let topic = match args {
+                    "" => bail!("Usage: /set-topic <text>|--editor"),
+                    "--editor" => {
+                        let current = room.topic().unwrap_or_default();
+                        let topic = tokio::task::block_in_place(|| open_editor(&current))?;
+                        if topic == current {
+                            println!("The topic didn't change");
+                            return Ok(ControlFlow::Continue(()));
+                        }
+                        topic
+                    }
+                    topic => topic.to_owned(),
+                };
+                room.set_room_topic(&topic).await?;
+                println!("Topic updated");
+            }
+            "/send-raw" => {
+                let Some((event_type, json)) = args.split_once(char::is_whitespace) else {
+                    bail!("Usage: /send-raw <event-type> <json>");
+                };
+                let content = serde_json::from_str(json)
+                    .map_err(|error| anyhow!("Invalid JSON content: {error}"))?;
+                send_custom_event(&self.joined_room("").await?, event_type, content, None).await?;
+            }
+            "/set-state" => {
+                let Some((event_type, rest)) = args.split_once(char::is_whitespace) else {
+                    bail!("Usage: /set-state <event-type> [state-key] <json>");
+                };
+                // The state key is empty unless it comes before the JSON object.
+                let rest = rest.trim_start();
+                let (state_key, json) = if rest.starts_with('{') {
+                    ("", rest)
+                } else {
+                    rest.split_once(char::is_whitespace).ok_or_else(|| {
+                        anyhow!("Usage: /set-state <event-type> [state-key] <json>")
+                    })?
+                };
+                let content = serde_json::from_str(json)
+                    .map_err(|error| anyhow!("Invalid JSON content: {error}"))?;
+                send_custom_event(
+                    &self.joined_room("").await?,
+                    event_type,
+                    content,
+                    Some(state_key),
+                )
+                .await?;
+            }
+            "/whoami" => {
+                let session_file = self.config.read().await.session_file.clone();
+                show_current_user(&self.client, &session_file).await?;
+            }
+            "/relogin" => {
+                let Some(session_file) = self.expired_session.session_file() else {
+                    println!("The session didn't expire");
+                    return Ok(ControlFlow::Continue(()));
+                };
+                relogin(&self.client, &session_file).await?;
+                self.expired_session.renewed();
+            }
+            "/openid-token" => {
+                let args = CommandArgs::parse(args, &["--full"]);
+                let token = get_openid_token(&self.client).await?;
+                show_openid_token(&token, args.flag("--full"));
+            }
+            "/switch" => {
+                if args.is_empty() {
+                    bail!("Usage: /switch <room-id-or-alias>");
+                }
+                let room = self.joined_room(args).await?;
+                self.switch_room(&room).await?;
+            }
+            "/join" => {
+                if args.is_empty() {
+                    bail!("Usage: /join <room-id|alias|matrix:-URI>");
+                }
+                let (target, via) = if args.starts_with(MATRIX_URI_PREFIX) {
+                    let uri = parse_matrix_uri(args)?;
+                    match uri.target {
+                        MatrixUriTarget::Room(room) => (room, uri.via),
+                        MatrixUriTarget::Event {
+                            room: Some(room), ..
+                        } => (room, uri.via),
+                        target => bail!("The URI points to the {target}, not to a room"),
+                    }
+                } else {
+                    (RoomOrAliasId::parse(args)?, Vec::new())
+                };
+
+                let room = self.client.join_room_by_id_or_alias(&target, &via).await?;
+                self.switch_room(&room).await?;
+            }
+            "/contacts" => {
+                if let Some(room_id) = list_contacts(&self.client).await? {
+                    let room = self.joined_room(room_id.as_str()).await?;
+                    self.switch_room(&room).await?;
+                }
+            }
+            "/ping" => {
+                let latency = if args.is_empty() {
+                    ping_homeserver(&self.client).await?
+                } else {
+                    ping_federation_server(<&ServerName>::try_from(args)?).await?
+                };
+                println!("Pong! {} ms", latency.as_millis());
+            }
+            "/version" => println!("{}", show_version_info()),
+            "/matrix-version" => list_unstable_features(&self.client).await?,
+            "/report-bug" => {
+                let report_file =
+                    generate_bug_report(&self.client, &*self.config.read().await).await?;
+                println!(
+                    "Bug report written to {}, please attach it to your bug report.",
+                    report_file.to_string_lossy()
+                );
+            }
+            "/stats" => show_room_stats(&self.joined_room(args).await?).await?,
+            "/who-can" => {
+                if args.is_empty() {
+                    bail!("Usage: /who-can <kick|ban|redact|invite|event-type>");
+                }
+                let room = self.joined_room("").await?;
+                let user_ids = who_can(&room, args).await?;
+                for user_id in &user_ids {
+                    let name = self.display_names.get(&room, user_id).await;
+                    println!("{name} ({user_id})");
+                }
+                println!("{} members can {args}", user_ids.len());
+            }
+            "/invite-link" => {
+                let mut args = CommandArgs::parse(args, &["--copy"]);
+                let link = generate_invite_link(&self.joined_room(args.take_room()).await?).await?;
+                println!("{link}");
+                if args.flag("--copy") {
+                    copy_to_clipboard(&link)?;
+                }
+            }
+            "/copy-room-id" => {
+                let room = self.joined_room(args).await?;
+                copy_to_clipboard(room.room_id().as_str())?;
+            }
+            "/copy-user-id" => {
+                let user_id = if args.is_empty() {
+                    self.client
+                        .user_id()
+                        .ok_or_else(|| anyhow!("The client is not logged in"))?
+                        .to_owned()
+                } else {
+                    self.parse_user_id(args)?
+                };
+                copy_to_clipboard(user_id.as_str())?;
+            }
+            "/space-explore" => {
+                let args = CommandArgs::parse(args, &[]);
+                let Some(space) = args.positional(0) else {
+                    bail!("Usage: /space-explore <room-id> [--depth N]");
+                };
+                let depth = args
+                    .option("--depth")
+                    .map(str::parse)
+                    .transpose()?
+                    .unwrap_or(DEFAULT_SPACE_DEPTH);
+                explore_space(&self.client, &RoomId::parse(space)?, depth).await?;
+            }
+            "/creation-info" => show_room_creation(&self.joined_room(args).await?).await?,
+            "/event-count" => {
+                let room = self.joined_room(args).await?;
+                println!("Counting the events of the whole history, this may take a while in large rooms…");
+                let count = count_room_events(&room).await?;
+                println!("{count} events since the creation of the room");
+            }
+            "/room-info" => show_room_info(&self.joined_room(args).await?).await?,
+            "/stats-global" => {
+                let config = self.config.read().await;
+                show_global_stats(&self.client, &config, &self.timeline_cache).await?;
+            }
+            "/perms" => show_power_level_requirements(&self.joined_room(args).await?).await?,
+            "/room-version" => {
+                let room = self.joined_room(args).await?;
+                match room_version(&room).await? {
+                    Some(version) => println!("Room version: {version}"),
+                    None => println!("Room version: unknown"),
+                }
+                if let Some(suggestion) = check_room_version(&room, &self.client).await? {
+                    println!("{suggestion}");
+                }
+            }
+            "/upgrade" => {
+                let mut args = CommandArgs::parse(args, &[]);
+                let room = self.joined_room(args.take_room()).await?;
+                let version = args
+                    .positional(0)
+                    .map(RoomVersionId::try_from)
+                    .transpose()?;
+
+                let target = version
+                    .as_ref()
+                    .map_or_else(|| "the recommended version".to_owned(), |v| format!("v{v}"));
+                if !confirm(&format!(
+                    "Upgrade {} to {target}? The members will have to join the new room.",
+                    room.room_id()
+                ))? {
+                    return Ok(ControlFlow::Continue(()));
+                }
+
+                let new_room_id = upgrade_room(&room, version).await?;
+                println!("Upgraded to {new_room_id}");
+            }
+            "/follow-upgrade" => {
+                let room = self.joined_room(args).await?;
+                let new_room_id = follow_tombstone(&room).await?;
+                println!("Joined {new_room_id}");
+            }
+            "/history" => {
+                let mut args = CommandArgs::parse(args, &[]);
+                let room = self.joined_room(args.take_room()).await?;
+                let count = args.positional(0).map(str::parse).transpose()?;
+                let display_config = self.config.read().await.display.clone();
+                show_last_messages(
+                    &room,
+                    count.unwrap_or(DEFAULT_HISTORY_COUNT),
+                    None,
+                    &display_config,
+                    &self.display_names,
+                )
+                .await?;
+            }
+            "/expand" => {
+                let mut args = CommandArgs::parse(args, &[]);
+                let room = self.joined_room(args.take_room()).await?;
+                let Some(event_id) = args.positional(0) else {
+                    bail!("Usage: /expand [room] <event-id>");
+                };
+
+                self.expand_event(&room, <&EventId>::try_from(event_id)?)
+                    .await?;
+            }
+            "/event-json" | "/copy" => {
+                let args = CommandArgs::parse(args, &["--copy"]);
+                let Some(event_id) = args.positional(0) else {
+                    bail!("Usage: {command} <event-id>");
+                };
+                let room = self.joined_room("").await?;
+                let json =
+                    copy_event_json(&room, &self.timeline_cache, <&EventId>::try_from(event_id)?)
+                        .await?;
+
+                if command == "/copy" || args.flag("--copy") {
+                    copy_to_clipboard(&json)?;
+                } else {
+                    println!("{json}");
+                }
+            }
+            "/play" => {
+                let mut args = CommandArgs::parse(args, &[]);
+                let room = self.joined_room(args.take_room()).await?;
+                let Some(event_id) = args.positional(0) else {
+                    bail!("Usage: /play [room] <event-id>");
+                };
+                play_media(&room, <&EventId>::try_from(event_id)?).await?;
+            }
+            "/media-list" => {
+                let page = if args.is_empty() {
+                    1
+                } else {
+                    args.parse::<usize>()
+                        .ok()
+                        .filter(|page| *page > 0)
+                        .ok_or_else(|| anyhow!("Usage: /media-list [page]"))?
+                };
+                let room = self.joined_room("").await?;
+                let media = list_room_media(room.room_id(), &self.timeline_cache);
+                if media.is_empty() {
+                    println!("No media received in this room");
+                    return Ok(ControlFlow::Continue(()));
+                }
+
+                let pages = media.len().div_ceil(MEDIA_LIST_PAGE_SIZE);
+                if page > pages {
+                    bail!("There are only {pages} pages");
+                }
+                let start = (page - 1) * MEDIA_LIST_PAGE_SIZE;
+                for (index, item) in media
+                    .iter()
+                    .enumerate()
+                    .skip(start)
+                    .take(MEDIA_LIST_PAGE_SIZE)
+                {
+                    item.show(index + 1);
+                }
+                if page < pages {
+                    println!("Page {page}/{pages}, /media-list {} for more", page + 1);
+                }
+            }
+            "/download-all-media" => {
+                let dest_dir = if args.is_empty() {
+                    std::env::current_dir()?
+                } else {
+                    PathBuf::from(args)
+                };
+                fs::create_dir_all(&dest_dir).await?;
+
+                let room = self.joined_room("").await?;
+                let media = list_room_media(room.room_id(), &self.timeline_cache);
+                let client = room.client();
+                for (index, item) in media.iter().enumerate() {
+                    match download_media(&client, item, &dest_dir).await {
+                        Ok(path) => println!("{}/{} {}", index + 1, media.len(), path.display()),
+                        Err(error) => println!("Could not download {}: {error}", item.event_id),
+                    }
+                }
+            }
+            "/logout-all" => {
+                if !confirm("Log out all the other sessions of this account?")? {
+                    return Ok(ControlFlow::Continue(()));
+                }
+                let revoked = logout_all_other_sessions(&self.client).await?;
+                println!("Revoked {} sessions", revoked.len());
+            }
+            "/devices" => list_devices(&self.client, &self.parse_user_id(args)?).await?,
+            "/verify-accept" => {
+                let auto_cross_sign = self.config.read().await.auto_cross_sign_on_verification;
+                self.pending_verifications
+                    .accept(&self.client, auto_cross_sign)
+                    .await?;
+            }
+            "/verify-reject" => self.pending_verifications.reject().await?,
+            "/trust" | "/untrust" | "/block" | "/unblock" => {
+                let args = CommandArgs::parse(args, &[]);
+                let (Some(user_id), Some(device_id)) = (args.positional(0), args.positional(1))
+                else {
+                    bail!("Usage: {command} <user-id> <device-id>");
+                };
+                let user_id = self.parse_user_id(user_id)?;
+                let device_id = <&DeviceId>::from(device_id);
+
+                match command {
+                    "/trust" => trust_device(&self.client, &user_id, device_id).await?,
+                    "/untrust" => untrust_device(&self.client, &user_id, device_id).await?,
+                    "/block" => block_device(&self.client, &user_id, device_id).await?,
+                    _ => unblock_device(&self.client, &user_id, device_id).await?,
+                }
+            }
+            "/recent" => {
+                let count = if args.is_empty() {
+                    DEFAULT_RECENT_COUNT
+                } else {
+                    args.parse()
+                        .map_err(|_| anyhow!("Usage: /recent [count]"))?
+                };
+                show_recent_across_rooms(&self.client, count).await?;
+            }
+            "/server-notices" => match find_server_notice_room(&self.client).await? {
+                Some(room) => self.switch_room(&room).await?,
+                None => println!("No server notices"),
+            },
+            "/mark-read" => {
+                let room = self.joined_room(args).await?;
+                let Some(latest) = self.timeline_cache.events(room.room_id()).pop() else {
+                    bail!("No event received in this room since the client started");
+                };
+                let receipt_type = self.config.read().await.receipt_type;
+                update_fully_read(&room, &latest.event_id, receipt_type).await?;
+            }
+            "/summarize" => summarize_since_last_read(&self.joined_room(args).await?).await?,
+            "/pending-invites" => {
+                list_sent_invites(&self.joined_room(args).await?).await?;
+            }
+            "/rescind-invite" => {
+                let room = self.joined_room("").await?;
+                let user_id = self.parse_user_id(args)?;
+                rescind_invite(&room, &user_id).await?;
+                println!("The invite of {user_id} was rescinded");
+            }
+            "/grep" => {
+                let args = CommandArgs::parse(args, &["--case-insensitive"]);
+                let pattern = args.positional.join(" ");
+                if pattern.is_empty() {
+                    bail!("Usage: /grep [--case-insensitive] [--sender <user-id>] <pattern>");
+                }
+                let room = self.joined_room("").await?;
+                let sender = args
+                    .option("--sender")
+                    .map(|sender| self.parse_user_id(sender))
+                    .transpose()?;
+
+                let matches = search_local(
+                    &pattern,
+                    room.room_id(),
+                    &self.timeline_cache,
+                    args.flag("--case-insensitive"),
+                    sender.as_deref(),
+                )?;
+                for matched in &matches {
+                    matched.show();
+                }
+                println!("{} matching messages", matches.len());
+            }
+            "/join-history" => show_join_history(&self.joined_room(args).await?).await?,
+            "/joined-since" => {
+                let mut args = CommandArgs::parse(args, &[]);
+                let room = self.joined_room(args.take_room()).await?;
+                let Some(since) = args.positional(0) else {
+                    bail!("Usage: /joined-since [room] <ISO-date>");
+                };
+
+                let members = members_joined_since(&room, parse_date(since)?).await?;
+                if members.is_empty() {
+                    println!("Nobody joined since {since}");
+                }
+                for member in members {
+                    let name = member.display_name.as_deref().unwrap_or("");
+                    println!(
+                        "{}  {:<30} {name}",
+                        format_date(member.joined_at),
+                        member.user_id.as_str()
+                    );
+                }
+            }
+            "/export-history" => {
+                let mut args = CommandArgs::parse(args, &[]);
+                let room = self.joined_room(args.take_room()).await?;
+                let Some(file) = args.positional(0) else {
+                    bail!("Usage: /export-history [room] <file> [--format csv|json] [--since <ISO-date>]");
+                };
+                let format = args
+                    .option("--format")
+                    .map(str::parse)
+                    .transpose()?
+                    .unwrap_or_default();
+                let since = args.option("--since").map(parse_date).transpose()?;
+
+                let exported = export_room_history(&room, Path::new(file), format, since).await?;
+                println!("{exported} events exported to {file} as {format}");
+            }
+            "/acl" => show_acl(&self.joined_room(args).await?).await?,
+            "/acl-set" => {
+                let mut args = CommandArgs::parse(args, &["--allow-ip-literals"]);
+                let room = self.joined_room(args.take_room()).await?;
+                let mut allow = args.options("--allow");
+                // Denying every server by default is surely not what the user wants.
+                if allow.is_empty() {
+                    allow.push("*");
+                }
+
+                set_acl(
+                    &room,
+                    &allow,
+                    &args.options("--deny"),
+                    args.flag("--allow-ip-literals"),
+                )
+                .await?;
+                println!("Server ACL updated");
+            }
+            "/acl-audit" => {
+                let room = self.joined_room(args).await?;
+                let data_dir = self.config.read().await.data_dir.clone();
+                let warnings = audit_room_acl(&room, &data_dir).await?;
+                if warnings.is_empty() {
+                    println!("No issue found with the server ACL");
+                }
+                for warning in warnings {
+                    println!("Warning: {warning}");
+                }
+            }
+            "/federation-info" => {
+                let room = self.joined_room(args).await?;
+                show_federated_servers(list_federated_servers(&room).await?);
+            }
+            "/timeline-export" => {
+                if args.is_empty() {
+                    bail!("Usage: /timeline-export <file>");
+                }
+                let room = self.joined_room("").await?;
+                let exported =
+                    export_timeline_json(&room, &self.timeline_cache, Path::new(args)).await?;
+                println!("{exported} events exported to {args}");
+            }
+            "/transfer-history" => {
+                let args = CommandArgs::parse(args, &[]);
+                let (Some(source), Some(dest)) = (args.positional(0), args.positional(1)) else {
+                    bail!("Usage: /transfer-history <source-room-id> <dest-room-id> [--limit N]");
+                };
+                let limit = args.option("--limit").map(str::parse).transpose()?;
+
+                transfer_history(
+                    &self.client,
+                    &RoomId::parse(source)?,
+                    &RoomId::parse(dest)?,
+                    limit.unwrap_or(DEFAULT_TRANSFER_LIMIT),
+                )
+                .await?;
+            }
+            "/resolve" => {
+                let resolved = resolve_alias(&self.client, <&RoomAliasId>::try_from(args)?).await?;
+                let servers: Vec<_> = resolved.servers.iter().map(|s| s.as_str()).collect();
+                println!("Room ID:  {}", resolved.room_id);
+                println!("Via:      {}", servers.join(", "));
+            }
+            "/create" => {
+                let args = CommandArgs::parse(args, &[]);
+                let name = args.positional.join(" ");
+                if name.is_empty() {
+                    bail!("Usage: /create [--preset private|public|trusted] [--invite <user-id>]… <name>");
+                }
+                let preset = args
+                    .option("--preset")
+                    .map(str::parse)
+                    .transpose()?
+                    .unwrap_or_default();
+                let invite = args
+                    .options("--invite")
+                    .into_iter()
+                    .map(|user_id| self.parse_user_id(user_id))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+
+                create_room(&self.client, &name, preset, invite).await?;
+            }
+            "/leave-muted" => {
+                let left = leave_muted_rooms(&self.client).await?;
+                println!("Left {} rooms", left.len());
+            }
+            // The filter is updated when the new ignore list is received from
+            // the sync.
+            "/ignore" => {
+                let user_id = self.parse_user_id(args)?;
+                self.client.account().ignore_user(&user_id).await?;
+                println!("{user_id} is now ignored");
+            }
+            "/unignore" => {
+                let user_id = self.parse_user_id(args)?;
+                self.client.account().unignore_user(&user_id).await?;
+                println!("{user_id} is no longer ignored");
+            }
+            "/ignored" => list_ignored_users(&self.client).await?,
+            "/watch-user" | "/unwatch-user" => {
+                let user_id = self.parse_user_id(args)?;
+                let mut config = self.config.write().await;
+                if command == "/watch-user" {
+                    if !config.watched_users.watch(user_id.clone()) {
+                        bail!("{user_id} is already watched");
+                    }
+                    println!("You will be notified when {user_id} joins one of your rooms");
+                } else {
+                    if !config.watched_users.unwatch(&user_id) {
+                        bail!("{user_id} is not watched");
+                    }
+                    println!("{user_id} is no longer watched");
+                }
+                config.save(&config.file()).await?;
+            }
+            "/watching" => self.config.read().await.watched_users.show(),
+            "/filter-sender" | "/unfilter-sender" => {
+                let user_id = self.parse_user_id(args)?;
+                let mut config = self.config.write().await;
+                if command == "/filter-sender" {
+                    config.filter.blocked_senders.insert(user_id);
+                } else {
+                    config.filter.blocked_senders.remove(&user_id);
+                }
+                config.save(&config.file()).await?;
+            }
+            "/filter-type" | "/unfilter-type" => {
+                if args.is_empty() {
+                    bail!("An event type is required");
+                }
+                let mut config = self.config.write().await;
+                if command == "/filter-type" {
+                    config.filter.blocked_types.insert(args.to_owned());
+                } else {
+                    config.filter.blocked_types.remove(args);
+                }
+                config.save(&config.file()).await?;
+            }
+            "/filters" => self.config.read().await.filter.show(),
+            "/set-receipt-type" => {
+                let receipt_type: ReceiptType = args.parse()?;
+                let mut config = self.config.write().await;
+                config.receipt_type = receipt_type;
+                config.save(&config.file()).await?;
+                println!("Read receipts are {receipt_type}");
+            }
+            "/image-mode" => {
+                let image_mode: ImageMode = args.parse()?;
+                self.config.write().await.override_image_mode(image_mode);
+                println!("Images are displayed with the {image_mode} mode");
+            }
+            "/save-settings" => {
+                let config = self.config.read().await;
+                let path = config.file();
+                let changes = config.diff_with_file(&path).await?;
+                if changes.is_empty() {
+                    println!("The settings didn't change since the last save");
+                    return Ok(ControlFlow::Continue(()));
+                }
+
+                config.save(&path).await?;
+                for change in &changes {
+                    println!("{change}");
+                }
+                println!("{} settings saved to {}", changes.len(), path.display());
+            }
+            "/mentions-only" => {
+                let enabled = match args {
+                    "" | "on" => true,
+                    "off" => false,
+                    _ => bail!("Usage: /mentions-only [off]"),
+                };
+                let mut config = self.config.write().await;
+                config.mention_filter.enabled = enabled;
+                let hidden_count = config.mention_filter.take_hidden_count();
+                config.save(&config.file()).await?;
+
+                if enabled {
+                    println!("Only the messages mentioning you are shown");
+                } else {
+                    println!("All messages are shown ({hidden_count} messages were hidden)");
+                }
+            }
+            "/listen-room" => {
+                let room = self.joined_room(args).await?;
+                self.bot.listen(room.room_id());
+                println!("Listening to bot commands in {}", room.room_id());
+            }
+            "/bridge" => {
+                let args = CommandArgs::parse(args, &[]);
+                let (Some(source), Some(dest)) = (args.positional(0), args.positional(1)) else {
+                    bail!("Usage: /bridge <source-room-id> <dest-room-id>");
+                };
+                let (source, dest) = (RoomId::parse(source)?, RoomId::parse(dest)?);
+                let bridge = bridge_rooms(&self.client, source.clone(), dest.clone()).await?;
+                if let Some(previous) = self.bridges.insert(source.clone(), bridge) {
+                    previous.abort();
+                }
+                println!("Bridging the messages of {source} to {dest}");
+            }
+            "/unbridge" => {
+                let source = RoomId::parse(args)?;
+                let Some(bridge) = self.bridges.remove(&source) else {
+                    bail!("{source} is not bridged");
+                };
+                bridge.abort();
+                println!("Stopped bridging {source}");
+            }
+            "/history-clear" => {
+                self.editor.clear_history()?;
+                if self.history_file.exists() {
+                    fs::remove_file(&self.history_file).await?;
+                }
+                println!("Command history cleared");
+            }
+            "/compact-db" => {
+                println!("Compacting the state store, this can take a while…");
+                let data_dir = self.config.read().await.data_dir.clone();
+                let freed = compact_state_store(&data_dir).await?;
+                println!("Freed {}", format_size(freed));
+            }
+            "/clear-cache" => {
+                if !confirm("Delete the local state store? The client will exit.")? {
+                    return Ok(ControlFlow::Continue(()));
+                }
+                let data_dir = self.config.read().await.data_dir.clone();
+                schedule_state_cache_clear(&data_dir).await?;
+                println!("The state cache will be cleared when the client starts again");
+                return Ok(ControlFlow::Break(ReplExit::Quit));
+            }
+            "/switch-server" => {
+                if args.is_empty() {
+                    bail!("Usage: /switch-server <homeserver-url>");
+                }
+                return Ok(ControlFlow::Break(ReplExit::SwitchServer(args.to_owned())));
+            }
+            "/detach" => return Ok(ControlFlow::Break(ReplExit::Detach)),
+            "/quit" => return Ok(ControlFlow::Break(ReplExit::Quit)),
+            _ => println!("Unknown command {command}, type /help to list the commands."),
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Parse a user ID argument, the users of our homeserver can be written
+    /// with their username only.
+    fn parse_user_id(&self, input: &str) -> anyhow::Result<OwnedUserId> {
+        let user_id = self
+            .client
+            .user_id()
+            .ok_or_else(|| anyhow!("The client is not logged in"))?;
+        parse_mxid(input, user_id.server_name())
+    }
+
+    /// Display the whole event with the given ID.
+    async fn expand_event(&self, room: &Room, event_id: &EventId) -> anyhow::Result<()> {
+        let event = room.event(event_id).await?;
+        let mut display_config = self.config.read().await.display.clone();
+        display_config.max_message_display_length = 0;
+        let shield = compute_shield(event.encryption_info.as_ref(), room, &self.client).await;
+        println!(
+            "{shield}{}",
+            render_timeline_event(
+                &event.event.deserialize()?,
+                &event.event,
+                room,
+                &display_config,
+                &self.display_names
+            )
+            .await
+        );
+
+        Ok(())
+    }
+
+    /// Switch to the room of the URI, and display the event it points to.
+    ///
+    /// The room must be joined already.
+    async fn open_matrix_uri(&mut self, uri: MatrixUri) -> anyhow::Result<()> {
+        match uri.target {
+            MatrixUriTarget::Room(room) => {
+                let room = self.joined_room(room.as_str()).await?;
+                self.switch_room(&room).await?;
+            }
+            MatrixUriTarget::Event { room, event_id } => {
+                let room = match room {
+                    Some(room) => {
+                        let room = self.joined_room(room.as_str()).await?;
+                        self.switch_room(&room).await?;
+                        room
+                    }
+                    None => self.joined_room("").await?,
+                };
+                self.expand_event(&room, &event_id).await?;
+            }
+            MatrixUriTarget::User(user_id) => bail!("{user_id} is a user, not a room"),
+        }
+
+        Ok(())
+    }
+
+    /// Make the given room the current room and display its latest messages.
+    async fn switch_room(&mut self, room: &Room) -> anyhow::Result<()> {
+        let room_name = match room.canonical_alias() {
+            Some(alias) => alias.to_string(),
+            None => room.display_name().await?.to_string(),
+        };
+
+        self.update_scroll_position();
+        self.current_room = Some(room.room_id().to_owned());
+        self.prompt = format!("[{room_name}]{PROMPT}");
+        if let Some(completer) = self.editor.helper_mut() {
+            completer.current_room = self.current_room.clone();
+        }
+
+        let version = room_version(room)
+            .await?
+            .map_or_else(|| "unknown".to_owned(), |version| version.to_string());
+        println!(
+            "── {room_name} ({}) · room version {version}",
+            room.room_id()
+        );
+        if let Some(suggestion) = check_room_version(room, &self.client).await? {
+            println!("{suggestion}");
+        }
+        self.active_users.set_current_room(room.room_id());
+
+        let display_config = self.config.read().await.display.clone();
+        let latest = show_last_messages(
+            room,
+            DEFAULT_HISTORY_COUNT,
+            self.scroll_state.last_read(room.room_id()),
+            &display_config,
+            &self.display_names,
+        )
+        .await?;
+        if let Some(latest) = latest {
+            self.scroll_state
+                .set_last_read(room.room_id().to_owned(), latest);
+        }
+
+        Ok(())
+    }
+
+    /// Remember the latest event received in the current room as read, since
+    /// it was displayed.
+    fn update_scroll_position(&mut self) {
+        let Some(room_id) = self.current_room.clone() else {
+            return;
+        };
+        if let Some(latest) = self.timeline_cache.events(&room_id).pop() {
+            self.scroll_state.set_last_read(room_id, latest.event_id);
+        }
+    }
+
+    /// Get the joined room with the given ID or alias, or the current room if
+    /// `room` is empty.
+    async fn joined_room(&self, room: &str) -> anyhow::Result<Room> {
+        let room_id = if room.is_empty() {
+            self.current_room
+                .clone()
+                .ok_or_else(|| anyhow!("No room selected, use /switch <room> first"))?
+        } else if room.starts_with('#') {
+            resolve_alias(&self.client, <&RoomAliasId>::try_from(room)?)
+                .await?
+                .room_id
+        } else {
+            RoomId::parse(room)?
+        };
+
+        self.client
+            .get_room(&room_id)
+            .filter(|room| room.state() == RoomState::Joined)
+            .ok_or_else(|| anyhow!("You are not a member of {room_id}"))
+    }
+}
+
+/// Completes the word under the cursor with the names of commands, room IDs,
+/// room aliases or user IDs.
+#[derive(Helper, Hinter, Highlighter, Validator)]
+struct MatrixCompleter {
+    client: Client,
+
+    /// The room whose members and events are completed.
+    current_room: Option<OwnedRoomId>,
+
+    /// The events whose IDs are completed.
+    timeline_cache: TimelineCache,
+
+    /// The number of recent events whose IDs are completed.
+    completed_events_count: usize,
+}
+
+impl MatrixCompleter {
+    /// The candidates to complete a word starting with the given character.
+    fn candidates(&self, sigil: char, is_first_word: bool) -> Vec<String> {
+        let rooms = self.client.joined_rooms();
+
+        match sigil {
+            '/' if is_first_word => COMMANDS
+                .iter()
+                .map(|(command, _)| (*command).to_owned())
+                .collect(),
+            '!' => rooms
+                .iter()
+                .map(|room| room.room_id().to_string())
+                .collect(),
+            '#' => rooms
+                .iter()
+                .flat_map(|room| room.canonical_alias().into_iter().chain(room.alt_aliases()))
+                .map(|alias| alias.to_string())
+                .collect(),
+            '@' => {
+                let Some(room) = self
+                    .current_room
+                    .as_deref()
+                    .and_then(|room_id| self.client.get_room(room_id))
+                else {
+                    return Vec::new();
+                };
+
+                // Completion is called while the REPL is blocked waiting for input.
+                tokio::runtime::Handle::current()
+                    .block_on(room.members_no_sync(RoomMemberships::JOIN))
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|member| member.user_id().to_string())
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The IDs of the most recent events of the current room, displayed with
+    /// their sender and the start of their body.
+    fn event_candidates(&self) -> Vec<Pair> {
+        let Some(room_id) = &self.current_room else {
+            return Vec::new();
+        };
+
+        // The events that could not be decrypted have no body to recognize them.
+        self.timeline_cache
+            .events(room_id)
+            .into_iter()
+            .rev()
+            .filter(|event| !event.is_undecryptable())
+            .take(self.completed_events_count)
+            .map(|event| {
+                let excerpt: String = event.body.chars().take(EVENT_EXCERPT_LENGTH).collect();
+                Pair {
+                    display: format!(
+                        "{} ({}: {excerpt})",
+                        event.event_id,
+                        event.sender.localpart()
+                    ),
+                    replacement: event.event_id.to_string(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Completer for MatrixCompleter {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        // Only the current word is completed.
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map_or(0, |idx| idx + 1);
+        let word = &line[start..pos];
+        let Some(sigil) = word.chars().next() else {
+            return Ok((start, Vec::new()));
+        };
+
+        if sigil == '$' {
+            let pairs = self
+                .event_candidates()
+                .into_iter()
+                .filter(|pair| pair.replacement.starts_with(word))
+                .collect();
+            return Ok((start, pairs));
+        }
+
+        let pairs = self
+            .candidates(sigil, start == 0)
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+
+        Ok((start, pairs))
+    }
+}
+
+/// The arguments of a command, split into positional arguments and options.
+struct CommandArgs<'a> {
+    /// The arguments that are not options.
+    positional: Vec<&'a str>,
+
+    /// The options and their values, in the order they were given.
+    options: HashMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> CommandArgs<'a> {
+    /// Parse the given arguments.
+    ///
+    /// Options start with `--` and take the following argument as their value,
+    /// unless they are listed in `switches`.
+    fn parse(args: &'a str, switches: &[&str]) -> Self {
+        let mut positional = Vec::new();
+        let mut options: HashMap<_, Vec<_>> = HashMap::new();
+
+        let mut words = args.split_whitespace();
+        while let Some(word) = words.next() {
+            if switches.contains(&word) {
+                options.entry(word).or_default();
+            } else if word.starts_with("--") {
+                let values = options.entry(word).or_default();
+                values.extend(words.next());
+            } else {
+                positional.push(word);
+            }
+        }
+
+        Self {
+            positional,
+            options,
+        }
+    }
+
+    /// Remove and return the first positional argument if it is a room ID or
+    /// alias, or return an empty string to use the current room.
+    fn take_room(&mut self) -> &'a str {
+        match self.positional.first() {
+            Some(arg) if arg.starts_with(['!', '#']) => self.positional.remove(0),
+            _ => "",
+        }
+    }
+
+    /// The positional argument at the given index.
+    fn positional(&self, index: usize) -> Option<&'a str> {
+        self.positional.get(index).copied()
+    }
+
+    /// The last value of the given option.
+    fn option(&self, name: &str) -> Option<&'a str> {
+        self.options.get(name)?.last().copied()
+    }
+
+    /// All the values of the given option.
+    fn options(&self, name: &str) -> Vec<&'a str> {
+        self.options.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Whether the given switch or option is present.
+    fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+}
+
+/// Print the list of commands.
+fn print_help() {
+    for (command, description) in COMMANDS {
+        println!("{command:<20} {description}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommandArgs;
+
+    #[test]
+    fn parse_positional_and_options() {
+        let args = CommandArgs::parse("!room:example.org 10 --format csv --since 2023-01-31", &[]);
+        assert_eq!(args.positional, ["!room:example.org", "10"]);
+        assert_eq!(args.option("--format"), Some("csv"));
+        assert_eq!(args.option("--since"), Some("2023-01-31"));
+        assert_eq!(args.option("--until"), None);
+    }
+
+    #[test]
+    fn parse_switches() {
+        let args = CommandArgs::parse("--copy $event --full", &["--copy", "--full"]);
+        assert_eq!(args.positional, ["$event"]);
+        assert!(args.flag("--copy"));
+        assert!(args.flag("--full"));
+        assert_eq!(args.option("--copy"), None);
+    }
+
+    #[test]
+    fn parse_repeated_options() {
+        let args = CommandArgs::parse("--sender @a:b --sender @c:d", &[]);
+        assert_eq!(args.options("--sender"), ["@a:b", "@c:d"]);
+        // The last value wins when a single value is expected.
+        assert_eq!(args.option("--sender"), Some("@c:d"));
+    }
+
+    #[test]
+    fn parse_option_without_value() {
+        let args = CommandArgs::parse("--depth", &[]);
+        assert!(args.flag("--depth"));
+        assert_eq!(args.option("--depth"), None);
+        assert!(args.positional.is_empty());
+    }
+
+    #[test]
+    fn take_room() {
+        let mut args = CommandArgs::parse("#room:example.org 5", &[]);
+        assert_eq!(args.take_room(), "#room:example.org");
+        assert_eq!(args.positional, ["5"]);
+
+        let mut args = CommandArgs::parse("5", &[]);
+        assert_eq!(args.take_room(), "");
+        assert_eq!(args.positional, ["5"]);
+    }
+}
@@ -0,0 +1,25 @@
+use matrix_sdk::{
+    ruma::{OwnedRoomId, OwnedServerName, RoomAliasId},
+    Client,
+};
+
+/// The room an alias points to.
+#[derive(Debug, Clone)]
+pub struct ResolvedAlias {
+    /// The ID of the room.
+    pub room_id: OwnedRoomId,
+
+    /// Servers that know about the room, to route a join request or a
+    /// permalink via.
+    pub servers: Vec<OwnedServerName>,
+}
+
+/// Ask the homeserver which room the given alias points to.
+pub async fn resolve_alias(client: &Client, alias: &RoomAliasId) -> anyhow::Result<ResolvedAlias> {
+    let response = client.resolve_room_alias(alias).await?;
+
+    Ok(ResolvedAlias {
+        room_id: response.room_id,
+        servers: response.servers,
+    })
+}
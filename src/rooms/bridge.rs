@@ -0,0 +1,74 @@
+use anyhow::{bail, ensure};
+use matrix_sdk::{
+    ruma::{
+        events::room::message::{
+            MessageType, OriginalSyncRoomMessageEvent, RoomMessageEventContent,
+        },
+        OwnedRoomId,
+    },
+    Client, RoomState,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::warn;
+
+/// The prefix of the messages re-sent by a bridge.
+///
+/// The messages starting with it are not re-sent, so two rooms bridged to each
+/// other don't loop.
+const BRIDGE_PREFIX: &str = "[Bridge";
+
+/// Re-send the text messages received in the source room to the destination
+/// room, like `[Bridge from #source:example.org] @alice:example.org: hello`.
+///
+/// The bridge runs until the returned task is aborted.
+pub async fn bridge_rooms(
+    client: &Client,
+    source: OwnedRoomId,
+    dest: OwnedRoomId,
+) -> anyhow::Result<JoinHandle<()>> {
+    ensure!(source != dest, "A room can't be bridged to itself");
+    let Some(source_room) = client.get_room(&source) else {
+        bail!("Unknown room {source}");
+    };
+    let Some(dest_room) = client
+        .get_room(&dest)
+        .filter(|room| room.state() == RoomState::Joined)
+    else {
+        bail!("You are not a member of {dest}");
+    };
+    let source_name = match source_room.canonical_alias() {
+        Some(alias) => alias.to_string(),
+        None => source.to_string(),
+    };
+
+    let (sender, mut messages) = mpsc::unbounded_channel();
+    let handle =
+        client.add_room_event_handler(&source, move |event: OriginalSyncRoomMessageEvent| {
+            let sender = sender.clone();
+            async move {
+                if let MessageType::Text(text_content) = event.content.msgtype {
+                    // There is no error while the bridge is running.
+                    let _ = sender.send((event.sender, text_content.body));
+                }
+            }
+        });
+    // The handler is removed when the task is aborted.
+    let guard = client.event_handler_drop_guard(handle);
+
+    Ok(tokio::spawn(async move {
+        let _guard = guard;
+        while let Some((sender, body)) = messages.recv().await {
+            if body.starts_with(BRIDGE_PREFIX) {
+                continue;
+            }
+
+            let body = format!("{BRIDGE_PREFIX} from {source_name}] {sender}: {body}");
+            if let Err(error) = dest_room
+                .send(RoomMessageEventContent::text_plain(body))
+                .await
+            {
+                warn!("Error bridging a message from {source} to {dest}: {error}");
+            }
+        }
+    }))
+}
@@ -0,0 +1,101 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+use matrix_sdk::{
+    ruma::{
+        api::client::room::{create_room, Visibility},
+        OwnedUserId,
+    },
+    Client, Room,
+};
+
+/// The presets of the room settings offered by the spec when creating a room.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum RoomPreset {
+    /// Joining requires an invite, the history is visible to the members.
+    #[default]
+    PrivateChat,
+
+    /// Anyone can join, the history is visible to the members.
+    PublicChat,
+
+    /// Like [`RoomPreset::PrivateChat`], but all invited users have the same
+    /// power level as the creator.
+    TrustedPrivateChat,
+}
+
+impl RoomPreset {
+    /// The name of the preset in the spec.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RoomPreset::PrivateChat => "private_chat",
+            RoomPreset::PublicChat => "public_chat",
+            RoomPreset::TrustedPrivateChat => "trusted_private_chat",
+        }
+    }
+
+    /// A description of the settings applied by this preset.
+    fn settings(&self) -> &'static str {
+        match self {
+            RoomPreset::PrivateChat => "invite only, history visible to members",
+            RoomPreset::PublicChat => "anyone can join, history visible to members",
+            RoomPreset::TrustedPrivateChat => {
+                "invite only, history visible to members, invited users are admins"
+            }
+        }
+    }
+}
+
+impl FromStr for RoomPreset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "private" | "private_chat" => Ok(Self::PrivateChat),
+            "public" | "public_chat" => Ok(Self::PublicChat),
+            "trusted" | "trusted_private_chat" => Ok(Self::TrustedPrivateChat),
+            _ => bail!("Unknown room preset {s}, expected `private`, `public` or `trusted`"),
+        }
+    }
+}
+
+impl fmt::Display for RoomPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<RoomPreset> for create_room::v3::RoomPreset {
+    fn from(preset: RoomPreset) -> Self {
+        create_room::v3::RoomPreset::from(preset.as_str())
+    }
+}
+
+/// Create a room with the given name and preset, inviting the given users.
+///
+/// Public rooms are also published in the room directory of the homeserver.
+pub async fn create_room(
+    client: &Client,
+    name: &str,
+    preset: RoomPreset,
+    invite: Vec<OwnedUserId>,
+) -> anyhow::Result<Room> {
+    let mut request = create_room::v3::Request::new();
+    request.name = Some(name.to_owned());
+    request.preset = Some(preset.into());
+    request.invite = invite.clone();
+    if let RoomPreset::PublicChat = preset {
+        request.visibility = Visibility::Public;
+    }
+
+    let room = client.create_room(request).await?;
+
+    println!("Room created: {}", room.room_id());
+    println!("  Name:     {name}");
+    println!("  Preset:   {preset} ({})", preset.settings());
+    for user_id in invite {
+        println!("  Invited:  {user_id}");
+    }
+
+    Ok(room)
+}
@@ -0,0 +1,74 @@
+use matrix_sdk::{
+    ruma::{
+        api::client::profile::get_profile, events::direct::DirectEventContent, OwnedRoomId, UserId,
+    },
+    Client,
+};
+
+use crate::ui_elements::confirm::ask;
+
+/// List the users the current user has direct conversations with, sorted by
+/// display name.
+///
+/// Returns the direct room selected by the user to start a conversation, if
+/// any.
+pub async fn list_contacts(client: &Client) -> anyhow::Result<Option<OwnedRoomId>> {
+    let Some(direct) = client
+        .account()
+        .account_data::<DirectEventContent>()
+        .await?
+    else {
+        println!("No contacts");
+        return Ok(None);
+    };
+
+    let mut contacts = Vec::new();
+    for (user_id, room_ids) in direct.deserialize()?.0 {
+        let Some(room_id) = room_ids.last().cloned() else {
+            continue;
+        };
+        let display_name = contact_display_name(client, &user_id, &room_id).await;
+        contacts.push((display_name, user_id, room_id));
+    }
+    contacts.sort_by_key(|(display_name, ..)| display_name.to_lowercase());
+
+    if contacts.is_empty() {
+        println!("No contacts");
+        return Ok(None);
+    }
+
+    for (idx, (display_name, user_id, room_id)) in contacts.iter().enumerate() {
+        println!("{idx}) {display_name} ({user_id}) in {room_id}");
+    }
+
+    let choice = ask("Type a number to start a conversation, or press Enter:")?;
+    if choice.trim().is_empty() {
+        return Ok(None);
+    }
+    match choice.trim().parse::<usize>() {
+        Ok(idx) if idx < contacts.len() => Ok(Some(contacts.swap_remove(idx).2)),
+        _ => {
+            println!("This is not a valid choice");
+            Ok(None)
+        }
+    }
+}
+
+/// The display name of a contact, from the direct room or their profile.
+async fn contact_display_name(client: &Client, user_id: &UserId, room_id: &OwnedRoomId) -> String {
+    if let Some(room) = client.get_room(room_id) {
+        if let Ok(Some(member)) = room.get_member_no_sync(user_id).await {
+            if let Some(display_name) = member.display_name() {
+                return display_name.to_owned();
+            }
+        }
+    }
+
+    match client
+        .send(get_profile::v3::Request::new(user_id.to_owned()), None)
+        .await
+    {
+        Ok(profile) => profile.displayname.unwrap_or_else(|| user_id.to_string()),
+        Err(_) => user_id.to_string(),
+    }
+}
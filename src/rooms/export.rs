@@ -0,0 +1,314 @@
+use std::{
+    fmt,
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use matrix_sdk::{
+    room::MessagesOptions,
+    ruma::{
+        events::{
+            room::message::RoomMessageEventContent, AnyMessageLikeEvent, AnyTimelineEvent,
+            MessageLikeEvent,
+        },
+        MilliSecondsSinceUnixEpoch, RoomId,
+    },
+    Client, Room, RoomState,
+};
+use serde_json::json;
+use tokio::{
+    fs::{self, File},
+    io::{AsyncWriteExt, BufWriter},
+    time::sleep,
+};
+
+use super::{format_date, timeline::TimelineCache};
+
+/// The file formats the room history can be exported to.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    #[default]
+    Json,
+
+    /// One CSV row per event.
+    Csv,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => bail!("Unknown export format {s}, expected `json` or `csv`"),
+        }
+    }
+}
+
+/// Format the timestamp of an exported event as an RFC 3339 date and time in
+/// UTC, with the milliseconds, like `2023-01-31T12:00:00.123Z`.
+///
+/// Unlike the dates displayed in the terminal, it doesn't depend on the
+/// timezone of the machine and keeps the order of the events of the same
+/// minute.
+fn export_timestamp(ts: MilliSecondsSinceUnixEpoch) -> String {
+    match Utc.timestamp_millis_opt(ts.get().into()).single() {
+        Some(date) => date.to_rfc3339_opts(SecondsFormat::Millis, true),
+        None => "unknown".to_owned(),
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::Json => write!(f, "json"),
+            ExportFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+/// Export the history of the room to the given file, from the most recent event
+/// back to the creation of the room, or to `since` if it is set.
+///
+/// Returns the number of exported events.
+pub async fn export_room_history(
+    room: &Room,
+    path: &Path,
+    format: ExportFormat,
+    since: Option<SystemTime>,
+) -> anyhow::Result<u64> {
+    let mut file = BufWriter::new(File::create(path).await?);
+    if let ExportFormat::Csv = format {
+        file.write_all(b"timestamp,sender,event_type,body_summary\n")
+            .await?;
+    }
+
+    let mut exported = 0;
+    let mut from = None;
+
+    'pagination: loop {
+        let messages = room
+            .messages(MessagesOptions::backward().from(from.as_deref()))
+            .await?;
+
+        for timeline_event in messages.chunk {
+            let Ok(event) = timeline_event.event.deserialize() else {
+                continue;
+            };
+
+            if since.is_some_and(|since| event.origin_server_ts().to_system_time() < Some(since)) {
+                break 'pagination;
+            }
+
+            let line = match format {
+                ExportFormat::Json => json!({
+                    "timestamp": export_timestamp(event.origin_server_ts()),
+                    "sender": event.sender(),
+                    "event_type": event.event_type().to_string(),
+                    "body_summary": body_summary(&event),
+                })
+                .to_string(),
+                ExportFormat::Csv => [
+                    export_timestamp(event.origin_server_ts()),
+                    event.sender().to_string(),
+                    event.event_type().to_string(),
+                    body_summary(&event),
+                ]
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>()
+                .join(","),
+            };
+            file.write_all(line.as_bytes()).await?;
+            file.write_all(b"\n").await?;
+
+            exported += 1;
+            if exported % 100 == 0 {
+                println!("{exported} events exported…");
+            }
+        }
+
+        match messages.end {
+            Some(end) => from = Some(end),
+            // We reached the start of the room.
+            None => break,
+        }
+    }
+
+    file.flush().await?;
+
+    Ok(exported)
+}
+
+/// The delay between two messages sent by [`transfer_history()`], to avoid being
+/// rate-limited.
+const TRANSFER_DELAY: Duration = Duration::from_millis(200);
+
+/// Copy the last `limit` messages of the source room to the destination room.
+///
+/// Each message is sent by the current user, with a header containing the
+/// source room and the original sender and timestamp.
+pub async fn transfer_history(
+    client: &Client,
+    source_room: &RoomId,
+    dest_room: &RoomId,
+    limit: u32,
+) -> anyhow::Result<()> {
+    let joined_room = |room_id: &RoomId| {
+        client
+            .get_room(room_id)
+            .filter(|room| room.state() == RoomState::Joined)
+            .ok_or_else(|| anyhow!("You are not a member of {room_id}"))
+    };
+    let source = joined_room(source_room)?;
+    let dest = joined_room(dest_room)?;
+
+    // Collect the messages from the most recent one.
+    let mut messages = Vec::new();
+    let mut from = None;
+    'pagination: loop {
+        // The messages of encrypted rooms are only known to be messages once
+        // they are decrypted, they can't be filtered by type on the server.
+        let chunk = source
+            .messages(MessagesOptions::backward().from(from.as_deref()))
+            .await?;
+
+        for timeline_event in chunk.chunk {
+            let Ok(AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+                MessageLikeEvent::Original(event),
+            ))) = timeline_event.event.deserialize()
+            else {
+                continue;
+            };
+            messages.push(event);
+
+            if messages.len() >= limit as usize {
+                break 'pagination;
+            }
+        }
+
+        match chunk.end {
+            Some(end) => from = Some(end),
+            None => break,
+        }
+    }
+
+    let source_name = source
+        .canonical_alias()
+        .map_or_else(|| source_room.to_string(), |alias| alias.to_string());
+    let total = messages.len();
+
+    // Send them in chronological order.
+    for (idx, event) in messages.into_iter().rev().enumerate() {
+        let body = format!(
+            "[Imported from {source_name}] {} ({}): {}",
+            event.sender,
+            format_date(event.origin_server_ts),
+            event.content.body()
+        );
+        dest.send(RoomMessageEventContent::text_plain(body)).await?;
+
+        if (idx + 1) % 10 == 0 {
+            println!("{}/{total} messages transferred…", idx + 1);
+        }
+        sleep(TRANSFER_DELAY).await;
+    }
+
+    println!("{total} messages transferred");
+
+    Ok(())
+}
+
+/// A short text describing the content of the event.
+pub fn body_summary(event: &AnyTimelineEvent) -> String {
+    match event {
+        AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+            MessageLikeEvent::Original(event),
+        )) => event.content.body().to_owned(),
+        AnyTimelineEvent::MessageLike(event) if event.original_content().is_none() => {
+            "(redacted)".to_owned()
+        }
+        _ => String::new(),
+    }
+}
+
+/// Escape a field of a CSV row.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Export the events of the room received since the client started to the
+/// given file, as a JSON array.
+///
+/// Each event comes with the display name of its sender when it was received
+/// and its decryption status. Decrypted events are exported with their
+/// decrypted content and how they were encrypted, the encrypted content is not
+/// kept by the SDK. Events that could not be decrypted are exported encrypted.
+///
+/// Returns the number of exported events.
+pub async fn export_timeline_json(
+    room: &Room,
+    cache: &TimelineCache,
+    path: &Path,
+) -> anyhow::Result<usize> {
+    let events = cache.events(room.room_id());
+
+    let mut exported = Vec::with_capacity(events.len());
+    for event in &events {
+        let decryption = match (&event.encryption_info, event.is_undecryptable()) {
+            (Some(_), _) => "decrypted",
+            (None, true) => "undecryptable",
+            (None, false) => "unencrypted",
+        };
+
+        exported.push(json!({
+            "event_id": event.event_id,
+            "sender": event.sender,
+            "sender_display_name": event.sender_name,
+            "origin_server_ts": event.origin_server_ts,
+            "decryption": decryption,
+            "encryption_info": event.encryption_info,
+            "event": event.raw,
+        }));
+    }
+
+    fs::write(path, serde_json::to_string_pretty(&exported)?).await?;
+
+    Ok(exported.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::ruma::{MilliSecondsSinceUnixEpoch, UInt};
+
+    use super::{csv_field, export_timestamp};
+
+    #[test]
+    fn csv_field_plain() {
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("hello world"), "hello world");
+    }
+
+    #[test]
+    fn csv_field_escaped() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn export_timestamp_is_utc_with_milliseconds() {
+        let ts = MilliSecondsSinceUnixEpoch(UInt::new(1_675_166_400_123).unwrap());
+        assert_eq!(export_timestamp(ts), "2023-01-31T12:00:00.123Z");
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::{anyhow, bail};
+use matrix_sdk::{
+    ruma::{
+        events::room::{
+            member::MembershipState,
+            power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
+        },
+        OwnedUserId, UserId,
+    },
+    Room, RoomMemberships,
+};
+
+use super::state_event;
+
+/// List the users who have been invited to the room and have not joined yet,
+/// except the current user.
+pub async fn list_sent_invites(room: &Room) -> anyhow::Result<Vec<OwnedUserId>> {
+    let own_user_id = room.client().user_id().map(ToOwned::to_owned);
+
+    let mut invitees = Vec::new();
+    for member in room.members_no_sync(RoomMemberships::INVITE).await? {
+        if Some(member.user_id()) == own_user_id.as_deref() {
+            continue;
+        }
+
+        match member.display_name() {
+            Some(display_name) => println!("{} ({display_name})", member.user_id()),
+            None => println!("{}", member.user_id()),
+        }
+        invitees.push(member.user_id().to_owned());
+    }
+
+    if invitees.is_empty() {
+        println!("No pending invites");
+    }
+
+    Ok(invitees)
+}
+
+/// Rescind the invite of the given user, by kicking them before they join.
+pub async fn rescind_invite(room: &Room, user_id: &UserId) -> anyhow::Result<()> {
+    let is_invited = room
+        .get_member_no_sync(user_id)
+        .await?
+        .is_some_and(|member| *member.membership() == MembershipState::Invite);
+    if !is_invited {
+        bail!("{user_id} has no pending invite in this room");
+    }
+
+    let client = room.client();
+    let own_user_id = client
+        .user_id()
+        .ok_or_else(|| anyhow!("The client is not logged in"))?;
+    let power_levels: RoomPowerLevels = state_event::<RoomPowerLevelsEventContent>(room)
+        .await?
+        .map(|event| event.content)
+        .unwrap_or_default()
+        .into();
+    if !power_levels.user_can_kick(own_user_id) {
+        bail!("You don't have the power level required to rescind invites in this room");
+    }
+
+    room.kick_user(user_id, Some("Invite rescinded")).await?;
+
+    Ok(())
+}
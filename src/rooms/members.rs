@@ -0,0 +1,154 @@
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use matrix_sdk::{
+    deserialized_responses::RawSyncOrStrippedState,
+    room::MessagesOptions,
+    ruma::{
+        events::{
+            room::member::{MembershipChange, RoomMemberEventContent},
+            AnyStateEvent, AnyTimelineEvent, StateEvent, SyncStateEvent,
+        },
+        MilliSecondsSinceUnixEpoch, OwnedUserId,
+    },
+    Room,
+};
+
+use super::format_date;
+
+/// Display the changes of membership of the current user in the room: when they
+/// were invited and by whom, when they joined and when they left.
+pub async fn show_join_history(room: &Room) -> anyhow::Result<()> {
+    let user_id = room
+        .client()
+        .user_id()
+        .ok_or_else(|| anyhow!("The client is not logged in"))?
+        .to_owned();
+
+    // The history is paginated backwards, from the most recent event.
+    let mut history = Vec::new();
+    let mut from = None;
+    loop {
+        let mut options = MessagesOptions::backward().from(from.as_deref());
+        options.filter.types = Some(vec!["m.room.member".to_owned()]);
+        let messages = room.messages(options).await?;
+
+        for timeline_event in messages.chunk {
+            let Ok(AnyTimelineEvent::State(AnyStateEvent::RoomMember(StateEvent::Original(event)))) =
+                timeline_event.event.deserialize()
+            else {
+                continue;
+            };
+            if event.state_key == user_id {
+                history.push(event);
+            }
+        }
+
+        match messages.end {
+            Some(end) => from = Some(end),
+            None => break,
+        }
+    }
+    history.reverse();
+
+    if history.is_empty() {
+        println!("No membership event found for {user_id} in this room");
+    }
+
+    for event in &history {
+        let sender = &event.sender;
+        let change = match event.membership_change() {
+            // Changes of display name or avatar are not changes of membership.
+            MembershipChange::ProfileChanged { .. } | MembershipChange::None => continue,
+            MembershipChange::Invited | MembershipChange::KnockAccepted => {
+                format!("invited by {sender}")
+            }
+            MembershipChange::Joined | MembershipChange::InvitationAccepted => "joined".to_owned(),
+            MembershipChange::Left => "left".to_owned(),
+            MembershipChange::InvitationRejected => "rejected the invitation".to_owned(),
+            MembershipChange::InvitationRevoked => format!("invitation revoked by {sender}"),
+            MembershipChange::Kicked => format!("removed by {sender}"),
+            MembershipChange::Banned | MembershipChange::KickedAndBanned => {
+                format!("banned by {sender}")
+            }
+            MembershipChange::Unbanned => format!("unbanned by {sender}"),
+            MembershipChange::Knocked => "knocked".to_owned(),
+            MembershipChange::KnockRetracted => "retracted the knock".to_owned(),
+            MembershipChange::KnockDenied => format!("knock denied by {sender}"),
+            _ => event.content.membership.to_string(),
+        };
+        let reason = event
+            .content
+            .reason
+            .as_ref()
+            .map(|reason| format!(" (reason: {reason})"))
+            .unwrap_or_default();
+
+        println!("{}  {change}{reason}", format_date(event.origin_server_ts));
+    }
+
+    let membership = match room.get_member_no_sync(&user_id).await? {
+        Some(member) => member.membership().to_string(),
+        None => "unknown".to_owned(),
+    };
+    println!("Current membership: {membership}");
+
+    Ok(())
+}
+
+/// A member of a room and when they joined.
+#[derive(Debug, Clone)]
+pub struct MemberJoinInfo {
+    /// The ID of the member.
+    pub user_id: OwnedUserId,
+
+    /// The display name of the member in the room, if any.
+    pub display_name: Option<String>,
+
+    /// When the member joined the room.
+    pub joined_at: MilliSecondsSinceUnixEpoch,
+}
+
+/// List the members who joined the room after the given date, sorted by join
+/// time.
+///
+/// Only the current membership of the members is known, a member whose last
+/// membership event is a change of profile is not listed.
+pub async fn members_joined_since(
+    room: &Room,
+    since: SystemTime,
+) -> anyhow::Result<Vec<MemberJoinInfo>> {
+    // Make sure all the members are known, they are lazy-loaded.
+    room.sync_members().await?;
+
+    let since = MilliSecondsSinceUnixEpoch::from_system_time(since)
+        .ok_or_else(|| anyhow!("The date is out of range"))?;
+
+    let mut members = Vec::new();
+    for raw_event in room
+        .get_state_events_static::<RoomMemberEventContent>()
+        .await?
+    {
+        let RawSyncOrStrippedState::Sync(raw_event) = raw_event else {
+            continue;
+        };
+        let Ok(SyncStateEvent::Original(event)) = raw_event.deserialize() else {
+            continue;
+        };
+
+        let joined = matches!(
+            event.membership_change(),
+            MembershipChange::Joined | MembershipChange::InvitationAccepted
+        );
+        if joined && event.origin_server_ts >= since {
+            members.push(MemberJoinInfo {
+                user_id: event.state_key,
+                display_name: event.content.displayname,
+                joined_at: event.origin_server_ts,
+            });
+        }
+    }
+    members.sort_by_key(|member| member.joined_at);
+
+    Ok(members)
+}
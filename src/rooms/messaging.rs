@@ -0,0 +1,78 @@
+use anyhow::bail;
+use matrix_sdk::{
+    ruma::{
+        events::{
+            room::{message::RoomMessageEventContent, ImageInfo},
+            sticker::StickerEventContent,
+        },
+        MxcUri,
+    },
+    Room,
+};
+
+/// Send a notice to the room.
+///
+/// Notices are meant for automated messages: clients don't notify about them
+/// and bots are not supposed to answer them, which prevents loops.
+pub async fn send_notice(room: &Room, body: &str) -> anyhow::Result<()> {
+    room.send(RoomMessageEventContent::notice_plain(body))
+        .await?;
+
+    Ok(())
+}
+
+/// Send an emote to the room, a message describing an action in the third
+/// person like `/me` on IRC.
+pub async fn send_emote(room: &Room, body: &str) -> anyhow::Result<()> {
+    room.send(RoomMessageEventContent::emote_plain(body))
+        .await?;
+
+    Ok(())
+}
+
+/// Send a sticker to the room.
+///
+/// The sticker must already be uploaded to the media repository, `alt_text` is
+/// displayed by clients that can't show images.
+pub async fn send_sticker(
+    room: &Room,
+    mxc_uri: &MxcUri,
+    alt_text: &str,
+    info: ImageInfo,
+) -> anyhow::Result<()> {
+    room.send(StickerEventContent::new(
+        alt_text.to_owned(),
+        info,
+        mxc_uri.to_owned(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Send an event of any type to the room.
+///
+/// The event is a state event if `state_key` is set, and a timeline event
+/// otherwise.
+pub async fn send_custom_event(
+    room: &Room,
+    event_type: &str,
+    content: serde_json::Value,
+    state_key: Option<&str>,
+) -> anyhow::Result<()> {
+    if !content.is_object() {
+        bail!("The content of an event must be a JSON object");
+    }
+
+    match state_key {
+        Some(state_key) => {
+            room.send_state_event_raw(content, event_type, state_key)
+                .await?;
+        }
+        None => {
+            room.send_raw(content, event_type).await?;
+        }
+    }
+
+    Ok(())
+}
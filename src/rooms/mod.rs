@@ -0,0 +1,327 @@
+pub mod aliases;
+pub mod bridge;
+pub mod create;
+pub mod direct;
+pub mod export;
+pub mod invites;
+pub mod members;
+pub mod messaging;
+pub mod moderation;
+pub mod permalinks;
+pub mod receipts;
+pub mod search;
+pub mod send;
+pub mod settings;
+pub mod state;
+pub mod stats;
+pub mod timeline;
+pub mod upgrade;
+
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use matrix_sdk::{
+    deserialized_responses::RawSyncOrStrippedState,
+    ruma::{
+        events::{
+            push_rules::PushRulesEventContent, room::create::RoomCreateEventContent, tag::TagName,
+            EmptyStateKey, OriginalSyncStateEvent, RedactContent, RedactedStateEventContent,
+            StaticEventContent, StaticStateEventContent, SyncStateEvent,
+        },
+        push::Action,
+        MilliSecondsSinceUnixEpoch, OwnedRoomId,
+    },
+    Client, Room,
+};
+
+use self::{state::list_federated_servers, stats::cached_event_count};
+use crate::ui_elements::confirm::confirm;
+
+/// Get the state event of the given type with an empty state key.
+///
+/// Returns `None` if the room doesn't have this state event or if it was
+/// redacted.
+pub async fn state_event<C>(room: &Room) -> anyhow::Result<Option<OriginalSyncStateEvent<C>>>
+where
+    C: StaticEventContent + StaticStateEventContent<StateKey = EmptyStateKey> + RedactContent,
+    C::Redacted: RedactedStateEventContent,
+{
+    let Some(RawSyncOrStrippedState::Sync(raw_event)) = room.get_state_event_static::<C>().await?
+    else {
+        return Ok(None);
+    };
+
+    match raw_event.deserialize()? {
+        SyncStateEvent::Original(event) => Ok(Some(event)),
+        SyncStateEvent::Redacted(_) => Ok(None),
+    }
+}
+
+/// Format a timestamp as a date and time in the local timezone.
+pub fn format_date(ts: MilliSecondsSinceUnixEpoch) -> String {
+    match Local.timestamp_millis_opt(ts.get().into()).single() {
+        Some(date) => date.format("%Y-%m-%d %H:%M").to_string(),
+        None => "unknown".to_owned(),
+    }
+}
+
+/// Parse a date in the ISO 8601 format, either a full date and time with a
+/// timezone like `2023-01-31T12:00:00+01:00` or a date like `2023-01-31` which is
+/// interpreted as midnight in the local timezone.
+pub fn parse_date(input: &str) -> anyhow::Result<SystemTime> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(input) {
+        return Ok(date.into());
+    }
+
+    let date = NaiveDate::parse_from_str(input, "%Y-%m-%d")?;
+    let midnight = Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0).expect("midnight is a valid time"))
+        .earliest()
+        .ok_or_else(|| anyhow!("{input} doesn't exist in the local timezone"))?;
+
+    Ok(midnight.into())
+}
+
+/// Leave all the joined rooms that are marked as low priority or muted, after
+/// confirmation from the user.
+///
+/// Returns the rooms that were left successfully.
+pub async fn leave_muted_rooms(client: &Client) -> anyhow::Result<Vec<OwnedRoomId>> {
+    // A room is muted with an override push rule with the room ID as rule ID and
+    // no notify action.
+    let push_rules = client
+        .account()
+        .account_data::<PushRulesEventContent>()
+        .await?
+        .map(|raw| raw.deserialize())
+        .transpose()?
+        .map(|content| content.global);
+    let is_muted = |room: &Room| {
+        push_rules.as_ref().is_some_and(|rules| {
+            rules.override_.iter().any(|rule| {
+                rule.enabled
+                    && rule.rule_id == room.room_id().as_str()
+                    && !rule
+                        .actions
+                        .iter()
+                        .any(|action| matches!(action, Action::Notify))
+            })
+        })
+    };
+
+    let mut rooms = Vec::new();
+    for room in client.joined_rooms() {
+        let is_low_priority = room
+            .tags()
+            .await?
+            .is_some_and(|tags| tags.contains_key(&TagName::LowPriority));
+        if is_low_priority || is_muted(&room) {
+            rooms.push(room);
+        }
+    }
+
+    if rooms.is_empty() {
+        println!("No low priority or muted room to leave");
+        return Ok(Vec::new());
+    }
+
+    println!("The following rooms are low priority or muted:");
+    for room in &rooms {
+        let room_name = match room.display_name().await {
+            Ok(room_name) => room_name.to_string(),
+            Err(_) => room.room_id().to_string(),
+        };
+        println!("  {room_name} ({})", room.room_id());
+    }
+    if !confirm(&format!("Leave these {} rooms?", rooms.len()))? {
+        return Ok(Vec::new());
+    }
+
+    let mut left = Vec::new();
+    for room in rooms {
+        match room.leave().await {
+            Ok(()) => left.push(room.room_id().to_owned()),
+            Err(error) => println!("Error leaving {}: {error}", room.room_id()),
+        }
+    }
+
+    Ok(left)
+}
+
+/// Whether the room is where the homeserver sends its server notices.
+pub async fn is_server_notice_room(room: &Room) -> anyhow::Result<bool> {
+    Ok(room
+        .tags()
+        .await?
+        .is_some_and(|tags| tags.contains_key(&TagName::ServerNotice)))
+}
+
+/// Find the joined room tagged with `m.server_notice`, where the homeserver
+/// sends its administrative messages.
+pub async fn find_server_notice_room(client: &Client) -> anyhow::Result<Option<Room>> {
+    for room in client.joined_rooms() {
+        if is_server_notice_room(&room).await? {
+            return Ok(Some(room));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Display all the metadata of the room.
+pub async fn show_room_info(room: &Room) -> anyhow::Result<()> {
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| "none".to_owned());
+
+    let create_event = state_event::<RoomCreateEventContent>(room).await?;
+    let alt_aliases: Vec<_> = room.alt_aliases().iter().map(ToString::to_string).collect();
+    let tags = room
+        .tags()
+        .await?
+        .map(|tags| {
+            tags.keys()
+                .map(|tag| tag.as_ref().to_owned())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let name = match room.display_name().await {
+        Ok(name) => name.to_string(),
+        Err(_) => or_none(room.name()),
+    };
+
+    let rows = [
+        ("Room ID", room.room_id().to_string()),
+        ("Name", name),
+        ("Topic", or_none(room.topic())),
+        (
+            "Canonical alias",
+            or_none(room.canonical_alias().map(|alias| alias.to_string())),
+        ),
+        (
+            "Alternative aliases",
+            if alt_aliases.is_empty() {
+                "none".to_owned()
+            } else {
+                alt_aliases.join(", ")
+            },
+        ),
+        (
+            "Avatar",
+            or_none(room.avatar_url().map(|url| url.to_string())),
+        ),
+        (
+            "Room version",
+            or_none(
+                create_event
+                    .as_ref()
+                    .map(|event| event.content.room_version.to_string()),
+            ),
+        ),
+        ("Join rule", room.join_rule().as_str().to_owned()),
+        (
+            "History visibility",
+            room.history_visibility().as_str().to_owned(),
+        ),
+        ("Guest access", room.guest_access().as_str().to_owned()),
+        (
+            "Encrypted",
+            if room.is_encrypted().await? {
+                "yes"
+            } else {
+                "no"
+            }
+            .to_owned(),
+        ),
+        (
+            "Created",
+            or_none(
+                create_event
+                    .as_ref()
+                    .map(|event| format_date(event.origin_server_ts)),
+            ),
+        ),
+        (
+            "Creator",
+            or_none(create_event.as_ref().map(|event| event.sender.to_string())),
+        ),
+        ("Members", room.joined_members_count().to_string()),
+        (
+            "Events",
+            cached_event_count(room.room_id()).map_or_else(
+                || "not counted, see /event-count".to_owned(),
+                |count| count.to_string(),
+            ),
+        ),
+        (
+            "Servers",
+            list_federated_servers(room).await?.len().to_string(),
+        ),
+        (
+            "Notifications",
+            or_none(
+                room.notification_mode()
+                    .await
+                    .map(|mode| format!("{mode:?}")),
+            ),
+        ),
+        (
+            "Tags",
+            if tags.is_empty() {
+                "none".to_owned()
+            } else {
+                tags.join(", ")
+            },
+        ),
+        (
+            "Direct message",
+            if room.is_direct().await? { "yes" } else { "no" }.to_owned(),
+        ),
+    ];
+
+    for (label, value) in rows {
+        println!("{label:<20} {value}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use chrono::{Local, NaiveDate, TimeZone};
+
+    use super::parse_date;
+
+    #[test]
+    fn parse_date_with_timezone() {
+        assert_eq!(
+            parse_date("2023-01-31T12:00:00+01:00").unwrap(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_675_162_800)
+        );
+    }
+
+    #[test]
+    fn parse_date_without_time() {
+        let midnight = NaiveDate::from_ymd_opt(2023, 1, 31)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let expected: SystemTime = Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .unwrap()
+            .into();
+
+        assert_eq!(parse_date("2023-01-31").unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_invalid_date() {
+        assert!(parse_date("").is_err());
+        assert!(parse_date("yesterday").is_err());
+        assert!(parse_date("2023-02-30").is_err());
+        assert!(parse_date("2023-01-31 12:00").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::{fmt, io, path::Path};
+
+use anyhow::{anyhow, bail};
+use matrix_sdk::{
+    ruma::{events::room::server_acl::RoomServerAclEventContent, ServerName},
+    Room,
+};
+use tokio::fs;
+
+use super::state_event;
+use crate::ui_elements::confirm::confirm;
+
+/// Display the server ACL of the room.
+pub async fn show_acl(room: &Room) -> anyhow::Result<()> {
+    let Some(event) = state_event::<RoomServerAclEventContent>(room).await? else {
+        println!("This room has no server ACL, all servers are allowed");
+        return Ok(());
+    };
+    let acl = event.content;
+
+    println!("Allowed servers:    {}", acl.allow.join(", "));
+    println!("Denied servers:     {}", acl.deny.join(", "));
+    println!("Allow IP literals:  {}", acl.allow_ip_literals);
+
+    Ok(())
+}
+
+/// Replace the server ACL of the room.
+///
+/// Asks for confirmation if the new ACL would deny the homeserver of the current
+/// user.
+pub async fn set_acl(
+    room: &Room,
+    allow: &[&str],
+    deny: &[&str],
+    allow_ips: bool,
+) -> anyhow::Result<()> {
+    let acl = RoomServerAclEventContent::new(
+        allow_ips,
+        allow.iter().map(|&server| server.to_owned()).collect(),
+        deny.iter().map(|&server| server.to_owned()).collect(),
+    );
+
+    let client = room.client();
+    let own_server = client
+        .user_id()
+        .ok_or_else(|| anyhow!("The client is not logged in"))?
+        .server_name();
+    if !acl.is_allowed(own_server)
+        && !confirm(&format!(
+            "Warning: this ACL denies your own homeserver {own_server}, you will lose access to the room. Continue?"
+        ))?
+    {
+        bail!("The server ACL was not changed");
+    }
+
+    room.send_state_event(acl).await?;
+
+    Ok(())
+}
+
+/// The name of the file listing known abusive servers, in the data directory.
+///
+/// It contains one server name per line. Empty lines and lines starting with `#`
+/// are ignored.
+const BLOCKED_SERVERS_FILE_NAME: &str = "blocked_servers.txt";
+
+/// A potential issue with the server ACL of a room.
+#[derive(Debug, Clone)]
+pub enum AclWarning {
+    /// The room has no server ACL, all servers are allowed.
+    NoAcl,
+
+    /// The server ACL allows all servers and IP literals.
+    FullyOpen,
+
+    /// A server listed as abusive is not denied.
+    BlockedServerAllowed(String),
+}
+
+impl fmt::Display for AclWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AclWarning::NoAcl => write!(f, "There is no server ACL, all servers are allowed"),
+            AclWarning::FullyOpen => write!(
+                f,
+                "The server ACL allows all servers and IP literals, it has no effect"
+            ),
+            AclWarning::BlockedServerAllowed(server) => {
+                write!(f, "{server} is a known abusive server but it is not denied")
+            }
+        }
+    }
+}
+
+/// The result of the audit of a server ACL.
+pub type AclAuditResult = Vec<AclWarning>;
+
+/// Check whether the server ACL of the room lets in more servers than it
+/// probably should.
+///
+/// The servers listed in `blocked_servers.txt` in the data directory are
+/// expected to be denied.
+pub async fn audit_room_acl(room: &Room, data_dir: &Path) -> anyhow::Result<AclAuditResult> {
+    let Some(event) = state_event::<RoomServerAclEventContent>(room).await? else {
+        return Ok(vec![AclWarning::NoAcl]);
+    };
+    let acl = event.content;
+
+    let mut warnings = Vec::new();
+    if acl.allow == ["*"] && acl.deny.is_empty() && acl.allow_ip_literals {
+        warnings.push(AclWarning::FullyOpen);
+    }
+
+    let blocked_servers = match fs::read_to_string(data_dir.join(BLOCKED_SERVERS_FILE_NAME)).await {
+        Ok(blocked_servers) => blocked_servers,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error.into()),
+    };
+    for server in blocked_servers
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        let is_allowed = match <&ServerName>::try_from(server) {
+            Ok(server_name) => acl.is_allowed(server_name),
+            // This is probably a glob, look for it as is.
+            Err(_) => !acl.deny.iter().any(|denied| denied == server),
+        };
+        if is_allowed {
+            warnings.push(AclWarning::BlockedServerAllowed(server.to_owned()));
+        }
+    }
+
+    Ok(warnings)
+}
@@ -0,0 +1,192 @@
+use std::fmt;
+
+use anyhow::bail;
+use matrix_sdk::{
+    ruma::{
+        EventId, OwnedEventId, OwnedRoomOrAliasId, OwnedServerName, OwnedUserId, RoomOrAliasId,
+        ServerName, UserId,
+    },
+    Room,
+};
+
+use super::state::list_federated_servers;
+
+/// The number of servers added to the invite links of rooms.
+const INVITE_LINK_VIA_COUNT: usize = 3;
+
+/// The prefix of the URIs of the `matrix:` scheme.
+pub const MATRIX_URI_PREFIX: &str = "matrix:";
+
+/// A reference to a room, a user or an event with the `matrix:` scheme.
+#[derive(Debug, Clone)]
+pub struct MatrixUri {
+    /// What the URI points to.
+    pub target: MatrixUriTarget,
+
+    /// The servers to join the room via, from the `via` parameters.
+    pub via: Vec<OwnedServerName>,
+}
+
+/// What a `matrix:` URI points to.
+#[derive(Debug, Clone)]
+pub enum MatrixUriTarget {
+    /// A room, with its ID or alias.
+    Room(OwnedRoomOrAliasId),
+
+    /// A user.
+    User(OwnedUserId),
+
+    /// An event, in the current room if the URI doesn't contain the room.
+    Event {
+        room: Option<OwnedRoomOrAliasId>,
+        event_id: OwnedEventId,
+    },
+}
+
+impl fmt::Display for MatrixUriTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixUriTarget::Room(room) => write!(f, "room {room}"),
+            MatrixUriTarget::User(user_id) => write!(f, "user {user_id}"),
+            MatrixUriTarget::Event {
+                room: Some(room),
+                event_id,
+            } => write!(f, "event {event_id} in {room}"),
+            MatrixUriTarget::Event {
+                room: None,
+                event_id,
+            } => write!(f, "event {event_id}"),
+        }
+    }
+}
+
+/// Parse a URI of the `matrix:` scheme.
+///
+/// Supports `matrix:r/<alias>`, `matrix:roomid/<id>` and `matrix:u/<user>`,
+/// the events of a room with a trailing `/e/<event>`, and `matrix:event/<id>`
+/// for an event of the current room. The identifiers are written without their
+/// sigil, like `matrix:r/room:example.org`.
+pub fn parse_matrix_uri(uri: &str) -> anyhow::Result<MatrixUri> {
+    let Some(rest) = uri.strip_prefix(MATRIX_URI_PREFIX) else {
+        bail!("{uri} is not a matrix: URI");
+    };
+    let rest = rest.split_once('#').map_or(rest, |(rest, _)| rest);
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+    let via = query
+        .split('&')
+        .filter_map(|param| param.strip_prefix("via="))
+        .map(ServerName::parse)
+        .collect::<Result<_, _>>()?;
+
+    let segments: Vec<_> = path.split('/').collect();
+    let target = match segments.as_slice() {
+        ["r", alias] => MatrixUriTarget::Room(RoomOrAliasId::parse(format!("#{alias}"))?),
+        ["roomid", room_id] => MatrixUriTarget::Room(RoomOrAliasId::parse(format!("!{room_id}"))?),
+        ["r", alias, "e", event_id] => MatrixUriTarget::Event {
+            room: Some(RoomOrAliasId::parse(format!("#{alias}"))?),
+            event_id: EventId::parse(format!("${event_id}"))?,
+        },
+        ["roomid", room_id, "e", event_id] => MatrixUriTarget::Event {
+            room: Some(RoomOrAliasId::parse(format!("!{room_id}"))?),
+            event_id: EventId::parse(format!("${event_id}"))?,
+        },
+        ["event", event_id] => MatrixUriTarget::Event {
+            room: None,
+            event_id: EventId::parse(format!("${event_id}"))?,
+        },
+        ["u", user_id] => MatrixUriTarget::User(UserId::parse(format!("@{user_id}"))?),
+        _ => bail!("Unsupported matrix: URI {uri}"),
+    };
+
+    Ok(MatrixUri { target, via })
+}
+
+/// Find the first `matrix:` URI in the text.
+pub fn find_matrix_uri(text: &str) -> Option<&str> {
+    text.split_whitespace()
+        .find(|word| word.starts_with(MATRIX_URI_PREFIX))
+}
+
+/// Generate a `https://matrix.to` link to join the room.
+///
+/// The link uses the canonical alias of the room if it has one, or its ID. The
+/// servers with the most joined members are added as `via` parameters, so the
+/// room can be joined through them.
+pub async fn generate_invite_link(room: &Room) -> anyhow::Result<String> {
+    let target = match room.canonical_alias() {
+        Some(alias) => alias.to_string(),
+        None => room.room_id().to_string(),
+    };
+
+    let mut servers: Vec<_> = list_federated_servers(room).await?.into_iter().collect();
+    servers.sort_unstable_by(|(a_name, a_count), (b_name, b_count)| {
+        b_count.cmp(a_count).then_with(|| a_name.cmp(b_name))
+    });
+    let via = servers
+        .iter()
+        .take(INVITE_LINK_VIA_COUNT)
+        .map(|(server, _)| format!("via={server}"))
+        .collect::<Vec<_>>();
+
+    let mut link = format!("https://matrix.to/#/{target}");
+    if !via.is_empty() {
+        link.push('?');
+        link.push_str(&via.join("&"));
+    }
+
+    Ok(link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_matrix_uri, parse_matrix_uri};
+
+    #[test]
+    fn parse_room_uris() {
+        let uri = parse_matrix_uri("matrix:r/room:example.org").unwrap();
+        assert_eq!(uri.target.to_string(), "room #room:example.org");
+        assert!(uri.via.is_empty());
+
+        let uri = parse_matrix_uri("matrix:roomid/abc:example.org?via=a.org&via=b.org").unwrap();
+        assert_eq!(uri.target.to_string(), "room !abc:example.org");
+        assert_eq!(uri.via, ["a.org", "b.org"]);
+    }
+
+    #[test]
+    fn parse_event_uris() {
+        let uri = parse_matrix_uri("matrix:r/room:example.org/e/event").unwrap();
+        assert_eq!(uri.target.to_string(), "event $event in #room:example.org");
+
+        let uri = parse_matrix_uri("matrix:roomid/abc:example.org/e/event?via=a.org").unwrap();
+        assert_eq!(uri.target.to_string(), "event $event in !abc:example.org");
+        assert_eq!(uri.via, ["a.org"]);
+
+        let uri = parse_matrix_uri("matrix:event/event").unwrap();
+        assert_eq!(uri.target.to_string(), "event $event");
+    }
+
+    #[test]
+    fn parse_user_uri() {
+        let uri = parse_matrix_uri("matrix:u/alice:example.org?action=chat#fragment").unwrap();
+        assert_eq!(uri.target.to_string(), "user @alice:example.org");
+    }
+
+    #[test]
+    fn parse_invalid_uris() {
+        assert!(parse_matrix_uri("https://matrix.to/#/#room:example.org").is_err());
+        assert!(parse_matrix_uri("matrix:group/group:example.org").is_err());
+        assert!(parse_matrix_uri("matrix:r/room:example.org/e").is_err());
+        assert!(parse_matrix_uri("matrix:u/alice").is_err());
+        assert!(parse_matrix_uri("matrix:r/room:example.org?via=invalid server").is_err());
+    }
+
+    #[test]
+    fn find_uri_in_text() {
+        assert_eq!(
+            find_matrix_uri("join matrix:r/room:example.org now"),
+            Some("matrix:r/room:example.org")
+        );
+        assert_eq!(find_matrix_uri("no link here"), None);
+    }
+}
@@ -0,0 +1,72 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+use matrix_sdk::{room::Receipts, ruma::EventId, Room};
+use serde::{Deserialize, Serialize};
+
+/// The read receipts sent when marking a room as read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptType {
+    /// Send `m.read` receipts, visible to the other members.
+    #[default]
+    Public,
+
+    /// Send `m.read.private` receipts, only known by the homeserver.
+    Private,
+
+    /// Never send read receipts.
+    Off,
+}
+
+impl fmt::Display for ReceiptType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let receipt_type = match self {
+            ReceiptType::Public => "public",
+            ReceiptType::Private => "private",
+            ReceiptType::Off => "off",
+        };
+        f.write_str(receipt_type)
+    }
+}
+
+impl FromStr for ReceiptType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(ReceiptType::Public),
+            "private" => Ok(ReceiptType::Private),
+            "off" => Ok(ReceiptType::Off),
+            _ => bail!("Unknown receipt type {s}, expected public, private or off"),
+        }
+    }
+}
+
+/// Mark the room as read up to the given event.
+///
+/// This sends a read receipt of the given type, and moves the fully read
+/// marker, which is only visible to the other sessions of the user.
+pub async fn update_fully_read(
+    room: &Room,
+    event_id: &EventId,
+    receipt_type: ReceiptType,
+) -> anyhow::Result<()> {
+    let receipts = Receipts::new().fully_read_marker(event_id.to_owned());
+    let receipts = match receipt_type {
+        ReceiptType::Public => receipts.public_read_receipt(event_id.to_owned()),
+        ReceiptType::Private => receipts.private_read_receipt(event_id.to_owned()),
+        ReceiptType::Off => receipts,
+    };
+    room.send_multiple_receipts(receipts).await?;
+
+    match receipt_type {
+        ReceiptType::Public | ReceiptType::Private => {
+            println!("Sent read receipt ({receipt_type}) for {event_id}");
+        }
+        ReceiptType::Off => println!("Read receipts are off, none was sent"),
+    }
+    println!("Moved fully read marker (private) to {event_id}");
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use crossterm::style::Stylize;
+use matrix_sdk::ruma::{RoomId, UserId};
+use regex::RegexBuilder;
+
+use super::{
+    format_date,
+    timeline::{CachedEvent, TimelineCache},
+};
+
+/// An event matching a search.
+#[derive(Debug)]
+pub struct MatchedEvent {
+    /// The event.
+    pub event: CachedEvent,
+
+    /// The body of the event, with the matches in bold.
+    pub highlighted_body: String,
+}
+
+impl MatchedEvent {
+    /// Display the event.
+    pub fn show(&self) {
+        println!(
+            "{} {}: {}",
+            format_date(self.event.origin_server_ts),
+            self.event.sender,
+            self.highlighted_body
+        );
+    }
+}
+
+/// Search the events of the room received since the client started for
+/// messages whose body matches the regular expression.
+///
+/// If `sender` is set, only the messages of this user are searched.
+pub fn search_local(
+    pattern: &str,
+    room_id: &RoomId,
+    cache: &TimelineCache,
+    case_insensitive: bool,
+    sender: Option<&UserId>,
+) -> anyhow::Result<Vec<MatchedEvent>> {
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()?;
+
+    let matches = cache
+        .events(room_id)
+        .into_iter()
+        .filter(|event| sender.map_or(true, |sender| event.sender == sender))
+        .filter(|event| regex.is_match(&event.body))
+        .map(|event| {
+            let highlighted_body = regex
+                .replace_all(&event.body, |captures: &regex::Captures<'_>| {
+                    captures[0].bold().to_string()
+                })
+                .into_owned();
+
+            MatchedEvent {
+                event,
+                highlighted_body,
+            }
+        })
+        .collect();
+
+    Ok(matches)
+}
@@ -0,0 +1,77 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{anyhow, Context};
+use matrix_sdk::{
+    ruma::{events::room::message::RoomMessageEventContent, OwnedEventId, RoomAliasId, RoomId},
+    Client, Room, RoomState,
+};
+use tokio::{fs, time::sleep};
+
+use crate::{cli::MessageKind, display::formatting::MessageFormat, rooms::aliases::resolve_alias};
+
+/// Send a message to the given room ID or alias without going through the
+/// REPL.
+///
+/// The client must have synced at least once to know the joined rooms.
+pub async fn send_message(
+    client: &Client,
+    room: &str,
+    content: RoomMessageEventContent,
+) -> anyhow::Result<OwnedEventId> {
+    let room = joined_room(client, room).await?;
+
+    Ok(room.send(content).await?.event_id)
+}
+
+/// Send each non-empty line of the file as a separate message of the given
+/// type to the given room ID or alias in the given format, waiting `delay`
+/// between messages.
+///
+/// Stops at the first error. Returns the number of messages sent.
+pub async fn send_lines(
+    client: &Client,
+    room: &str,
+    file: &Path,
+    delay: Duration,
+    kind: MessageKind,
+    format: MessageFormat,
+) -> anyhow::Result<usize> {
+    let room = joined_room(client, room).await?;
+    let content = fs::read_to_string(file).await?;
+
+    let lines: Vec<_> = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .collect();
+    let total = lines.len();
+
+    for (sent, (idx, line)) in lines.into_iter().enumerate() {
+        if sent > 0 {
+            sleep(delay).await;
+        }
+
+        room.send(kind.content(line, format))
+            .await
+            .with_context(|| format!("Failed to send line {}", idx + 1))?;
+        println!("{}/{total} messages sent", sent + 1);
+    }
+
+    Ok(total)
+}
+
+/// Get the joined room with the given ID or alias.
+async fn joined_room(client: &Client, room: &str) -> anyhow::Result<Room> {
+    let room_id = if room.starts_with('#') {
+        resolve_alias(client, <&RoomAliasId>::try_from(room)?)
+            .await?
+            .room_id
+    } else {
+        RoomId::parse(room)?
+    };
+
+    client
+        .get_room(&room_id)
+        .filter(|room| room.state() == RoomState::Joined)
+        .ok_or_else(|| anyhow!("You are not a member of {room_id}"))
+}
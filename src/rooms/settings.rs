@@ -0,0 +1,180 @@
+use anyhow::{anyhow, bail};
+use crossterm::style::Stylize;
+use matrix_sdk::{
+    ruma::{
+        api::client::discovery::get_capabilities,
+        events::{
+            room::{
+                create::RoomCreateEventContent,
+                power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
+            },
+            TimelineEventType,
+        },
+        OwnedUserId, RoomVersionId,
+    },
+    Client, Room, RoomMemberships,
+};
+
+use super::state_event;
+
+/// The event types of messages, that use `events_default` as their default
+/// power level. The other event types are considered state events.
+const MESSAGE_EVENT_TYPES: &[&str] = &[
+    "m.room.message",
+    "m.room.encrypted",
+    "m.room.redaction",
+    "m.reaction",
+    "m.sticker",
+];
+
+/// The power level of room administrators.
+const ADMIN_POWER_LEVEL: i64 = 100;
+
+/// The version of the room, from its creation event.
+pub async fn room_version(room: &Room) -> anyhow::Result<Option<RoomVersionId>> {
+    Ok(state_event::<RoomCreateEventContent>(room)
+        .await?
+        .map(|event| event.content.room_version))
+}
+
+/// The version of new rooms recommended by the homeserver.
+pub async fn recommended_room_version(client: &Client) -> anyhow::Result<RoomVersionId> {
+    Ok(client
+        .send(get_capabilities::v3::Request::new(), None)
+        .await?
+        .capabilities
+        .room_versions
+        .default)
+}
+
+/// Compare the version of the room with the one recommended by the homeserver.
+///
+/// Returns a suggestion to upgrade the room if it is on an older version and
+/// the current user is an administrator of the room.
+pub async fn check_room_version(room: &Room, client: &Client) -> anyhow::Result<Option<String>> {
+    let Some(version) = room_version(room).await? else {
+        return Ok(None);
+    };
+
+    let recommended = recommended_room_version(client).await?;
+
+    // Only the official versions are numbers that can be compared.
+    let is_older = match (
+        version.as_str().parse::<u32>(),
+        recommended.as_str().parse::<u32>(),
+    ) {
+        (Ok(version), Ok(recommended)) => version < recommended,
+        _ => false,
+    };
+    if !is_older {
+        return Ok(None);
+    }
+
+    let user_id = client
+        .user_id()
+        .ok_or_else(|| anyhow!("The client is not logged in"))?;
+    let is_admin = room
+        .get_member_no_sync(user_id)
+        .await?
+        .is_some_and(|member| member.power_level() >= ADMIN_POWER_LEVEL);
+    if !is_admin {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "Room is on v{version}; server recommends v{recommended}. Use /upgrade to update."
+    )))
+}
+
+/// Display the power level required for each common action in the room, and
+/// whether the current user can perform it.
+///
+/// The actions the current user can't perform are highlighted in red.
+pub async fn show_power_level_requirements(room: &Room) -> anyhow::Result<()> {
+    let client = room.client();
+    let user_id = client
+        .user_id()
+        .ok_or_else(|| anyhow!("The client is not logged in"))?;
+    let power_levels: RoomPowerLevels = state_event::<RoomPowerLevelsEventContent>(room)
+        .await?
+        .map(|event| event.content)
+        .unwrap_or_default()
+        .into();
+    let user_level = power_levels.for_user(user_id);
+
+    let event_level = |event_type: TimelineEventType, default| {
+        power_levels
+            .events
+            .get(&event_type)
+            .copied()
+            .unwrap_or(default)
+    };
+    let requirements = [
+        (
+            "m.room.message",
+            event_level(TimelineEventType::RoomMessage, power_levels.events_default),
+        ),
+        (
+            "m.room.name",
+            event_level(TimelineEventType::RoomName, power_levels.state_default),
+        ),
+        ("kick", power_levels.kick),
+        ("ban", power_levels.ban),
+        ("redact", power_levels.redact),
+        ("invite", power_levels.invite),
+        ("state_default", power_levels.state_default),
+        ("events_default", power_levels.events_default),
+    ];
+
+    println!("{:<20} {user_level}", "Your level");
+    for (action, level) in requirements {
+        let row = format!("{action:<20} {level}");
+        if user_level >= level {
+            println!("{row}");
+        } else {
+            println!("{}", row.red());
+        }
+    }
+
+    Ok(())
+}
+
+/// List the joined members of the room whose power level allows them to perform
+/// the given action.
+///
+/// The action is either `kick`, `ban`, `redact`, `invite` or an event type.
+pub async fn who_can(room: &Room, action: &str) -> anyhow::Result<Vec<OwnedUserId>> {
+    let power_levels: RoomPowerLevels = state_event::<RoomPowerLevelsEventContent>(room)
+        .await?
+        .map(|event| event.content)
+        .unwrap_or_default()
+        .into();
+
+    let required_level = match action {
+        "kick" => power_levels.kick,
+        "ban" => power_levels.ban,
+        "redact" => power_levels.redact,
+        "invite" => power_levels.invite,
+        event_type if event_type.contains('.') => {
+            let default = if MESSAGE_EVENT_TYPES.contains(&event_type) {
+                power_levels.events_default
+            } else {
+                power_levels.state_default
+            };
+            power_levels
+                .events
+                .get(&TimelineEventType::from(event_type))
+                .copied()
+                .unwrap_or(default)
+        }
+        _ => bail!("Unknown action {action}, expected kick, ban, redact, invite or an event type"),
+    };
+
+    Ok(room
+        .members(RoomMemberships::JOIN)
+        .await?
+        .into_iter()
+        .filter(|member| power_levels.for_user(member.user_id()) >= required_level)
+        .map(|member| member.user_id().to_owned())
+        .collect())
+}
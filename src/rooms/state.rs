@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+use matrix_sdk::{
+    ruma::{events::room::create::RoomCreateEventContent, EventId, OwnedServerName},
+    Room, RoomMemberships,
+};
+
+use super::{format_date, state_event, timeline::TimelineCache};
+use crate::ui_elements::confirm::confirm;
+
+/// Count the joined members of the room per homeserver.
+///
+/// This is derived from the server names of the members' user IDs, not from a
+/// federation API.
+pub async fn list_federated_servers(
+    room: &Room,
+) -> anyhow::Result<HashMap<OwnedServerName, usize>> {
+    let mut servers: HashMap<OwnedServerName, usize> = HashMap::new();
+    for member in room.members(RoomMemberships::JOIN).await? {
+        *servers
+            .entry(member.user_id().server_name().to_owned())
+            .or_default() += 1;
+    }
+
+    Ok(servers)
+}
+
+/// Display the servers of the joined members of the room, with the most members
+/// first.
+pub fn show_federated_servers(servers: HashMap<OwnedServerName, usize>) {
+    let mut servers: Vec<_> = servers.into_iter().collect();
+    servers.sort_unstable_by(|(a_name, a_count), (b_name, b_count)| {
+        b_count.cmp(a_count).then_with(|| a_name.cmp(b_name))
+    });
+
+    println!("{:<40} Members", "Server");
+    for (server, count) in servers {
+        println!("{:<40} {count}", server.as_str());
+    }
+}
+
+/// Get the JSON of the event, pretty-printed.
+///
+/// The event is taken from the timeline cache if it was received since the
+/// client started, and requested from the homeserver otherwise.
+pub async fn copy_event_json(
+    room: &Room,
+    cache: &TimelineCache,
+    event_id: &EventId,
+) -> anyhow::Result<String> {
+    let cached = cache
+        .events(room.room_id())
+        .into_iter()
+        .find(|event| event.event_id == event_id);
+    let json: serde_json::Value = match cached {
+        Some(event) => event.raw.deserialize_as()?,
+        None => room.event(event_id).await?.event.deserialize_as()?,
+    };
+
+    Ok(serde_json::to_string_pretty(&json)?)
+}
+
+/// Display the details of the `m.room.create` event of the room.
+///
+/// If the room replaces an upgraded room, offer to show a link to the old room.
+pub async fn show_room_creation(room: &Room) -> anyhow::Result<()> {
+    let Some(event) = state_event::<RoomCreateEventContent>(room).await? else {
+        bail!("The creation event of this room is not known");
+    };
+    let content = &event.content;
+
+    let rows = [
+        ("Creator", event.sender.to_string()),
+        ("Created", format_date(event.origin_server_ts)),
+        ("Room version", content.room_version.to_string()),
+        (
+            "Federated",
+            if content.federate { "yes" } else { "no" }.to_owned(),
+        ),
+        (
+            "Room type",
+            content
+                .room_type
+                .as_ref()
+                .map_or_else(|| "none".to_owned(), ToString::to_string),
+        ),
+        (
+            "Predecessor",
+            content.predecessor.as_ref().map_or_else(
+                || "none".to_owned(),
+                |previous| previous.room_id.to_string(),
+            ),
+        ),
+    ];
+    for (label, value) in rows {
+        println!("{label:<20} {value}");
+    }
+
+    if let Some(previous) = &content.predecessor {
+        if confirm("Show a link to the previous room?")? {
+            println!("{}", previous.room_id.matrix_to_uri());
+        }
+    }
+
+    Ok(())
+}
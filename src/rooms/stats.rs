@@ -0,0 +1,120 @@
+use std::sync::OnceLock;
+
+use dashmap::DashMap;
+use matrix_sdk::{
+    room::MessagesOptions,
+    ruma::{
+        events::room::{create::RoomCreateEventContent, power_levels::RoomPowerLevelsEventContent},
+        Int, OwnedRoomId, RoomId,
+    },
+    Room, RoomMemberships,
+};
+
+use super::{format_date, state::list_federated_servers, state_event};
+
+/// The number of pages between two updates of the progress of the event count.
+const PROGRESS_PAGES_INTERVAL: u64 = 100;
+
+/// The number of events of the rooms that were counted during the session.
+static EVENT_COUNTS: OnceLock<DashMap<OwnedRoomId, u64>> = OnceLock::new();
+
+/// Display statistics about the given room as a two-column table.
+pub async fn show_room_stats(room: &Room) -> anyhow::Result<()> {
+    let members = room.members(RoomMemberships::ACTIVE).await?;
+    let servers = list_federated_servers(room).await?;
+
+    let create_event = state_event::<RoomCreateEventContent>(room).await?;
+    let (room_version, created) = match &create_event {
+        Some(event) => (
+            event.content.room_version.to_string(),
+            format_date(event.origin_server_ts),
+        ),
+        None => ("unknown".to_owned(), "unknown".to_owned()),
+    };
+
+    let power_levels = match state_event::<RoomPowerLevelsEventContent>(room).await? {
+        Some(event) => summarize_power_levels(&event.content),
+        None => "not set".to_owned(),
+    };
+
+    let canonical_alias = room
+        .canonical_alias()
+        .map_or_else(|| "none".to_owned(), |alias| alias.to_string());
+
+    let rows = [
+        ("Members", members.len().to_string()),
+        ("Joined", room.joined_members_count().to_string()),
+        ("Invited", room.invited_members_count().to_string()),
+        ("Servers", servers.len().to_string()),
+        ("Room version", room_version),
+        ("Created", created),
+        ("Encrypted", room.is_encrypted().await?.to_string()),
+        ("Canonical alias", canonical_alias),
+        ("Alternative aliases", room.alt_aliases().len().to_string()),
+        ("Power levels", power_levels),
+    ];
+
+    for (label, value) in rows {
+        println!("{label:<20} {value}");
+    }
+
+    Ok(())
+}
+
+/// Summarize the power levels of a room in a single line.
+fn summarize_power_levels(power_levels: &RoomPowerLevelsEventContent) -> String {
+    let count_at_least = |level: i64| {
+        power_levels
+            .users
+            .values()
+            .filter(|user_level| **user_level >= Int::from(level as i32))
+            .count()
+    };
+    let admins = count_at_least(100);
+    let moderators = count_at_least(50) - admins;
+
+    format!(
+        "{admins} admins, {moderators} moderators, default level {}",
+        power_levels.users_default
+    )
+}
+
+/// Count all the events of the room since its creation, by paginating through
+/// the whole history.
+///
+/// This can take a long time in large rooms, so the result is cached for the
+/// session.
+pub async fn count_room_events(room: &Room) -> anyhow::Result<u64> {
+    let counts = EVENT_COUNTS.get_or_init(DashMap::new);
+    if let Some(count) = counts.get(room.room_id()) {
+        return Ok(*count);
+    }
+
+    let mut count = 0;
+    let mut pages = 0;
+    let mut from = None;
+    loop {
+        let messages = room
+            .messages(MessagesOptions::backward().from(from.as_deref()))
+            .await?;
+        count += messages.chunk.len() as u64;
+        pages += 1;
+        if pages % PROGRESS_PAGES_INTERVAL == 0 {
+            println!("{pages} pages fetched, {count} events so far…");
+        }
+
+        match messages.end {
+            Some(end) => from = Some(end),
+            None => break,
+        }
+    }
+
+    counts.insert(room.room_id().to_owned(), count);
+    Ok(count)
+}
+
+/// The number of events of the room, if they were already counted during the
+/// session.
+pub fn cached_event_count(room_id: &RoomId) -> Option<u64> {
+    EVENT_COUNTS.get()?.get(room_id).map(|count| *count)
+}
@@ -0,0 +1,410 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use crossterm::style::Stylize;
+use futures::stream::{self, StreamExt};
+use matrix_sdk::{
+    deserialized_responses::EncryptionInfo,
+    room::MessagesOptions,
+    ruma::{
+        events::{
+            fully_read::FullyReadEventContent,
+            room::{member::MembershipChange, message::Relation},
+            AnyMessageLikeEvent, AnyStateEvent, AnySyncTimelineEvent, AnyTimelineEvent,
+            MessageLikeEvent, StateEvent,
+        },
+        serde::Raw,
+        MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId,
+    },
+    Client, Room,
+};
+
+use super::{export::body_summary, format_date, is_server_notice_room};
+
+/// The number of characters of the messages displayed by `/recent`.
+const RECENT_EXCERPT_LENGTH: usize = 80;
+
+/// The number of events of each room searched for the latest message.
+const RECENT_EVENTS_LOOKUP: u32 = 20;
+
+/// The number of rooms whose latest message is requested at the same time.
+const RECENT_CONCURRENT_REQUESTS: usize = 10;
+
+/// An event received during the sync.
+#[derive(Debug, Clone)]
+pub struct CachedEvent {
+    /// The ID of the event.
+    pub event_id: OwnedEventId,
+
+    /// The sender of the event.
+    pub sender: OwnedUserId,
+
+    /// When the event was sent.
+    pub origin_server_ts: MilliSecondsSinceUnixEpoch,
+
+    /// The body of the message, or an empty string if the event is not a
+    /// message.
+    pub body: String,
+
+    /// The display name of the sender when the event was received.
+    pub sender_name: String,
+
+    /// The raw JSON of the event, decrypted if it was encrypted and could be
+    /// decrypted.
+    pub raw: Raw<AnySyncTimelineEvent>,
+
+    /// How the event was encrypted, if it was decrypted.
+    pub encryption_info: Option<EncryptionInfo>,
+}
+
+impl CachedEvent {
+    /// Whether the event is encrypted and could not be decrypted.
+    pub fn is_undecryptable(&self) -> bool {
+        self.raw
+            .get_field::<String>("type")
+            .ok()
+            .flatten()
+            .is_some_and(|event_type| event_type == "m.room.encrypted")
+    }
+}
+
+/// The latest events received during the sync, per room.
+///
+/// The cache is shared between the event handlers that fill it and the REPL, it
+/// can be cloned cheaply.
+#[derive(Debug, Clone)]
+pub struct TimelineCache {
+    rooms: Arc<RwLock<HashMap<OwnedRoomId, VecDeque<CachedEvent>>>>,
+
+    /// The maximum number of events kept per room.
+    capacity: usize,
+}
+
+impl TimelineCache {
+    /// Create a cache keeping at most `capacity` events per room.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            rooms: Default::default(),
+            capacity,
+        }
+    }
+
+    /// Add an event at the end of the timeline of the room.
+    ///
+    /// An event that is already cached, like an event that is decrypted after
+    /// it was received, replaces the previous one in place. The oldest event of
+    /// the room is dropped if the cache is full.
+    pub fn push(&self, room_id: &RoomId, event: CachedEvent) {
+        let mut rooms = self
+            .rooms
+            .write()
+            .expect("the timeline cache lock is not poisoned");
+        let events = rooms.entry(room_id.to_owned()).or_default();
+
+        if let Some(cached) = events
+            .iter_mut()
+            .find(|cached| cached.event_id == event.event_id)
+        {
+            *cached = event;
+            return;
+        }
+        events.push_back(event);
+        while events.len() > self.capacity {
+            events.pop_front();
+        }
+    }
+
+    /// The number of cached events, in all the rooms.
+    pub fn total_event_count(&self) -> usize {
+        self.rooms
+            .read()
+            .expect("the timeline cache lock is not poisoned")
+            .values()
+            .map(VecDeque::len)
+            .sum()
+    }
+
+    /// The cached events of the room, in chronological order.
+    pub fn events(&self, room_id: &RoomId) -> Vec<CachedEvent> {
+        self.rooms
+            .read()
+            .expect("the timeline cache lock is not poisoned")
+            .get(room_id)
+            .map(|events| events.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// The activity in a room over a period of time.
+#[derive(Debug, Default)]
+struct ActivitySummary {
+    /// The number of messages sent by each user.
+    messages_by_sender: HashMap<String, usize>,
+
+    /// The number of reactions.
+    reactions: usize,
+
+    /// The number of edited messages.
+    edits: usize,
+
+    /// The number of redacted events.
+    redactions: usize,
+
+    /// The number of users who joined.
+    joins: usize,
+
+    /// The number of users who left.
+    leaves: usize,
+}
+
+impl ActivitySummary {
+    /// Count the given event.
+    fn add(&mut self, event: &AnyTimelineEvent) {
+        match event {
+            AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+                MessageLikeEvent::Original(event),
+            )) => {
+                if let Some(Relation::Replacement(_)) = &event.content.relates_to {
+                    self.edits += 1;
+                } else {
+                    *self
+                        .messages_by_sender
+                        .entry(event.sender.to_string())
+                        .or_default() += 1;
+                }
+            }
+            AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::Reaction(_)) => self.reactions += 1,
+            AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomRedaction(_)) => {
+                self.redactions += 1
+            }
+            AnyTimelineEvent::State(AnyStateEvent::RoomMember(StateEvent::Original(event))) => {
+                match event.membership_change() {
+                    MembershipChange::Joined | MembershipChange::InvitationAccepted => {
+                        self.joins += 1
+                    }
+                    MembershipChange::Left
+                    | MembershipChange::Kicked
+                    | MembershipChange::Banned
+                    | MembershipChange::KickedAndBanned => self.leaves += 1,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for ActivitySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: usize = self.messages_by_sender.values().sum();
+        let plural = |count: usize, word: &str| {
+            if count == 1 {
+                format!("{count} {word}")
+            } else {
+                format!("{count} {word}s")
+            }
+        };
+
+        write!(
+            f,
+            "{} from {}, {}, {}, {}, {}, {} since your last read",
+            plural(messages, "message"),
+            plural(self.messages_by_sender.len(), "user"),
+            plural(self.reactions, "reaction"),
+            plural(self.edits, "edit"),
+            plural(self.redactions, "redaction"),
+            plural(self.joins, "new member"),
+            plural(self.leaves, "departure"),
+        )
+    }
+}
+
+/// Print a summary of the activity in the room since the event marked as
+/// fully read by the current user.
+pub async fn summarize_since_last_read(room: &Room) -> anyhow::Result<()> {
+    let fully_read = room
+        .account_data_static::<FullyReadEventContent>()
+        .await?
+        .map(|raw| raw.deserialize())
+        .transpose()?
+        .map(|event| event.content.event_id);
+
+    let mut summary = ActivitySummary::default();
+    let mut from = None;
+    'pagination: loop {
+        let messages = room
+            .messages(MessagesOptions::backward().from(from.as_deref()))
+            .await?;
+
+        for timeline_event in messages.chunk {
+            let Ok(event) = timeline_event.event.deserialize() else {
+                continue;
+            };
+            if fully_read.as_deref() == Some(event.event_id()) {
+                break 'pagination;
+            }
+
+            summary.add(&event);
+        }
+
+        match messages.end {
+            Some(end) => from = Some(end),
+            None => break,
+        }
+    }
+
+    if fully_read.is_none() {
+        println!("No read marker found in this room, summarizing the whole history");
+    }
+    println!("{summary}");
+
+    let mut senders: Vec<_> = summary.messages_by_sender.iter().collect();
+    senders.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+    for (sender, count) in senders {
+        println!("  {sender}: {count}");
+    }
+
+    Ok(())
+}
+
+/// Display the latest message of each joined room, the most recent first, up
+/// to `count` rooms.
+pub async fn show_recent_across_rooms(client: &Client, count: usize) -> anyhow::Result<()> {
+    let rooms = client.joined_rooms();
+    let latest_messages: Vec<_> = stream::iter(&rooms)
+        .map(|room| async move { (room, latest_message(room).await) })
+        .buffer_unordered(RECENT_CONCURRENT_REQUESTS)
+        .collect()
+        .await;
+
+    let mut recent = Vec::new();
+    for (room, message) in latest_messages {
+        match message {
+            Ok(Some(event)) => recent.push((room, event)),
+            Ok(None) => {}
+            Err(error) => println!(
+                "Could not get the latest message of {}: {error}",
+                room.room_id()
+            ),
+        }
+    }
+    recent.sort_unstable_by(|(_, a), (_, b)| b.origin_server_ts().cmp(&a.origin_server_ts()));
+
+    for (room, event) in recent.into_iter().take(count) {
+        let mut room_name = match room.display_name().await {
+            Ok(name) => name.to_string(),
+            Err(_) => room.room_id().to_string(),
+        };
+        if is_server_notice_room(room).await.unwrap_or_default() {
+            room_name = room_name.yellow().to_string();
+        }
+        let body: String = body_summary(&event)
+            .replace('\n', " ")
+            .chars()
+            .take(RECENT_EXCERPT_LENGTH)
+            .collect();
+        println!(
+            "{} [{room_name}] {}: {body}",
+            format_date(event.origin_server_ts()),
+            event.sender()
+        );
+    }
+
+    Ok(())
+}
+
+/// The latest message of the room, among the last events.
+async fn latest_message(room: &Room) -> anyhow::Result<Option<AnyTimelineEvent>> {
+    let mut options = MessagesOptions::backward();
+    options.limit = RECENT_EVENTS_LOOKUP.into();
+    let messages = room.messages(options).await?;
+
+    Ok(messages
+        .chunk
+        .iter()
+        .filter_map(|timeline_event| timeline_event.event.deserialize().ok())
+        .find(|event| {
+            matches!(
+                event,
+                AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(_))
+            )
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::ruma::{
+        owned_event_id, room_id, serde::Raw, user_id, MilliSecondsSinceUnixEpoch, UInt,
+    };
+
+    use super::{CachedEvent, TimelineCache};
+
+    fn cached_event(index: u32) -> CachedEvent {
+        let event_id = format!("$event{index}:example.org");
+        let raw = Raw::from_json_string(format!(
+            r#"{{"type":"m.room.message","event_id":"{event_id}","sender":"@alice:example.org","origin_server_ts":{index},"content":{{"msgtype":"m.text","body":"{index}"}}}}"#
+        ))
+        .unwrap();
+
+        CachedEvent {
+            event_id: event_id.try_into().unwrap(),
+            sender: user_id!("@alice:example.org").to_owned(),
+            origin_server_ts: MilliSecondsSinceUnixEpoch(UInt::from(index)),
+            body: index.to_string(),
+            sender_name: "Alice".to_owned(),
+            raw,
+            encryption_info: None,
+        }
+    }
+
+    #[test]
+    fn cache_keeps_the_latest_events_of_each_room() {
+        let cache = TimelineCache::new(10);
+        let room_id = room_id!("!room:example.org");
+        for index in 0..1000 {
+            cache.push(room_id, cached_event(index));
+        }
+
+        assert_eq!(cache.total_event_count(), 10);
+        let events = cache.events(room_id);
+        assert_eq!(events.first().unwrap().body, "990");
+        assert_eq!(
+            events.last().unwrap().event_id,
+            owned_event_id!("$event999:example.org")
+        );
+    }
+
+    #[test]
+    fn cache_bounds_each_room_separately() {
+        let cache = TimelineCache::new(10);
+        for index in 0..30 {
+            cache.push(room_id!("!first:example.org"), cached_event(index));
+        }
+        cache.push(room_id!("!second:example.org"), cached_event(30));
+
+        assert_eq!(cache.total_event_count(), 11);
+        assert_eq!(cache.events(room_id!("!second:example.org")).len(), 1);
+    }
+
+    #[test]
+    fn cache_replaces_an_event_received_again() {
+        let cache = TimelineCache::new(10);
+        let room_id = room_id!("!room:example.org");
+        cache.push(room_id, cached_event(1));
+        cache.push(room_id, cached_event(2));
+
+        let mut decrypted = cached_event(1);
+        decrypted.body = "decrypted".to_owned();
+        cache.push(room_id, decrypted);
+
+        let bodies: Vec<_> = cache
+            .events(room_id)
+            .into_iter()
+            .map(|event| event.body)
+            .collect();
+        assert_eq!(bodies, ["decrypted", "2"]);
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::bail;
+use matrix_sdk::{
+    ruma::{
+        api::client::room::upgrade_room,
+        events::{direct::DirectEventContent, room::tombstone::RoomTombstoneEventContent},
+        OwnedRoomId, RoomId, RoomVersionId,
+    },
+    Client, Room, RoomState,
+};
+
+use super::{settings::recommended_room_version, state_event};
+
+/// Upgrade the room to the given version, or to the version recommended by the
+/// homeserver.
+///
+/// The homeserver creates the new room and closes the old one with a
+/// tombstone, the members have to join the new room. Returns the ID of the new
+/// room.
+pub async fn upgrade_room(
+    room: &Room,
+    version: Option<RoomVersionId>,
+) -> anyhow::Result<OwnedRoomId> {
+    let client = room.client();
+    let version = match version {
+        Some(version) => version,
+        None => recommended_room_version(&client).await?,
+    };
+
+    let request = upgrade_room::v3::Request::new(room.room_id().to_owned(), version);
+    Ok(client.send(request, None).await?.replacement_room)
+}
+
+/// Join the room that replaces the given room, after it was upgraded.
+///
+/// The direct conversations pointing to the old room are updated to point to
+/// the new one. Returns the ID of the new room.
+pub async fn follow_tombstone(room: &Room) -> anyhow::Result<OwnedRoomId> {
+    let Some(event) = state_event::<RoomTombstoneEventContent>(room).await? else {
+        bail!("{} has not been upgraded", room.room_id());
+    };
+    let new_room_id = event.content.replacement_room;
+
+    let client = room.client();
+    if is_joined(&client, &new_room_id) {
+        bail!("You are already a member of {new_room_id}");
+    }
+
+    // The server of the user who upgraded the room is the most likely to be in
+    // the new room.
+    let mut via = vec![event.sender.server_name().to_owned()];
+    if let Some(server) = new_room_id.server_name() {
+        if !via.iter().any(|via| via == server) {
+            via.push(server.to_owned());
+        }
+    }
+
+    client
+        .join_room_by_id_or_alias((&new_room_id).into(), &via)
+        .await?;
+    replace_direct_room(&client, room.room_id(), &new_room_id).await?;
+
+    Ok(new_room_id)
+}
+
+/// Whether we are a member of the given room.
+pub fn is_joined(client: &Client, room_id: &RoomId) -> bool {
+    client
+        .get_room(room_id)
+        .is_some_and(|room| room.state() == RoomState::Joined)
+}
+
+/// Replace the old room with the new one in the direct conversations.
+async fn replace_direct_room(
+    client: &Client,
+    old_room_id: &RoomId,
+    new_room_id: &RoomId,
+) -> anyhow::Result<()> {
+    let Some(raw_direct) = client
+        .account()
+        .account_data::<DirectEventContent>()
+        .await?
+    else {
+        return Ok(());
+    };
+
+    let mut direct = raw_direct.deserialize()?;
+    let mut updated = false;
+    for room_ids in direct.values_mut() {
+        for room_id in room_ids.iter_mut() {
+            if *room_id == old_room_id {
+                *room_id = new_room_id.to_owned();
+                updated = true;
+            }
+        }
+    }
+
+    if updated {
+        client.account().set_account_data(direct).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::bail;
+use matrix_sdk::{
+    ruma::{
+        api::client::space::{get_hierarchy, SpaceHierarchyRoomsChunk},
+        OwnedRoomId, OwnedServerName, RoomId, UInt,
+    },
+    Client,
+};
+
+use crate::ui_elements::confirm::ask;
+
+/// A room of the hierarchy of a space, as displayed.
+struct HierarchyEntry<'a> {
+    /// The room.
+    room: &'a SpaceHierarchyRoomsChunk,
+
+    /// The depth of the room in the tree, the space is at 0.
+    level: usize,
+
+    /// Whether the parent space suggests to join the room.
+    suggested: bool,
+
+    /// The servers to join the room via, from the parent space.
+    via: Vec<OwnedServerName>,
+}
+
+/// Display the rooms and subspaces of the space as a tree, down to the given
+/// depth.
+///
+/// The user can enter the number of a room to join it.
+pub async fn explore_space(
+    client: &Client,
+    space_room_id: &RoomId,
+    depth: u32,
+) -> anyhow::Result<()> {
+    let mut rooms = Vec::new();
+    let mut from = None;
+    loop {
+        let mut request = get_hierarchy::v1::Request::new(space_room_id.to_owned());
+        request.max_depth = Some(UInt::from(depth));
+        request.from = from;
+        let response = client.send(request, None).await?;
+
+        rooms.extend(response.rooms);
+        match response.next_batch {
+            Some(next_batch) => from = Some(next_batch),
+            None => break,
+        }
+    }
+
+    let entries = hierarchy_tree(space_room_id, &rooms);
+    if entries.is_empty() {
+        bail!("{space_room_id} is not a space or is not accessible");
+    }
+
+    for (index, entry) in entries.iter().enumerate() {
+        let room = entry.room;
+        let indent = "  ".repeat(entry.level);
+        let name = room
+            .name
+            .clone()
+            .or_else(|| room.canonical_alias.as_ref().map(ToString::to_string))
+            .unwrap_or_else(|| room.room_id.to_string());
+        let suggested = if entry.suggested { ", suggested" } else { "" };
+        println!(
+            "{:>3}. {indent}{name} ({} members, {}{suggested})",
+            index + 1,
+            room.num_joined_members,
+            room.join_rule.as_str()
+        );
+        if let Some(topic) = &room.topic {
+            println!("     {indent}{}", topic.replace('\n', " "));
+        }
+    }
+
+    let answer = ask("Enter the number of a room to join it, or nothing to skip:")?;
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Ok(());
+    }
+    let Some(entry) = answer
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| entries.get(number.checked_sub(1)?))
+    else {
+        bail!("{answer} is not the number of a displayed room");
+    };
+
+    let room = client
+        .join_room_by_id_or_alias(entry.room.room_id.as_ref().into(), &entry.via)
+        .await?;
+    println!("Joined {}", room.room_id());
+
+    Ok(())
+}
+
+/// Order the rooms of the hierarchy depth-first from the space, following the
+/// `m.space.child` events of each space.
+///
+/// The rooms that are not reachable from the space are left out.
+fn hierarchy_tree<'a>(
+    space_room_id: &RoomId,
+    rooms: &'a [SpaceHierarchyRoomsChunk],
+) -> Vec<HierarchyEntry<'a>> {
+    let by_id: HashMap<&RoomId, &SpaceHierarchyRoomsChunk> =
+        rooms.iter().map(|room| (&*room.room_id, room)).collect();
+
+    let mut entries = Vec::new();
+    let mut visited: HashSet<OwnedRoomId> = HashSet::new();
+    let mut stack = vec![(space_room_id.to_owned(), 0, false, Vec::new())];
+    while let Some((room_id, level, suggested, via)) = stack.pop() {
+        // Spaces can contain each other.
+        if !visited.insert(room_id.clone()) {
+            continue;
+        }
+        let Some(room) = by_id.get(&*room_id) else {
+            continue;
+        };
+
+        // The children are pushed in reverse to pop them in order.
+        for child in room.children_state.iter().rev() {
+            let Ok(child) = child.deserialize() else {
+                continue;
+            };
+            stack.push((
+                child.state_key,
+                level + 1,
+                child.content.suggested,
+                child.content.via,
+            ));
+        }
+
+        entries.push(HierarchyEntry {
+            room,
+            level,
+            suggested,
+            via,
+        });
+    }
+
+    entries
+}
@@ -0,0 +1,575 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::bail;
+use crossterm::style::Stylize;
+use matrix_sdk::{
+    self,
+    config::SyncSettings,
+    deserialized_responses::EncryptionInfo,
+    event_handler::{Ctx, RawEvent},
+    ruma::{
+        api::client::{error::ErrorKind, filter::FilterDefinition},
+        events::{
+            ignored_user_list::IgnoredUserListEventContent,
+            room::{
+                encrypted::OriginalSyncRoomEncryptedEvent,
+                member::{MembershipChange, SyncRoomMemberEvent},
+                message::{MessageType, OriginalSyncRoomMessageEvent},
+                tombstone::OriginalSyncRoomTombstoneEvent,
+            },
+            sticker::OriginalSyncStickerEvent,
+            AnyMessageLikeEvent, AnyTimelineEvent, GlobalAccountDataEvent, MessageLikeEvent,
+        },
+        serde::Raw,
+    },
+    Client, Error, LoopCtrl, Room, RoomState,
+};
+use tokio::{fs, sync::Mutex};
+use tracing::warn;
+
+use crate::{
+    bot,
+    config::SharedConfig,
+    crypto::{self, verification},
+    display::{
+        cache::DisplayNameCache,
+        image::show_image,
+        status::{ActiveUsersDisplay, CountdownDisplay},
+        timeline::{compute_shield, format_member_event, render_live_event},
+    },
+    login::persist_session::{recover_soft_logout, ExpiredSession, FullSession},
+    notifications::{is_mention, sound::SoundNotifier, watcher},
+    plugins,
+    profile::get_ignored_users,
+    rooms::{
+        timeline::{CachedEvent, TimelineCache},
+        upgrade::is_joined,
+    },
+};
+
+/// The maximum delay before retrying a failed sync.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// The interval between two checks of the session file.
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The age after which the session file is written again with the latest sync
+/// token, because it should be written after every sync.
+const SESSION_FILE_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Setup the client to listen to new messages.
+pub async fn sync(
+    client: Client,
+    mut initial_sync_token: Option<String>,
+    session_file: PathBuf,
+    config: SharedConfig,
+    expired_session: ExpiredSession,
+    countdown: CountdownDisplay,
+) -> anyhow::Result<()> {
+    let session_file = session_file.as_path();
+
+    println!("Launching a first sync to ignore past messages…");
+
+    // Enable room members lazy-loading, it will speed up the initial sync a lot
+    // with accounts in lots of rooms.
+    // See <https://spec.matrix.org/v1.6/client-server-api/#lazy-loading-room-members>.
+    let filter = FilterDefinition::with_lazy_loading();
+
+    let mut sync_settings = SyncSettings::default().filter(filter.into());
+
+    // We restore the sync where we left.
+    // This is not necessary when not using `sync_once`. The other sync methods get
+    // the sync token from the store.
+    if let Some(sync_token) = initial_sync_token.clone() {
+        sync_settings = sync_settings.token(sync_token);
+    }
+
+    // Let's ignore messages before the program was launched.
+    // This is a loop in case the initial sync is longer than our timeout. The
+    // server should cache the response and it will ultimately take less time to
+    // receive.
+    loop {
+        match client.sync_once(sync_settings.clone()).await {
+            Ok(response) => {
+                // This is the last time we need to provide this token, the sync method after
+                // will handle it on its own.
+                sync_settings = sync_settings.token(response.next_batch.clone());
+                initial_sync_token = Some(response.next_batch.clone());
+                persist_sync_token(session_file, response.next_batch).await?;
+                break;
+            }
+            Err(error) => {
+                println!("An error occurred during initial sync: {error}");
+                println!("Trying again…");
+            }
+        }
+    }
+
+    println!("The client is ready! Listening to new messages…");
+
+    // The ignore list received during the initial sync is not handled by the
+    // event handlers.
+    match get_ignored_users(&client).await {
+        Ok(ignored_users) => config.write().await.filter.ignored_users = ignored_users,
+        Err(error) => println!("Error getting the ignored users: {error}"),
+    }
+
+    if config.read().await.auto_cross_sign_on_verification {
+        if let Err(error) = verification::cross_sign_current_device(&client).await {
+            println!("Error cross-signing this device: {error}");
+        }
+    }
+
+    // Now that we've synced, let's attach a handler for incoming room messages.
+    add_event_handlers(&client, config);
+
+    // The number of syncs that failed in a row.
+    let failures = &AtomicU32::new(0);
+
+    // The token of the latest successful sync, to repair the session file.
+    let latest_sync_token = &Mutex::new(initial_sync_token);
+
+    // This loops until we kill the program or the session is logged out.
+    let sync_client = &client;
+    let expired_session = &expired_session;
+    let countdown = &countdown;
+    let sync = client.sync_with_result_callback(sync_settings, |sync_result| async move {
+        let response = match sync_result {
+            Ok(response) => response,
+            Err(error) => {
+                match error.client_api_error_kind() {
+                    // The access token must be renewed, the session is still valid.
+                    Some(ErrorKind::UnknownToken { soft_logout: true }) => {
+                        let restored = recover_soft_logout(sync_client, session_file)
+                            .await
+                            .map_err(|err| Error::UnknownError(err.into()))?;
+                        if !restored {
+                            // The REPL asks for the credentials, the sync waits
+                            // meanwhile.
+                            expired_session.wait_for_relogin(session_file).await;
+                        }
+                        return Ok(LoopCtrl::Continue);
+                    }
+                    Some(ErrorKind::UnknownToken { soft_logout: false }) => {
+                        println!("This session was logged out, restart the client to log in again");
+                        return Err(error);
+                    }
+                    _ => {}
+                }
+
+                warn!("The sync failed: {error}");
+                let failures = failures.fetch_add(1, Ordering::Relaxed) + 1;
+                let delay = retry_delay(failures);
+
+                countdown.start(delay);
+                tokio::time::sleep(delay).await;
+
+                return Ok(LoopCtrl::Continue);
+            }
+        };
+        if failures.swap(0, Ordering::Relaxed) > 0 {
+            countdown.stop();
+        }
+
+        // We persist the token each time to be able to restore our session
+        *latest_sync_token.lock().await = Some(response.next_batch.clone());
+        persist_sync_token(session_file, response.next_batch)
+            .await
+            .map_err(|err| Error::UnknownError(err.into()))?;
+
+        Ok(LoopCtrl::Continue)
+    });
+
+    tokio::select! {
+        result = sync => result?,
+        () = watch_session_file(&client, session_file, latest_sync_token) => {}
+    }
+
+    Ok(())
+}
+
+/// Attach the handlers of the events received after the initial sync.
+///
+/// The other contexts of the handlers, like the [`TimelineCache`] and the
+/// [`SoundNotifier`], must be added to the client before.
+pub fn add_event_handlers(client: &Client, config: SharedConfig) {
+    client.add_event_handler_context(config);
+    client.add_event_handler(on_room_message);
+    client.add_event_handler(bot::on_room_message);
+    client.add_event_handler(on_sticker);
+    client.add_event_handler(on_undecryptable_event);
+    client.add_event_handler(on_room_tombstone);
+    client.add_event_handler(on_room_member);
+    client.add_event_handler(watcher::on_room_member);
+    client.add_event_handler(plugins::on_room_message);
+    client.add_event_handler(plugins::on_state_event);
+    client.add_event_handler(verification::on_verification_request);
+    client.add_event_handler(verification::on_room_verification_request);
+    client.add_event_handler(crypto::on_secret_request);
+    client.add_event_handler(on_ignored_user_list);
+}
+
+/// Check the session file every [`SESSION_CHECK_INTERVAL`], to detect early
+/// that it was deleted, corrupted or replaced by another instance.
+async fn watch_session_file(
+    client: &Client,
+    session_file: &Path,
+    latest_sync_token: &Mutex<Option<String>>,
+) {
+    let mut interval = tokio::time::interval(SESSION_CHECK_INTERVAL);
+    // The first tick completes immediately, right after the initial sync.
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        if let Err(error) = check_session_file(client, session_file, latest_sync_token).await {
+            println!(
+                "{}",
+                format!(
+                    "Warning: the session file {} is broken: {error}",
+                    session_file.display()
+                )
+                .yellow()
+            );
+        }
+    }
+}
+
+/// Check that the session file is readable and belongs to the running session.
+///
+/// It is written again with the latest sync token if it was not modified for
+/// [`SESSION_FILE_MAX_AGE`].
+async fn check_session_file(
+    client: &Client,
+    session_file: &Path,
+    latest_sync_token: &Mutex<Option<String>>,
+) -> anyhow::Result<()> {
+    let serialized_session = fs::read_to_string(session_file).await?;
+    let full_session: FullSession = serde_json::from_str(&serialized_session)?;
+
+    let user_id = &full_session.user_session.meta.user_id;
+    if client.user_id() != Some(user_id) {
+        bail!(
+            "it contains the session of {user_id}, it may have been replaced by another instance"
+        );
+    }
+    let homeserver = full_session.client_session.homeserver();
+    if homeserver.trim_end_matches('/') != client.homeserver().as_str().trim_end_matches('/') {
+        bail!(
+            "it contains a session on {homeserver}, it may have been replaced by another instance"
+        );
+    }
+
+    let age = fs::metadata(session_file)
+        .await?
+        .modified()?
+        .elapsed()
+        .unwrap_or_default();
+    if age > SESSION_FILE_MAX_AGE {
+        if let Some(sync_token) = latest_sync_token.lock().await.clone() {
+            warn!("The session file was not written for {age:?}, writing it again");
+            persist_sync_token(session_file, sync_token).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The delay before retrying after the given number of failed syncs in a row.
+///
+/// It doubles after each failure, up to [`MAX_RETRY_DELAY`].
+fn retry_delay(failures: u32) -> Duration {
+    let delay = Duration::from_secs(2u64.saturating_pow(failures.min(16)));
+    delay.min(MAX_RETRY_DELAY)
+}
+
+/// Persist the sync token for a future session.
+/// Note that this is needed only when using `sync_once`. Other sync methods get
+/// the sync token from the store.
+async fn persist_sync_token(session_file: &Path, sync_token: String) -> anyhow::Result<()> {
+    let serialized_session = fs::read_to_string(session_file).await?;
+    let mut full_session: FullSession = serde_json::from_str(&serialized_session)?;
+
+    full_session.sync_token = Some(sync_token);
+    let serialized_session = serde_json::to_string(&full_session)?;
+    fs::write(session_file, serialized_session).await?;
+
+    Ok(())
+}
+
+/// Handle room messages.
+async fn on_room_message(
+    event: OriginalSyncRoomMessageEvent,
+    room: Room,
+    raw_event: RawEvent,
+    encryption_info: Option<EncryptionInfo>,
+    config: Ctx<SharedConfig>,
+    notifier: Ctx<SoundNotifier>,
+    timeline_cache: Ctx<TimelineCache>,
+    display_names: Ctx<Arc<DisplayNameCache>>,
+    active_users: Ctx<ActiveUsersDisplay>,
+) {
+    // We only want to log text messages in joined rooms.
+    if room.state() != RoomState::Joined {
+        return;
+    }
+
+    let raw = Raw::from_json(raw_event.0);
+    let sender_name = display_names.get(&room, &event.sender).await;
+    timeline_cache.push(
+        room.room_id(),
+        CachedEvent {
+            event_id: event.event_id.clone(),
+            sender: event.sender.clone(),
+            origin_server_ts: event.origin_server_ts,
+            body: event.content.body().to_owned(),
+            sender_name: sender_name.clone(),
+            raw: raw.clone(),
+            encryption_info: encryption_info.clone(),
+        },
+    );
+    let body = event.content.body();
+    let is_notice = matches!(event.content.msgtype, MessageType::Notice(_));
+
+    let types = ["m.room.message", event.content.msgtype()];
+    if config
+        .read()
+        .await
+        .filter
+        .is_filtered(&event.sender, &types)
+    {
+        return;
+    }
+
+    let mentioned = is_mention(body, &room).await;
+    // Notices never notify, like with the default push rules.
+    if mentioned && !is_notice {
+        notifier.play();
+    }
+
+    if config.read().await.mention_filter.enabled {
+        let mut config = config.write().await;
+        if !mentioned {
+            config.mention_filter.hidden_count += 1;
+            return;
+        }
+
+        let hidden_count = config.mention_filter.take_hidden_count();
+        if hidden_count > 0 {
+            println!("({hidden_count} messages hidden by mention filter)");
+        }
+    }
+
+    let shield = compute_shield(encryption_info.as_ref(), &room, &room.client()).await;
+    let display_config = config.read().await.display.clone();
+    let timeline_event = AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+        MessageLikeEvent::Original(event.clone().into_full_event(room.room_id().to_owned())),
+    ));
+    let rendered = render_live_event(
+        &timeline_event,
+        &raw.cast(),
+        &room,
+        &room_name(&room).await,
+        &display_config,
+        &display_names,
+    )
+    .await;
+    println!("{shield}{rendered}");
+    active_users.record(
+        room.room_id(),
+        &event.sender,
+        sender_name,
+        event.origin_server_ts,
+    );
+
+    if let MessageType::Image(content) = &event.content.msgtype {
+        let image_mode = config.read().await.display.image_mode;
+        if let Err(error) = show_image(&room.client(), content, image_mode).await {
+            println!("Error displaying the image: {error}");
+        }
+    }
+}
+
+/// Keep the encrypted messages that could not be decrypted in the timeline
+/// cache, so they can be exported.
+async fn on_undecryptable_event(
+    event: OriginalSyncRoomEncryptedEvent,
+    room: Room,
+    raw_event: RawEvent,
+    timeline_cache: Ctx<TimelineCache>,
+    display_names: Ctx<Arc<DisplayNameCache>>,
+) {
+    if room.state() != RoomState::Joined {
+        return;
+    }
+
+    timeline_cache.push(
+        room.room_id(),
+        CachedEvent {
+            event_id: event.event_id.clone(),
+            sender: event.sender.clone(),
+            origin_server_ts: event.origin_server_ts,
+            body: String::new(),
+            sender_name: display_names.get(&room, &event.sender).await,
+            raw: Raw::from_json(raw_event.0),
+            encryption_info: None,
+        },
+    );
+}
+
+/// Handle stickers.
+async fn on_sticker(
+    event: OriginalSyncStickerEvent,
+    room: Room,
+    raw_event: RawEvent,
+    encryption_info: Option<EncryptionInfo>,
+    config: Ctx<SharedConfig>,
+    timeline_cache: Ctx<TimelineCache>,
+    display_names: Ctx<Arc<DisplayNameCache>>,
+) {
+    if room.state() != RoomState::Joined {
+        return;
+    }
+
+    let raw = Raw::from_json(raw_event.0);
+    timeline_cache.push(
+        room.room_id(),
+        CachedEvent {
+            event_id: event.event_id.clone(),
+            sender: event.sender.clone(),
+            origin_server_ts: event.origin_server_ts,
+            body: event.content.body.clone(),
+            sender_name: display_names.get(&room, &event.sender).await,
+            raw: raw.clone(),
+            encryption_info,
+        },
+    );
+    {
+        let mut config = config.write().await;
+        if config.filter.is_filtered(&event.sender, &["m.sticker"]) {
+            return;
+        }
+        // Stickers can't mention us.
+        if config.mention_filter.enabled {
+            config.mention_filter.hidden_count += 1;
+            return;
+        }
+    }
+
+    let display_config = config.read().await.display.clone();
+    let timeline_event = AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::Sticker(
+        MessageLikeEvent::Original(event.into_full_event(room.room_id().to_owned())),
+    ));
+    let rendered = render_live_event(
+        &timeline_event,
+        &raw.cast(),
+        &room,
+        &room_name(&room).await,
+        &display_config,
+        &display_names,
+    )
+    .await;
+    println!("{rendered}");
+}
+
+/// Handle changes of the ignore list, including from other sessions.
+async fn on_ignored_user_list(
+    event: GlobalAccountDataEvent<IgnoredUserListEventContent>,
+    config: Ctx<SharedConfig>,
+) {
+    config.write().await.filter.ignored_users = event.content.ignored_users.into_keys().collect();
+}
+
+/// Handle room upgrades.
+///
+/// The new room is joined with `/follow-upgrade`, nothing is printed if we are
+/// already a member.
+async fn on_room_tombstone(event: OriginalSyncRoomTombstoneEvent, room: Room) {
+    let new_room_id = &event.content.replacement_room;
+    if room.state() != RoomState::Joined || is_joined(&room.client(), new_room_id) {
+        return;
+    }
+
+    println!(
+        "[{}] This room has been upgraded to {new_room_id}, join the new room with \
+         /follow-upgrade {}",
+        room_name(&room).await,
+        room.room_id()
+    );
+}
+
+/// Handle membership changes.
+async fn on_room_member(
+    event: SyncRoomMemberEvent,
+    room: Room,
+    config: Ctx<SharedConfig>,
+    display_names: Ctx<Arc<DisplayNameCache>>,
+) {
+    if let SyncRoomMemberEvent::Original(event) = &event {
+        if let MembershipChange::ProfileChanged {
+            displayname_change: Some(change),
+            ..
+        } = event.membership_change()
+        {
+            display_names.update(&event.state_key, change.new);
+        }
+    }
+
+    if room.state() != RoomState::Joined {
+        return;
+    }
+    {
+        let config = config.read().await;
+        if config.mention_filter.enabled
+            || config
+                .filter
+                .is_filtered(event.sender(), &["m.room.member"])
+        {
+            return;
+        }
+    }
+
+    if let Some(notice) = format_member_event(&event, &room, &display_names).await {
+        println!("[{}] {notice}", room_name(&room).await);
+    }
+}
+
+/// The display name of the room, or its ID if it can't be computed.
+async fn room_name(room: &Room) -> String {
+    match room.display_name().await {
+        Ok(room_name) => room_name.to_string(),
+        Err(error) => {
+            println!("Error getting room display name: {error}");
+            // Let's fallback to the room ID.
+            room.room_id().to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{retry_delay, MAX_RETRY_DELAY};
+
+    #[test]
+    fn retry_delay_doubles_after_each_failure() {
+        assert_eq!(retry_delay(1), Duration::from_secs(2));
+        assert_eq!(retry_delay(2), Duration::from_secs(4));
+        assert_eq!(retry_delay(5), Duration::from_secs(32));
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        assert_eq!(retry_delay(6), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(30), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+}
@@ -0,0 +1,221 @@
+use std::sync::{Arc, Mutex};
+
+use matrix_sdk::{
+    matrix_auth::{MatrixSession, MatrixSessionTokens},
+    Client, SessionMeta,
+};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, method, path, path_regex},
+    Mock, Request, Respond, ResponseTemplate,
+};
+
+/// The user ID of the account logged in on the mock homeserver.
+pub const MOCK_USER_ID: &str = "@alice:localhost";
+
+/// The device ID returned by the mock homeserver on login.
+pub const MOCK_DEVICE_ID: &str = "MOCKDEVICE";
+
+/// The access token returned by the mock homeserver on login.
+const MOCK_ACCESS_TOKEN: &str = "mock_access_token";
+
+/// The URI of the files uploaded to the mock homeserver.
+const MOCK_MEDIA_URI: &str = "mxc://localhost/mockmedia";
+
+/// A homeserver answering the requests made during login and sync with
+/// pre-programmed responses.
+///
+/// Set [`MockServer::uri()`] as the homeserver of the config, with the
+/// credentials, to log in against it with `login_new` without any network
+/// access.
+pub struct MockServer {
+    server: wiremock::MockServer,
+}
+
+impl MockServer {
+    /// Start a homeserver that only accepts the given username and password.
+    pub async fn with_password_login(user: &str, password: &str) -> Self {
+        let server = Self::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/v3/login"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "flows": [{ "type": "m.login.password" }]
+            })))
+            .mount(&server.server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/login"))
+            .and(body_partial_json(json!({
+                "type": "m.login.password",
+                "identifier": { "type": "m.id.user", "user": user },
+                "password": password,
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(login_response()))
+            .mount(&server.server)
+            .await;
+
+        // Any other credentials are rejected.
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/login"))
+            .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+                "errcode": "M_FORBIDDEN",
+                "error": "Invalid username or password",
+            })))
+            .mount(&server.server)
+            .await;
+
+        server
+    }
+
+    /// Start a homeserver that only offers SSO login.
+    ///
+    /// The login token sent back by the SSO flow is always accepted.
+    pub async fn with_sso_login() -> Self {
+        let server = Self::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/v3/login"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "flows": [{ "type": "m.login.sso" }, { "type": "m.login.token" }]
+            })))
+            .mount(&server.server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/login"))
+            .and(body_partial_json(json!({ "type": "m.login.token" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(login_response()))
+            .mount(&server.server)
+            .await;
+
+        server
+    }
+
+    /// The base URL of the mock homeserver.
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Answer every sync with the given response instead of an empty one.
+    pub async fn mount_sync_response(&self, response: serde_json::Value) {
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/v3/sync"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(response))
+            // The default response is mounted with the default priority of 5.
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Keep the last file uploaded to the media repository and serve it to
+    /// any download.
+    ///
+    /// Returns the content of the last uploaded file, as it was received.
+    pub async fn mount_media_repository(&self) -> Arc<Mutex<Vec<u8>>> {
+        let stored = Arc::new(Mutex::new(Vec::new()));
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/media/v3/upload"))
+            .respond_with(UploadResponder(stored.clone()))
+            .mount(&self.server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path_regex("^/_matrix/media/v3/download/"))
+            .respond_with(DownloadResponder(stored.clone()))
+            .mount(&self.server)
+            .await;
+
+        stored
+    }
+
+    /// Build a client logged in on the mock homeserver, with an in-memory
+    /// store.
+    pub async fn logged_in_client(&self) -> anyhow::Result<Client> {
+        let client = Client::builder().homeserver_url(self.uri()).build().await?;
+        client
+            .restore_session(MatrixSession {
+                meta: SessionMeta {
+                    user_id: MOCK_USER_ID.try_into()?,
+                    device_id: MOCK_DEVICE_ID.into(),
+                },
+                tokens: MatrixSessionTokens {
+                    access_token: MOCK_ACCESS_TOKEN.to_owned(),
+                    refresh_token: None,
+                },
+            })
+            .await?;
+
+        Ok(client)
+    }
+
+    /// Start the server with the endpoints that don't depend on the login type.
+    async fn start() -> Self {
+        let server = wiremock::MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "versions": ["r0.6.1", "v1.1", "v1.2", "v1.3", "v1.4", "v1.5", "v1.6"],
+                "unstable_features": {},
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/v3/sync"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "next_batch": "s1_mock",
+            })))
+            .mount(&server)
+            .await;
+
+        // The encryption keys of a new device are uploaded after the first sync.
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/keys/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "one_time_key_counts": {},
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/logout"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .mount(&server)
+            .await;
+
+        Self { server }
+    }
+}
+
+/// The body of a successful login response.
+fn login_response() -> serde_json::Value {
+    json!({
+        "user_id": MOCK_USER_ID,
+        "access_token": MOCK_ACCESS_TOKEN,
+        "device_id": MOCK_DEVICE_ID,
+    })
+}
+
+/// Stores the uploaded files in memory.
+struct UploadResponder(Arc<Mutex<Vec<u8>>>);
+
+impl Respond for UploadResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        *self.0.lock().expect("the media lock is not poisoned") = request.body.clone();
+        ResponseTemplate::new(200).set_body_json(json!({ "content_uri": MOCK_MEDIA_URI }))
+    }
+}
+
+/// Serves the last uploaded file.
+struct DownloadResponder(Arc<Mutex<Vec<u8>>>);
+
+impl Respond for DownloadResponder {
+    fn respond(&self, _request: &Request) -> ResponseTemplate {
+        let stored = self.0.lock().expect("the media lock is not poisoned");
+        ResponseTemplate::new(200).set_body_bytes(stored.clone())
+    }
+}
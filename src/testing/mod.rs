@@ -0,0 +1,2 @@
+//! Helpers to run the client without a real homeserver.
+pub mod mock_server;
@@ -0,0 +1,23 @@
+use std::io::{self, Write};
+
+/// Ask a question on the terminal and return the answer, without the trailing
+/// newline.
+pub fn ask(question: &str) -> anyhow::Result<String> {
+    print!("{question} ");
+    io::stdout().flush()?;
+
+    // Reading from stdin blocks, make sure the sync keeps running meanwhile.
+    let mut answer = String::new();
+    tokio::task::block_in_place(|| io::stdin().read_line(&mut answer))?;
+
+    Ok(answer.trim_end_matches(['\n', '\r']).to_owned())
+}
+
+/// Ask a yes or no question on the terminal.
+///
+/// Only an answer starting with `y` or `Y` is considered a yes.
+pub fn confirm(question: &str) -> anyhow::Result<bool> {
+    let answer = ask(&format!("{question} [y/N]"))?;
+
+    Ok(answer.trim_start().starts_with(['y', 'Y']))
+}
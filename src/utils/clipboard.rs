@@ -0,0 +1,19 @@
+/// Copy the text to the clipboard.
+///
+/// The clipboard needs a graphical session. Without one, or if the client is
+/// built without the `clipboard` feature, the text is printed with a `Copied: `
+/// prefix instead, to be copied from the terminal.
+pub fn copy_to_clipboard(text: &str) -> anyhow::Result<()> {
+    #[cfg(feature = "clipboard")]
+    match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text)) {
+        Ok(()) => {
+            println!("Copied to the clipboard");
+            return Ok(());
+        }
+        Err(error) => tracing::debug!("The clipboard is not available: {error}"),
+    }
+
+    println!("Copied: {text}");
+
+    Ok(())
+}
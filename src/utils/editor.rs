@@ -0,0 +1,50 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    process::Command,
+};
+
+use anyhow::{bail, Context};
+
+/// The editor used when `$EDITOR` is not set.
+const DEFAULT_EDITOR: &str = "nano";
+
+/// Let the user edit the text in `$EDITOR`, or `nano` if it is not set.
+///
+/// Returns the edited text, without the trailing newline added by most
+/// editors. This blocks until the editor exits, and so do the event handlers
+/// printing meanwhile, so they don't draw over the editor.
+pub fn open_editor(initial_content: &str) -> anyhow::Result<String> {
+    let editor = env::var("EDITOR").unwrap_or_else(|_| DEFAULT_EDITOR.to_owned());
+    // The file is only readable by the user and deleted when it is dropped.
+    let mut file = tempfile::Builder::new()
+        .prefix("matrix-client-")
+        .suffix(".txt")
+        .tempfile()?;
+    file.write_all(initial_content.as_bytes())?;
+    file.flush()?;
+
+    // The output of the sync waits for the lock, the editor writes to the
+    // terminal directly.
+    let stdout = io::stdout().lock();
+
+    // The editor can contain arguments, like `code --wait`.
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_EDITOR);
+    let status = Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .with_context(|| format!("Could not start the editor {editor}"));
+    drop(stdout);
+
+    // Some editors replace the file instead of writing to it, it must be read
+    // again from its path.
+    let content = fs::read_to_string(file.path());
+
+    if !status?.success() {
+        bail!("The editor {editor} exited with an error, nothing was changed");
+    }
+
+    Ok(content?.trim_end_matches('\n').to_owned())
+}
@@ -0,0 +1,3 @@
+pub mod clipboard;
+pub mod editor;
+pub mod mxid;
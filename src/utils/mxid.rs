@@ -0,0 +1,92 @@
+use anyhow::bail;
+use matrix_sdk::ruma::{OwnedUserId, ServerName, UserId};
+
+/// Parse a user ID typed by the user.
+///
+/// Accepts full user IDs like `@alice:example.org`, server-relative forms like
+/// `alice:example.org`, and bare localparts like `alice` or `@alice`, that are
+/// assumed to be on `default_server`.
+///
+/// Full user IDs are parsed with the historical grammar, so existing users with
+/// uppercase letters or other characters can still be written. Bare localparts
+/// must only contain the characters allowed by the spec for new user IDs:
+/// lowercase letters, digits and `._=-/+`.
+pub fn parse_mxid(input: &str, default_server: &ServerName) -> anyhow::Result<OwnedUserId> {
+    let input = input.trim();
+    let without_sigil = input.strip_prefix('@').unwrap_or(input);
+
+    let (localpart, is_full_id) = match without_sigil.split_once(':') {
+        Some((localpart, _)) => (localpart, true),
+        None => (without_sigil, false),
+    };
+
+    if localpart.is_empty() {
+        bail!("{input} is not a valid user ID: the username is empty");
+    }
+    if is_full_id {
+        return Ok(UserId::parse(format!("@{without_sigil}"))?);
+    }
+    if let Some(invalid) = localpart.chars().find(|c| !is_localpart_char(*c)) {
+        bail!(
+            "{input} is not a valid user ID: {invalid:?} is not allowed in a username, \
+             only lowercase letters, digits and ._=-/+ are"
+        );
+    }
+
+    Ok(UserId::parse_with_server_name(localpart, default_server)?)
+}
+
+/// Whether the character is allowed in the localpart of a user ID.
+fn is_localpart_char(c: char) -> bool {
+    matches!(c, 'a'..='z' | '0'..='9' | '.' | '_' | '=' | '-' | '/' | '+')
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::ruma::server_name;
+
+    use super::parse_mxid;
+
+    #[test]
+    fn parse_full_user_ids() {
+        let server = server_name!("example.org");
+        assert_eq!(
+            parse_mxid("@alice:other.org", server).unwrap(),
+            "@alice:other.org"
+        );
+        assert_eq!(
+            parse_mxid(" alice:other.org ", server).unwrap(),
+            "@alice:other.org"
+        );
+    }
+
+    #[test]
+    fn parse_historical_user_ids() {
+        let server = server_name!("example.org");
+        assert_eq!(
+            parse_mxid("@Alice!:other.org", server).unwrap(),
+            "@Alice!:other.org"
+        );
+    }
+
+    #[test]
+    fn parse_bare_localparts() {
+        let server = server_name!("example.org");
+        assert_eq!(parse_mxid("alice", server).unwrap(), "@alice:example.org");
+        assert_eq!(
+            parse_mxid("@bob.smith", server).unwrap(),
+            "@bob.smith:example.org"
+        );
+    }
+
+    #[test]
+    fn reject_invalid_user_ids() {
+        let server = server_name!("example.org");
+        assert!(parse_mxid("", server).is_err());
+        assert!(parse_mxid("@", server).is_err());
+        assert!(parse_mxid("Alice", server).is_err());
+        assert!(parse_mxid("al ice", server).is_err());
+        assert!(parse_mxid("@alice:", server).is_err());
+        assert!(parse_mxid(":example.org", server).is_err());
+    }
+}